@@ -0,0 +1,226 @@
+use crate::args::{split_selector, Colons};
+use crate::stream::Stream;
+use globset::{Glob, GlobMatcher};
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What to do when a file changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Run the commands, as for any other change.
+    Run,
+    /// Update a file's modification time, creating it if needed.
+    Touch(PathBuf),
+    /// Send a signal, like `HUP`, to a process.
+    Signal { signal: String, target: Process },
+    /// Print a line, with `{}` replaced by the changed path.
+    Print(String),
+}
+
+/// A process by its id, or by a file containing its id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Process {
+    Pid(u32),
+    PidFile(PathBuf),
+}
+
+impl Action {
+    fn perform(&self, changed: &Path, stream: Stream) -> Result<(), Box<dyn Error>> {
+        match self {
+            Action::Run => {}
+            Action::Touch(path) => {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?
+                    .set_modified(SystemTime::now())?;
+            }
+            Action::Signal { signal, target } => {
+                let pid = match target {
+                    Process::Pid(pid) => *pid,
+                    Process::PidFile(path) => std::fs::read_to_string(path)?.trim().parse()?,
+                };
+                let status = Command::new("kill")
+                    .arg(format!("-{}", signal))
+                    .arg(pid.to_string())
+                    .status()?;
+                if !status.success() {
+                    return Err(format!("can't send {} to process {}", signal, pid).into());
+                }
+            }
+            Action::Print(text) => {
+                stream.println(text.replace("{}", &changed.display().to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = s.split_once(' ').unwrap_or((s, ""));
+        let arg = arg.trim();
+        match (name, arg) {
+            ("run", "") => Ok(Action::Run),
+            ("touch", path) if !path.is_empty() => Ok(Action::Touch(PathBuf::from(path))),
+            ("signal", arg) => {
+                let (signal, target) = arg
+                    .split_once(' ')
+                    .ok_or_else(|| format!("expected signal <signal> <pid>, got {:?}", s))?;
+                let target = target.trim();
+                Ok(Action::Signal {
+                    signal: signal.trim_start_matches("SIG").to_string(),
+                    target: match target.parse() {
+                        Ok(pid) => Process::Pid(pid),
+                        Err(_) => Process::PidFile(PathBuf::from(target)),
+                    },
+                })
+            }
+            ("print", text) => Ok(Action::Print(text.to_string())),
+            _ => Err(format!(
+                "expected run, touch <path>, signal <signal> <pid> or print <text>, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// An action for changes to files matching a glob, as `<glob>:<action>`.
+#[derive(Debug, Clone)]
+pub struct Reaction {
+    glob: GlobMatcher,
+    pub action: Action,
+}
+
+impl FromStr for Reaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (glob, action) = split_selector(s, "<glob>:<action>", Colons::Right)?;
+        Ok(Reaction {
+            glob: Glob::new(glob)
+                .map_err(|e| e.to_string())?
+                .compile_matcher(),
+            action: action.parse()?,
+        })
+    }
+}
+
+/// Performs the actions for each changed path, returning the changes that should still run the
+/// commands, or `None` if none should. Paths without a reaction run the commands, as do unknown
+/// changes.
+pub fn react(
+    reactions: &[Reaction],
+    changed: Vec<PathBuf>,
+    stream: Stream,
+) -> Option<Vec<PathBuf>> {
+    if reactions.is_empty() || changed.is_empty() {
+        return Some(changed);
+    }
+
+    let mut run = Vec::new();
+    for path in changed {
+        let actions = reactions
+            .iter()
+            .filter(|r| r.glob.is_match(&path))
+            .map(|r| &r.action)
+            .collect::<Vec<_>>();
+        for action in &actions {
+            if let Err(e) = action.perform(&path, stream) {
+                stream.println(format!(
+                    "watchdo: {:?} for {}: {}",
+                    action,
+                    path.display(),
+                    e
+                ));
+            }
+        }
+        if actions.is_empty() || actions.contains(&&Action::Run) {
+            run.push(path);
+        }
+    }
+    Some(run).filter(|r| !r.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!("run".parse(), Ok(Action::Run));
+        assert_eq!(
+            "touch reload.txt".parse(),
+            Ok(Action::Touch(PathBuf::from("reload.txt")))
+        );
+        assert_eq!(
+            "signal SIGHUP 42".parse(),
+            Ok(Action::Signal {
+                signal: "HUP".to_string(),
+                target: Process::Pid(42),
+            })
+        );
+        assert_eq!(
+            "signal USR1 server.pid".parse(),
+            Ok(Action::Signal {
+                signal: "USR1".to_string(),
+                target: Process::PidFile(PathBuf::from("server.pid")),
+            })
+        );
+        assert_eq!(
+            "print changed: {}".parse(),
+            Ok(Action::Print("changed: {}".to_string()))
+        );
+        assert!("touch".parse::<Action>().is_err());
+        assert!("explode".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn only_paths_without_other_actions_run_commands() {
+        let dir = TestDir::new();
+        let touched = dir.join("touched");
+        let reactions = [
+            format!("assets/**:touch {}", touched.display())
+                .parse::<Reaction>()
+                .unwrap(),
+            "assets/*.css:run".parse().unwrap(),
+        ];
+
+        let run = react(
+            &reactions,
+            vec![
+                PathBuf::from("assets/logo.png"),
+                PathBuf::from("assets/main.css"),
+                PathBuf::from("src/main.rs"),
+            ],
+            Stream::Stderr,
+        );
+
+        assert_eq!(
+            run,
+            Some(vec![
+                PathBuf::from("assets/main.css"),
+                PathBuf::from("src/main.rs"),
+            ])
+        );
+        assert!(touched.exists());
+    }
+
+    #[test]
+    fn nothing_runs_when_every_path_has_another_action() {
+        let reactions = ["*.md:print {} changed".parse::<Reaction>().unwrap()];
+
+        assert_eq!(
+            react(&reactions, vec![PathBuf::from("README.md")], Stream::Stderr),
+            None
+        );
+        assert_eq!(react(&reactions, vec![], Stream::Stderr), Some(vec![]));
+    }
+}
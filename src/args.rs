@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::time::Duration;
+
+/// Which part of a `<left>:<right>` option value can contain ':' itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colons {
+    /// The left, like a command given by its text (`npm run db:migrate`), so the value is split
+    /// at the last ':'.
+    Left,
+    /// The right, when it's free-form like a regex, script or command, so the value is split at
+    /// the first ':'. The left then can't contain one.
+    Right,
+}
+
+/// Splits a `<left>:<right>` option value, `format` naming the parts for the error. Only one part
+/// can contain ':', which is the right when it's free-form text and otherwise the left, since
+/// commands given by their text often do.
+pub fn split_selector<'a>(
+    s: &'a str,
+    format: &str,
+    colons: Colons,
+) -> Result<(&'a str, &'a str), String> {
+    let split = match colons {
+        Colons::Left => s.rsplit_once(':'),
+        Colons::Right => s.split_once(':'),
+    };
+    split.ok_or_else(|| format!("expected {}, got {:?}", format, s))
+}
+
+/// Splits a `<name>:<command>` option value, where the name is a single word, `kind` saying what
+/// is named for the error. Both parts are trimmed.
+pub fn split_named<'a>(s: &'a str, kind: &str) -> Result<(&'a str, &'a str), String> {
+    let (name, command) = split_selector(s, "<name>:<command>", Colons::Right)?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("{} names are a single word, got {:?}", kind, name));
+    }
+    Ok((name, command.trim()))
+}
+
+pub fn parse_size(s: &str) -> Result<u64, Box<dyn Error>> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse()?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        other => return Err(format!("unknown size unit {:?}", other).into()),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{} is too large", s).into())
+}
+
+/// Parses durations like `500ms`, `10s`, `5m` or `1h`. Plain numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, Box<dyn Error>> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse()?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.,
+        "" | "s" => number,
+        "m" => number * 60.,
+        "h" => number * 60. * 60.,
+        other => return Err(format!("unknown duration unit {:?}", other).into()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{}: {}", s, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_the_colon_the_other_part_cannot_contain() {
+        assert_eq!(
+            split_selector("npm run db:migrate:1", "<command>:<phase>", Colons::Left),
+            Ok(("npm run db:migrate", "1"))
+        );
+        assert_eq!(
+            split_selector("1:^test .*: FAILED$", "<command>:<regex>", Colons::Right),
+            Ok(("1", "^test .*: FAILED$"))
+        );
+    }
+
+    #[test]
+    fn split_without_colon_is_error() {
+        assert_eq!(
+            split_selector("cargo test", "<command>:<phase>", Colons::Left),
+            Err("expected <command>:<phase>, got \"cargo test\"".to_string())
+        );
+    }
+
+    #[test]
+    fn name_is_a_single_word() {
+        assert_eq!(
+            split_named(" lint : cargo clippy", "task"),
+            Ok(("lint", "cargo clippy"))
+        );
+        assert_eq!(
+            split_named("seed db:./seed", "task"),
+            Err("task names are a single word, got \"seed db\"".to_string())
+        );
+        assert!(split_named(":./seed", "task").is_err());
+    }
+
+    #[test]
+    fn size_without_unit_is_bytes() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+    }
+
+    #[test]
+    fn size_with_units() {
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1gb").unwrap(), 1 << 30);
+    }
+
+    #[test]
+    fn size_with_unknown_unit_is_error() {
+        assert!(parse_size("12Q").is_err());
+    }
+
+    #[test]
+    fn size_too_large_is_error() {
+        assert!(parse_size("99999999999G").is_err());
+    }
+
+    #[test]
+    fn duration_without_unit_is_seconds() {
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn duration_with_units() {
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn duration_with_unknown_unit_is_error() {
+        assert!(parse_duration("3d").is_err());
+    }
+
+    #[test]
+    fn duration_too_long_is_error() {
+        assert!(parse_duration(&format!("{}h", "9".repeat(40))).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Overall state of the commands, as shown on the badge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Passing,
+    Failing,
+    Running,
+}
+
+impl Aggregate {
+    pub fn message(self) -> &'static str {
+        match self {
+            Aggregate::Passing => "passing",
+            Aggregate::Failing => "failing",
+            Aggregate::Running => "running",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Aggregate::Passing => "#4c1",
+            Aggregate::Failing => "#e05d44",
+            Aggregate::Running => "#dfb317",
+        }
+    }
+}
+
+/// An SVG status badge written to a file whenever the status changes.
+pub struct Badge {
+    path: PathBuf,
+    last: Option<Aggregate>,
+}
+
+impl Badge {
+    pub fn new(path: PathBuf) -> Self {
+        Badge { path, last: None }
+    }
+
+    pub fn update(&mut self, status: Aggregate) -> Result<(), Box<dyn Error>> {
+        if self.last == Some(status) {
+            return Ok(());
+        }
+        std::fs::write(&self.path, render("build", status))?;
+        self.last = Some(status);
+        Ok(())
+    }
+}
+
+fn render(label: &str, status: Aggregate) -> String {
+    // Roughly the width of the text in the badge's 11px font.
+    let width = |s: &str| s.len() * 7 + 10;
+    let (label_width, message_width) = (width(label), width(status.message()));
+    let total = label_width + message_width;
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{total}" height="20" role="img" aria-label="{label}: {message}">
+  <rect width="{label_width}" height="20" fill="#555"/>
+  <rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,sans-serif" font-size="11">
+    <text x="{label_x}" y="14">{label}</text>
+    <text x="{message_x}" y="14">{message}</text>
+  </g>
+</svg>
+"##,
+        total = total,
+        label = label,
+        message = status.message(),
+        label_width = label_width,
+        message_width = message_width,
+        color = status.color(),
+        label_x = label_width / 2,
+        message_x = label_width + message_width / 2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn renders_status() {
+        let svg = render("build", Aggregate::Failing);
+
+        assert!(svg.contains(">failing</text>"));
+        assert!(svg.contains("#e05d44"));
+    }
+
+    #[test]
+    fn writes_only_on_change() {
+        let dir = TestDir::new();
+        let path = dir.join("badge.svg");
+        let mut badge = Badge::new(path.clone());
+
+        badge.update(Aggregate::Passing).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        badge.update(Aggregate::Passing).unwrap();
+        assert!(!path.exists());
+
+        badge.update(Aggregate::Running).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().contains("running"));
+    }
+}
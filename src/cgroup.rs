@@ -0,0 +1,117 @@
+use std::error::Error;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Limits on the memory and CPU of everything watchdo runs, taken together.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CgroupLimits {
+    pub memory: Option<u64>,
+    /// How many CPUs' worth of time, like 1.5.
+    pub cpus: Option<f64>,
+}
+
+/// A cgroup the commands run in, removed when dropped.
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+const CPU_PERIOD: u64 = 100_000;
+
+impl Cgroup {
+    /// Creates a cgroup with the limits next to watchdo's own, since cgroups with processes in
+    /// them can't hand controllers down. That needs cgroup v2 and permission to create one
+    /// there, like in a systemd user session. `None` without limits, and on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn create(limits: CgroupLimits) -> Result<Option<Self>, Box<dyn Error>> {
+        if limits == CgroupLimits::default() {
+            return Ok(None);
+        }
+
+        let root = Path::new("/sys/fs/cgroup");
+        if !root.join("cgroup.controllers").exists() {
+            return Err("cgroup limits need cgroup v2 mounted at /sys/fs/cgroup".into());
+        }
+        let own = std::fs::read_to_string("/proc/self/cgroup")?;
+        let own = own
+            .lines()
+            .find_map(|l| l.strip_prefix("0::"))
+            .ok_or("watchdo isn't in a cgroup v2 cgroup")?;
+        let own = root.join(own.trim_start_matches('/'));
+        let parent = if own == root {
+            root
+        } else {
+            own.parent().unwrap()
+        };
+
+        let controllers = [
+            ("+memory", limits.memory.is_some()),
+            ("+cpu", limits.cpus.is_some()),
+        ]
+        .iter()
+        .filter(|(_, used)| *used)
+        .map(|(controller, _)| *controller)
+        .collect::<Vec<_>>()
+        .join(" ");
+        write(&parent.join("cgroup.subtree_control"), &controllers)?;
+
+        let path = parent.join(format!("watchdo-{}", std::process::id()));
+        std::fs::create_dir(&path)
+            .map_err(|e| format!("can't create cgroup {}: {}", path.display(), e))?;
+        let cgroup = Cgroup { path };
+        if let Some(memory) = limits.memory {
+            write(&cgroup.path.join("memory.max"), &memory.to_string())?;
+        }
+        if let Some(cpus) = limits.cpus {
+            write(&cgroup.path.join("cpu.max"), &cpu_max(cpus))?;
+        }
+        Ok(Some(cgroup))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn create(_limits: CgroupLimits) -> Result<Option<Self>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// The file a process joins the cgroup by writing its pid to.
+    pub fn procs(&self) -> PathBuf {
+        self.path.join("cgroup.procs")
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Fails if something is still running in it, which then keeps it around.
+        let _ = std::fs::remove_dir(&self.path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn write(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, contents)
+        .map_err(|e| format!("can't write {:?} to {}: {}", contents, path.display(), e).into())
+}
+
+/// The quota and period for `cpus` CPUs' worth of time.
+#[cfg(target_os = "linux")]
+fn cpu_max(cpus: f64) -> String {
+    format!("{} {}", (cpus * CPU_PERIOD as f64) as u64, CPU_PERIOD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn cpu_quota_per_period() {
+        assert_eq!(cpu_max(1.5), "150000 100000");
+        assert_eq!(cpu_max(0.25), "25000 100000");
+    }
+
+    #[test]
+    fn no_limits_no_cgroup() {
+        assert!(Cgroup::create(CgroupLimits::default()).unwrap().is_none());
+    }
+}
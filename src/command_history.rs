@@ -1,43 +1,253 @@
 use crate::command_runner::*;
 use crate::executor::*;
+use regex::Regex;
+use std::time::{Duration, Instant};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+type OutputCheck = Box<dyn Fn(&mut CommandOutput)>;
+
+type TransitionHook = Box<dyn Fn(&str, &CommandState, bool)>;
+
 pub struct CommandHistory<E: Executor> {
+    name: String,
     runner: CommandRunner<E>,
     history: Vec<CommandState>,
-    // True if the last run was explicitly terminated.
-    terminated: bool,
+    // Set if the last run was explicitly terminated, with why.
+    terminated: Option<Termination>,
+    // Why the last run was killed, appended to its output once it finishes.
+    kill_reason: Option<String>,
+    min_interval: Option<Duration>,
+    // Count intentional terminations, like restarts, as failures.
+    restarts_fail: bool,
+    last_started: Option<Instant>,
+    last_finished: Option<Instant>,
+    expect_failure: bool,
+    warmup: Warmup,
+    output_checks: Vec<OutputCheck>,
+    retry: Option<Retry>,
+    // Whether a run was retried since take_retried was last called.
+    retried: bool,
+    transition_hooks: Vec<TransitionHook>,
+    // Restarts start the new run alongside the old one, which is only terminated once the new
+    // one's output matches this.
+    ready: Option<Regex>,
+    // Once started, only shutting down stops it.
+    persistent: bool,
+}
+
+/// Failing runs to try again, up to `budget` times in a row.
+struct Retry {
+    budget: usize,
+    left: usize,
+    when: Box<dyn Fn(&CommandOutput) -> bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Warmup {
+    None,
+    Pending,
+    Running,
+    Done,
 }
 
 impl<E: Executor> CommandHistory<E> {
-    pub fn new(runner: CommandRunner<E>) -> Self {
+    pub fn new(name: impl Into<String>, runner: CommandRunner<E>) -> Self {
         Self {
+            name: name.into(),
             runner,
             history: Vec::new(),
-            terminated: false,
+            terminated: None,
+            kill_reason: None,
+            min_interval: None,
+            restarts_fail: false,
+            last_started: None,
+            last_finished: None,
+            expect_failure: false,
+            warmup: Warmup::None,
+            output_checks: Vec::new(),
+            retry: None,
+            retried: false,
+            transition_hooks: Vec::new(),
+            ready: None,
+            persistent: false,
+        }
+    }
+
+    /// Adds a check run on each run's output when it finishes, which may change its outcome.
+    pub fn add_output_check(&mut self, check: impl Fn(&mut CommandOutput) + 'static) {
+        self.output_checks.push(Box::new(check));
+    }
+
+    /// Adds a hook called with the command's name whenever a run starts or finishes, with the
+    /// state it entered and whether that is passing.
+    pub fn add_transition_hook(&mut self, hook: impl Fn(&str, &CommandState, bool) + 'static) {
+        self.transition_hooks.push(Box::new(hook));
+    }
+
+    fn transitioned(&self, index: usize) {
+        let state = &self.history[index];
+        let passed = match state {
+            CommandState::Completed(output) => output.success != self.expect_failure,
+            _ => false,
+        };
+        for hook in &self.transition_hooks {
+            hook(&self.name, state, passed);
         }
     }
 
+    /// Runs the command again when it fails with output matching `when`, up to `budget` times in
+    /// a row. Retried runs are recorded as such rather than as failures.
+    pub fn set_retry(&mut self, budget: usize, when: impl Fn(&CommandOutput) -> bool + 'static) {
+        self.retry = Some(Retry {
+            budget,
+            left: budget,
+            when: Box::new(when),
+        });
+    }
+
+    /// Whether a run was retried since this was last called.
+    pub fn take_retried(&mut self) -> bool {
+        std::mem::take(&mut self.retried)
+    }
+
+    /// Makes the first run a warm-up that isn't recorded, for priming caches. It replaces the
+    /// first requested run.
+    pub fn set_warmup(&mut self) {
+        self.warmup = Warmup::Pending;
+    }
+
+    /// Runs won't start sooner than `interval` after the previous one started. Requests made in
+    /// the meantime wait until the interval has passed.
+    pub fn set_min_interval(&mut self, interval: Duration) {
+        self.min_interval = Some(interval);
+    }
+
+    /// Marks the command as expected to fail, so failing counts as passing and vice versa.
+    pub fn set_expect_failure(&mut self, expect_failure: bool) {
+        self.expect_failure = expect_failure;
+    }
+
+    pub fn expects_failure(&self) -> bool {
+        self.expect_failure
+    }
+
+    /// Restarts keep the old run going until the new one writes output matching `ready`. If the
+    /// new one exits first, the old one keeps going and the new one's run is recorded.
+    pub fn set_graceful_restart(&mut self, ready: Regex) {
+        self.ready = Some(ready);
+    }
+
+    /// Keeps the command going once it's started, rather than restarting it.
+    pub fn set_persistent(&mut self) {
+        self.persistent = true;
+    }
+
+    /// Whether the command is persistent and has been started, so it's left alone by restarts.
+    pub fn survives_restart(&self) -> bool {
+        self.persistent && self.last_started.is_some()
+    }
+
+    /// Counts intentional terminations, like restarts, as failing runs.
+    pub fn set_restarts_count_as_failures(&mut self, restarts_fail: bool) {
+        self.restarts_fail = restarts_fail;
+    }
+
+    /// Whether each finished run passed, latest first, leaving out the runs that don't count
+    /// either way.
+    fn outcomes(&self) -> impl Iterator<Item = bool> + '_ {
+        self.history
+            .iter()
+            .rev()
+            .filter_map(move |state| match state {
+                CommandState::Completed(output) => Some(output.success != self.expect_failure),
+                CommandState::Terminated(_, Termination::Limit) => Some(false),
+                CommandState::Terminated(_, Termination::Intentional) if self.restarts_fail => {
+                    Some(false)
+                }
+                _ => None,
+            })
+    }
+
+    /// Whether the latest finished run counts as passing or failing.
+    pub fn last_run_counts(&self) -> bool {
+        match self.last() {
+            Some(CommandState::Terminated(_, Termination::Intentional)) => self.restarts_fail,
+            _ => true,
+        }
+    }
+
+    /// Whether the last run completed with the expected outcome. An intentionally terminated
+    /// run goes by the one before it.
+    pub fn is_passing(&self) -> bool {
+        match self.last() {
+            Some(CommandState::Completed(output)) => output.success != self.expect_failure,
+            Some(CommandState::Terminated(_, Termination::Intentional)) if !self.restarts_fail => {
+                self.outcomes().next() == Some(true)
+            }
+            // A warm-up doesn't say anything about the command, so it doesn't hold others back.
+            None => self.warmup == Warmup::Done,
+            _ => false,
+        }
+    }
+
+    /// Whether nothing is left to run, whatever the outcome.
+    pub fn is_finished(&self) -> bool {
+        match self.last() {
+            Some(CommandState::Completed(_) | CommandState::Terminated(..)) => true,
+            None => !matches!(self.warmup, Warmup::Pending | Warmup::Running),
+            _ => false,
+        }
+    }
+
+    /// How many of the latest finished runs in a row didn't pass.
+    pub fn consecutive_failures(&self) -> usize {
+        self.outcomes().take_while(|passed| !passed).count()
+    }
+
+    fn too_soon(&self) -> bool {
+        match (self.min_interval, self.last_started) {
+            (Some(interval), Some(started)) => started.elapsed() < interval,
+            _ => false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn request_run(&mut self) {
+        if self.warmup == Warmup::Pending {
+            return;
+        }
         self.history.push(CommandState::Requested);
     }
 
+    /// Drops a requested run that hasn't started yet.
+    pub fn cancel_request(&mut self) {
+        if self.has_outstanding_request() {
+            self.history.pop();
+        }
+    }
+
     pub fn run_if_needed(&mut self) -> Result<()> {
-        if self.is_running()? {
+        if self.is_running()? || self.too_soon() {
             return Ok(());
         }
 
-        match self.history.last_mut() {
-            Some(CommandState::Requested) => {
-                self.run()?;
-            }
-            _ => {}
+        if self.warmup == Warmup::Pending {
+            self.warmup = Warmup::Running;
+            self.last_started = Some(Instant::now());
+            return self.runner.run(&self.env());
+        }
+
+        if let Some(CommandState::Requested) = self.history.last_mut() {
+            self.run()?;
         }
         Ok(())
     }
 
-    fn is_running(&mut self) -> Result<bool> {
+    pub fn is_running(&mut self) -> Result<bool> {
         if self.runner.is_running()? {
             return Ok(true);
         }
@@ -48,61 +258,276 @@ impl<E: Executor> CommandHistory<E> {
 
     fn run(&mut self) -> Result<()> {
         *self.history.last_mut().unwrap() = CommandState::Running;
-        self.terminated = false;
-        self.runner.run()
+        self.terminated = None;
+        self.last_started = Some(Instant::now());
+        self.runner.run(&self.env())?;
+        self.transitioned(self.history.len() - 1);
+        Ok(())
+    }
+
+    /// Environment telling the command about its previous run.
+    fn env(&self) -> Vec<(String, String)> {
+        let previous = self.history.iter().rev().find_map(|state| match state {
+            CommandState::Completed(output) | CommandState::Retried(output) => Some(output.success),
+            _ => None,
+        });
+        previous
+            .map(|success| {
+                vec![(
+                    "WATCHDO_PREVIOUS_SUCCESS".to_string(),
+                    (success as u8).to_string(),
+                )]
+            })
+            .unwrap_or_default()
     }
 
     pub fn try_finish(&mut self) -> Result<Option<&CommandOutput>> {
-        let output = match self.runner.try_finish()? {
+        let mut output = match self.runner.try_finish()? {
             Some(output) => output,
             None => return Ok(None),
         };
 
+        if self.warmup == Warmup::Running {
+            self.warmup = Warmup::Done;
+            // A killed warm-up isn't recorded, so why it was killed goes with it.
+            self.terminated = None;
+            self.kill_reason = None;
+            return Ok(None);
+        }
+
+        self.last_finished = Some(Instant::now());
+        output.duration = self.last_duration().unwrap_or_default();
         let running = self
             .history
-            .iter_mut()
-            .rfind(|h| *h == &CommandState::Running)
+            .iter()
+            .rposition(|h| *h == CommandState::Running)
             .unwrap();
 
-        if self.terminated {
-            *running = CommandState::Terminated(output);
-        } else {
-            *running = CommandState::Completed(output);
+        for check in &self.output_checks {
+            check(&mut output);
         }
 
-        match running {
-            CommandState::Completed(output) => Ok(Some(&*output)),
-            CommandState::Terminated(output) => Ok(Some(&*output)),
+        if let Some(reason) = self.kill_reason.take() {
+            if !output.err.is_empty() && !output.err.ends_with('\n') {
+                output.err.push('\n');
+            }
+            output.err.push_str(&reason);
+        }
+
+        if let Some(retry) = self.retry.as_mut() {
+            if self.terminated.is_none()
+                && !output.success
+                && retry.left > 0
+                && (retry.when)(&output)
+            {
+                retry.left -= 1;
+                self.retried = true;
+                self.history[running] = CommandState::Retried(output);
+                self.transitioned(running);
+                if !self.has_outstanding_request() {
+                    self.history.push(CommandState::Requested);
+                }
+                return Ok(None);
+            }
+            retry.left = retry.budget;
+        }
+
+        self.history[running] = match self.terminated {
+            Some(termination) => CommandState::Terminated(output, termination),
+            None => CommandState::Completed(output),
+        };
+        self.transitioned(running);
+
+        match &self.history[running] {
+            CommandState::Completed(output) => Ok(Some(output)),
+            CommandState::Terminated(output, _) => Ok(Some(output)),
             _ => unreachable!(),
         }
     }
 
-    pub fn has_outstanding_request(&self) -> bool {
-        match self.history.last() {
-            Some(CommandState::Requested) => true,
-            _ => false,
+    /// How long the current run has been going, if one is, including a warm-up.
+    pub fn running_for(&self) -> Option<Duration> {
+        let running = self.warmup == Warmup::Running
+            || self
+                .history
+                .iter()
+                .any(|s| matches!(s, CommandState::Running));
+        self.last_started.filter(|_| running).map(|s| s.elapsed())
+    }
+
+    /// How long the current run has gone without writing any output, if one is going.
+    pub fn quiet_for(&self) -> Option<Duration> {
+        self.running_for()?;
+        let since = match (self.runner.last_output(), self.last_started) {
+            (Some(output), Some(started)) => output.max(started),
+            (output, started) => output.or(started)?,
+        };
+        Some(since.elapsed())
+    }
+
+    /// How long the latest finished run took.
+    pub fn last_duration(&self) -> Option<Duration> {
+        match (self.last_started, self.last_finished) {
+            (Some(started), Some(finished)) if finished >= started => Some(finished - started),
+            _ => None,
         }
     }
 
+    /// Whether a run finished less than `window` ago.
+    pub fn finished_within(&self, window: Duration) -> bool {
+        self.last_finished
+            .map(|t| t.elapsed() < window)
+            .unwrap_or(false)
+    }
+
+    /// Whether a run is in progress or finished less than `window` ago.
+    pub fn ran_within(&self, window: Duration) -> bool {
+        self.history.contains(&CommandState::Running) || self.finished_within(window)
+    }
+
+    pub fn has_outstanding_request(&self) -> bool {
+        matches!(self.history.last(), Some(CommandState::Requested))
+    }
+
     pub fn last(&self) -> Option<&CommandState> {
         self.history.last()
     }
 
     pub fn restart(&mut self) -> Result<()> {
+        if let Some(ready) = self.ready.clone() {
+            return self.restart_gracefully(&ready);
+        }
+
         if self.is_running()? {
-            if self.terminated {
+            if self.terminated.is_some() {
                 // Wait for graceful shutdown.
                 return Ok(());
             }
 
-            self.terminated = true;
+            self.terminated = Some(Termination::Intentional);
             self.runner.terminate()?;
-        } else {
+        } else if !self.too_soon() {
             self.run()?;
         }
         Ok(())
     }
 
+    fn restart_gracefully(&mut self, ready: &Regex) -> Result<()> {
+        let running = self.is_running()?;
+        match self.runner.incoming(ready)? {
+            Incoming::None if running => {
+                if !self.too_soon() {
+                    self.runner.start_incoming(&self.env())?;
+                }
+            }
+            Incoming::None => {
+                if !self.too_soon() {
+                    self.run()?;
+                }
+            }
+            Incoming::Starting if running => {}
+            // Once the old run has exited there's no reason to wait any longer.
+            Incoming::Starting | Incoming::Ready => {
+                if let Some(out) = self.runner.promote()? {
+                    let replaced = self
+                        .history
+                        .iter()
+                        .rposition(|h| *h == CommandState::Running)
+                        .unwrap();
+                    let output = CommandOutput {
+                        out,
+                        ..CommandOutput::default()
+                    };
+                    self.history[replaced] =
+                        CommandState::Terminated(output, Termination::Intentional);
+                    self.transitioned(replaced);
+                }
+                *self.history.last_mut().unwrap() = CommandState::Running;
+                self.terminated = None;
+                self.last_started = Some(Instant::now());
+                self.transitioned(self.history.len() - 1);
+            }
+            Incoming::Exited(mut output) => {
+                for check in &self.output_checks {
+                    check(&mut output);
+                }
+                *self.history.last_mut().unwrap() = CommandState::Completed(output);
+                self.transitioned(self.history.len() - 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminates the current run, recording `reason` in its output.
+    pub fn kill(&mut self, reason: String, termination: Termination) -> Result<()> {
+        self.runner.abandon_incoming()?;
+        if self.terminated.is_some() || !self.is_running()? {
+            return Ok(());
+        }
+
+        self.terminated = Some(termination);
+        self.kill_reason = Some(reason);
+        self.runner.terminate()
+    }
+
+    /// Shows each run's output as it's written, through `take_live_output`.
+    pub fn set_live_output(&mut self) {
+        self.runner.set_live();
+    }
+
+    pub fn is_live_output(&self) -> bool {
+        self.runner.is_live()
+    }
+
+    /// The output the current or just finished run has written since this was last called.
+    pub fn take_live_output(&mut self) -> Option<String> {
+        self.runner.take_live_output()
+    }
+
+    pub fn children(&self) -> usize {
+        self.runner.children()
+    }
+
+    /// Reaps runs that were replaced before they finished, returning how many were.
+    pub fn reap_orphans(&mut self) -> Result<usize> {
+        self.runner.reap_orphans()
+    }
+
+    pub fn memory_usage(&self) -> Result<Option<u64>> {
+        self.runner.memory_usage()
+    }
+
+    /// Forgets past runs, keeping the latest finished one and anything after it, like a
+    /// current run or request.
+    pub fn clear_history(&mut self) {
+        let finished = self
+            .history
+            .iter()
+            .rposition(|h| matches!(h, CommandState::Completed(_) | CommandState::Terminated(..)));
+        let running = self
+            .history
+            .iter()
+            .rposition(|h| *h == CommandState::Running);
+        let keep_from = finished.into_iter().chain(running).min().unwrap_or(0);
+        self.history.drain(..keep_from);
+    }
+
+    /// How long each completed run in the history took, oldest first.
+    pub fn durations(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.history.iter().filter_map(|state| match state {
+            CommandState::Completed(output) => Some(output.duration),
+            _ => None,
+        })
+    }
+
+    /// Output of the latest finished run.
+    pub fn last_output(&self) -> Option<&CommandOutput> {
+        self.history.iter().rev().find_map(|state| match state {
+            CommandState::Completed(output) | CommandState::Terminated(output, _) => Some(output),
+            _ => None,
+        })
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &CommandState> {
         self.history.iter()
     }
@@ -113,5 +538,431 @@ pub enum CommandState {
     Requested,
     Running,
     Completed(CommandOutput),
-    Terminated(CommandOutput),
+    Terminated(CommandOutput, Termination),
+    /// Failed in a way that was retried.
+    Retried(CommandOutput),
+}
+
+/// Why watchdo terminated a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// Restarted, shut down or disabled, which doesn't say anything about the command.
+    Intentional,
+    /// Ran past a limit, like the timeout or the memory limit, which counts as failing.
+    Limit,
+}
+
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod tests {
+    use super::*;
+    use crate::commands::tests::terminable;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn finishing_executor(runs: usize) -> MockExecutor {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().times(runs).returning(|_| {
+            let mut child = MockChild::new();
+            child
+                .expect_poll()
+                .returning(|| Ok(Some(CommandOutput::default())));
+            Ok(child)
+        });
+        executor
+    }
+
+    #[test]
+    fn clear_history_keeps_last_state() {
+        let mut history = CommandHistory::new("test", CommandRunner::new(finishing_executor(2)));
+
+        history.request_run();
+        history.run_if_needed();
+        history.request_run();
+        history.run_if_needed();
+        history.try_finish();
+
+        history.clear_history();
+
+        assert_eq!(history.iter().count(), 1);
+        assert!(matches!(history.last(), Some(CommandState::Completed(_))));
+    }
+
+    #[test]
+    fn clear_history_keeps_last_result_before_a_request() {
+        let mut history = CommandHistory::new("test", CommandRunner::new(finishing_executor(2)));
+
+        for _ in 0..2 {
+            history.request_run();
+            history.run_if_needed();
+            history.try_finish();
+        }
+        history.request_run();
+
+        history.clear_history();
+
+        let states = history.iter().collect::<Vec<_>>();
+        assert!(matches!(
+            states.as_slice(),
+            [CommandState::Completed(_), CommandState::Requested]
+        ));
+        assert!(history.last_output().is_some());
+    }
+
+    #[test]
+    fn clear_history_keeps_running_and_later_requests() {
+        let mut child = MockChild::new();
+        child.expect_poll().returning(|| Ok(None));
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(move |_| Ok(child));
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+
+        history.request_run();
+        history.run_if_needed();
+        history.request_run();
+
+        history.clear_history();
+
+        let states = history.iter().collect::<Vec<_>>();
+        assert!(matches!(
+            states.as_slice(),
+            [CommandState::Running, CommandState::Requested]
+        ));
+    }
+
+    /// The first run keeps going. The second writes "Listening" once `ready` is set, or exits
+    /// if `exits` is.
+    fn graceful_executor(ready: Arc<AtomicBool>, exits: bool) -> MockExecutor {
+        let mut old = MockChild::new();
+        old.expect_poll().returning(|| Ok(None));
+        old.expect_partial_output().returning(|| "old".to_string());
+        old.expect_terminate()
+            .times(if exits { 0 } else { 1 })
+            .returning(|| Ok(()));
+        let mut new = MockChild::new();
+        new.expect_poll().returning(move || {
+            Ok(exits.then(|| CommandOutput {
+                err: "address in use".to_string(),
+                ..CommandOutput::default()
+            }))
+        });
+        new.expect_partial_output().returning(move || {
+            match ready.load(Ordering::SeqCst) {
+                true => "Listening",
+                false => "",
+            }
+            .to_string()
+        });
+        let mut executor = MockExecutor::new();
+        let mut children = vec![new, old];
+        executor
+            .expect_start()
+            .times(2)
+            .returning(move |_| Ok(children.pop().unwrap()));
+        executor
+    }
+
+    #[test]
+    fn graceful_restart_stops_old_run_once_new_one_is_ready() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let executor = graceful_executor(ready.clone(), false);
+        let mut history = CommandHistory::new("server", CommandRunner::new(executor));
+        history.set_graceful_restart(Regex::new("Listening").unwrap());
+
+        history.request_run();
+        history.restart();
+        history.request_run();
+        history.restart();
+        history.restart();
+        assert!(matches!(
+            history.iter().collect::<Vec<_>>().as_slice(),
+            [CommandState::Running, CommandState::Requested]
+        ));
+        assert_eq!(history.children(), 2);
+
+        ready.store(true, Ordering::SeqCst);
+        history.restart();
+        let states = history.iter().collect::<Vec<_>>();
+        assert!(matches!(
+            states.as_slice(),
+            [
+                CommandState::Terminated(output, Termination::Intentional),
+                CommandState::Running
+            ] if output.out == "old"
+        ));
+    }
+
+    #[test]
+    fn graceful_restart_keeps_old_run_when_new_one_exits() {
+        let executor = graceful_executor(Arc::new(AtomicBool::new(false)), true);
+        let mut history = CommandHistory::new("server", CommandRunner::new(executor));
+        history.set_graceful_restart(Regex::new("Listening").unwrap());
+
+        history.request_run();
+        history.restart();
+        history.request_run();
+        history.restart();
+        history.restart();
+
+        let states = history.iter().collect::<Vec<_>>();
+        assert!(matches!(
+            states.as_slice(),
+            [CommandState::Running, CommandState::Completed(output)]
+                if output.err == "address in use"
+        ));
+        assert!(history.running_for().is_some());
+    }
+
+    fn executor_with_results(results: Vec<bool>) -> MockExecutor {
+        let mut results = results.into_iter();
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            let success = results.next().unwrap();
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        executor
+    }
+
+    fn run_to_completion<E: Executor>(history: &mut CommandHistory<E>) {
+        history.request_run();
+        history.run_if_needed();
+        history.try_finish();
+    }
+
+    #[test]
+    fn consecutive_failures_since_last_pass() {
+        let executor = executor_with_results(vec![false, true, false, false]);
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+
+        for _ in 0..4 {
+            run_to_completion(&mut history);
+        }
+
+        assert_eq!(history.consecutive_failures(), 2);
+    }
+
+    #[test]
+    fn consecutive_failures_when_expecting_failure() {
+        let executor = executor_with_results(vec![false, true]);
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+        history.set_expect_failure(true);
+
+        run_to_completion(&mut history);
+        assert_eq!(history.consecutive_failures(), 0);
+
+        run_to_completion(&mut history);
+        assert_eq!(history.consecutive_failures(), 1);
+    }
+
+    // Passes once, then is terminated during its second run.
+    fn terminated_after_pass(termination: Termination) -> CommandHistory<MockExecutor> {
+        let mut runs = 0;
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            runs += 1;
+            let first = runs == 1;
+            Ok(terminable(
+                Arc::new(AtomicBool::new(false)),
+                move |terminated| {
+                    (first || terminated).then(|| CommandOutput {
+                        success: first,
+                        ..CommandOutput::default()
+                    })
+                },
+            ))
+        });
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+
+        run_to_completion(&mut history);
+        history.request_run();
+        history.run_if_needed();
+        history.kill("stopped".to_string(), termination);
+        history.try_finish();
+        history
+    }
+
+    #[test]
+    fn intentional_termination_is_not_a_failure() {
+        let mut history = terminated_after_pass(Termination::Intentional);
+
+        assert!(!history.last_run_counts());
+        assert!(history.is_passing());
+        assert_eq!(history.consecutive_failures(), 0);
+
+        history.set_restarts_count_as_failures(true);
+        assert!(history.last_run_counts());
+        assert!(!history.is_passing());
+        assert_eq!(history.consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn termination_for_limit_is_a_failure() {
+        let history = terminated_after_pass(Termination::Limit);
+
+        assert!(history.last_run_counts());
+        assert!(!history.is_passing());
+        assert_eq!(history.consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn warmup_is_not_recorded() {
+        let mut history = CommandHistory::new("test", CommandRunner::new(finishing_executor(2)));
+        history.set_warmup();
+
+        history.request_run();
+        history.run_if_needed();
+        assert_eq!(history.try_finish().unwrap(), None);
+        assert_eq!(history.iter().count(), 0);
+        assert!(history.is_passing());
+
+        run_to_completion(&mut history);
+        assert_eq!(history.iter().count(), 1);
+    }
+
+    #[test]
+    fn killed_warmup_leaves_the_next_run_alone() {
+        let mut runs = 0;
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            runs += 1;
+            let warmup = runs == 1;
+            Ok(terminable(
+                Arc::new(AtomicBool::new(false)),
+                move |terminated| (!warmup || terminated).then(CommandOutput::default),
+            ))
+        });
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+        history.set_warmup();
+
+        history.request_run();
+        history.run_if_needed();
+        assert!(history.running_for().is_some());
+        history.kill("timed out".to_string(), Termination::Limit);
+        history.try_finish();
+        run_to_completion(&mut history);
+
+        assert!(matches!(
+            history.last(),
+            Some(CommandState::Completed(output)) if output.err.is_empty()
+        ));
+    }
+
+    #[test]
+    fn retries_matching_failures() {
+        let executor = executor_with_results(vec![false, false, true]);
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+        history.set_retry(2, |_| true);
+
+        history.request_run();
+        let mut retried = 0;
+        for _ in 0..3 {
+            history.run_if_needed();
+            history.try_finish();
+            retried += history.take_retried() as usize;
+        }
+        assert_eq!(retried, 2);
+
+        let states = history.iter().collect::<Vec<_>>();
+        assert!(matches!(
+            states.as_slice(),
+            [
+                CommandState::Retried(_),
+                CommandState::Retried(_),
+                CommandState::Completed(_)
+            ]
+        ));
+        assert!(history.is_passing());
+    }
+
+    #[test]
+    fn retry_budget_runs_out() {
+        let executor = executor_with_results(vec![false, false]);
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+        history.set_retry(1, |_| true);
+
+        history.request_run();
+        for _ in 0..2 {
+            history.run_if_needed();
+            history.try_finish();
+        }
+
+        assert!(matches!(history.last(), Some(CommandState::Completed(_))));
+        assert_eq!(history.consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn previous_success_in_env() {
+        let mut executor = MockExecutor::new();
+        let mut expected = vec![vec![], vec!["0"], vec!["1"]].into_iter();
+        let mut results = vec![false, true, true].into_iter();
+        executor.expect_start().returning(move |env| {
+            let previous = env
+                .iter()
+                .filter(|(key, _)| key == "WATCHDO_PREVIOUS_SUCCESS")
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(previous, expected.next().unwrap());
+
+            let success = results.next().unwrap();
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+
+        for _ in 0..3 {
+            run_to_completion(&mut history);
+        }
+    }
+
+    #[test]
+    fn transition_hooks_see_each_state() {
+        let executor = executor_with_results(vec![false, true]);
+        let mut history = CommandHistory::new("test", CommandRunner::new(executor));
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let record = seen.clone();
+        history.add_transition_hook(move |name, state, passed| {
+            let state = match state {
+                CommandState::Running => "running",
+                CommandState::Completed(_) => "completed",
+                _ => "other",
+            };
+            record.borrow_mut().push((name.to_string(), state, passed));
+        });
+
+        run_to_completion(&mut history);
+        run_to_completion(&mut history);
+
+        let test = "test".to_string();
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (test.clone(), "running", false),
+                (test.clone(), "completed", false),
+                (test.clone(), "running", false),
+                (test, "completed", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn clear_empty_history() {
+        let mut history = CommandHistory::new("test", CommandRunner::new(MockExecutor::new()));
+
+        history.clear_history();
+
+        assert_eq!(history.iter().count(), 0);
+    }
 }
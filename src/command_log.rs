@@ -0,0 +1,113 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A file a command's output is appended to after each run, as `<command>:<file>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLog {
+    pub selector: String,
+    pub path: PathBuf,
+}
+
+impl CommandLog {
+    /// Empties the file, or just creates it if `append`.
+    pub fn start(&self, append: bool) -> std::io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.path)?;
+        Ok(())
+    }
+
+    /// Appends a finished run's output, after a line saying when it finished and how.
+    pub fn write(&self, name: &str, output: &CommandOutput) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        write!(file, "{}", entry(name, output))
+    }
+}
+
+fn entry(name: &str, output: &CommandOutput) -> String {
+    let result = match (output.success, output.warning) {
+        (true, false) => "passed",
+        (true, true) => "passed with warnings",
+        (false, _) => "failed",
+    };
+    let mut entry = format!(
+        "== {} {} {} in {:?} ==\n{}{}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        name,
+        result,
+        output.duration,
+        output.err,
+        output.out
+    );
+    if !entry.ends_with('\n') {
+        entry.push('\n');
+    }
+    entry
+}
+
+impl FromStr for CommandLog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, path) = split_selector(s, "<command>:<file>", Colons::Left)?;
+        Ok(CommandLog {
+            selector: selector.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn parses_selector_and_file() {
+        assert_eq!(
+            "cargo test:test.log".parse(),
+            Ok(CommandLog {
+                selector: "cargo test".to_string(),
+                path: PathBuf::from("test.log"),
+            })
+        );
+        assert!("test.log".parse::<CommandLog>().is_err());
+    }
+
+    #[test]
+    fn entries_accumulate_until_truncated() {
+        let dir = TestDir::new();
+        let log = CommandLog {
+            selector: "1".to_string(),
+            path: dir.join("commands.log"),
+        };
+        let output = CommandOutput {
+            success: false,
+            out: "out".to_string(),
+            err: "err\n".to_string(),
+            ..CommandOutput::default()
+        };
+
+        log.start(false).unwrap();
+        log.write("test", &output).unwrap();
+        log.write("test", &output).unwrap();
+        let contents = std::fs::read_to_string(&log.path).unwrap();
+        assert_eq!(contents.matches(" test failed in ").count(), 2);
+        assert_eq!(contents.matches("err\nout\n").count(), 2);
+
+        log.start(true).unwrap();
+        assert_eq!(std::fs::read_to_string(&log.path).unwrap(), contents);
+        log.start(false).unwrap();
+        assert_eq!(std::fs::read_to_string(&log.path).unwrap(), "");
+    }
+}
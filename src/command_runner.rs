@@ -1,10 +1,53 @@
 use crate::executor::*;
+use regex::Regex;
 use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// How long a terminated child gets to exit before it's seen as left over and killed.
+pub const KILL_AFTER: Duration = Duration::from_secs(5);
 
 pub struct CommandRunner<E: Executor> {
     executor: E,
     child: Option<E::Child>,
     child_output: Option<CommandOutput>,
+    // Children replaced by a new run before they finished. They're terminated and polled until
+    // they've been reaped, so they don't linger as zombies.
+    orphans: Vec<Orphan<E::Child>>,
+    // How long orphans get to exit, before they're killed and reported as left over.
+    kill_after: Duration,
+    // How much of the run's partial output has been taken, when it's shown live.
+    live_taken: Option<usize>,
+    // The finished run's partial output and how much was dropped from its start, until the rest
+    // of it has been taken.
+    finished_partial: Option<(String, usize)>,
+    // A new run started alongside the current one, to take over once it's ready.
+    incoming: Option<E::Child>,
+}
+
+// A terminated child that hasn't exited yet.
+struct Orphan<C> {
+    child: C,
+    terminated: Instant,
+    killed: bool,
+}
+
+impl<C> Orphan<C> {
+    fn new(child: C) -> Self {
+        Orphan {
+            child,
+            terminated: Instant::now(),
+            killed: false,
+        }
+    }
+}
+
+/// How a run started alongside the current one is doing.
+pub enum Incoming {
+    None,
+    Starting,
+    Ready,
+    /// Exited before it was ready.
+    Exited(CommandOutput),
 }
 
 impl<E: Executor> CommandRunner<E> {
@@ -13,14 +56,151 @@ impl<E: Executor> CommandRunner<E> {
             executor,
             child: None,
             child_output: None,
+            orphans: Vec::new(),
+            kill_after: KILL_AFTER,
+            live_taken: None,
+            finished_partial: None,
+            incoming: None,
+        }
+    }
+
+    /// Keeps each run's output as it's written, for `take_live_output`.
+    pub fn set_live(&mut self) {
+        self.live_taken = Some(0);
+    }
+
+    pub fn run(&mut self, env: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        if let Some(mut child) = self.child.take() {
+            if child.poll()?.is_none() {
+                child.terminate()?;
+                self.orphans.push(Orphan::new(child));
+            }
+        }
+        self.child = Some(self.executor.start(env)?);
+        if let Some(taken) = &mut self.live_taken {
+            *taken = 0;
+        }
+        self.finished_partial = None;
+        Ok(())
+    }
+
+    /// Starts a new run alongside the current one, which keeps going until the new one is
+    /// promoted.
+    pub fn start_incoming(&mut self, env: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        self.abandon_incoming()?;
+        self.incoming = Some(self.executor.start(env)?);
+        Ok(())
+    }
+
+    /// Whether the incoming run is ready, going by its output so far matching `ready`.
+    pub fn incoming(&mut self, ready: &Regex) -> Result<Incoming, Box<dyn Error>> {
+        let child = match &mut self.incoming {
+            Some(child) => child,
+            None => return Ok(Incoming::None),
+        };
+        if let Some(output) = child.poll()? {
+            self.incoming = None;
+            return Ok(Incoming::Exited(output));
+        }
+        Ok(match ready.is_match(&child.partial_output()) {
+            true => Incoming::Ready,
+            false => Incoming::Starting,
+        })
+    }
+
+    /// Makes the incoming run the current one, terminating the one it replaces. Returns the
+    /// replaced run's output, if there was one still going.
+    pub fn promote(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let replaced = match self.is_running()? {
+            true => {
+                let mut child = self.child.take().unwrap();
+                let output = child.partial_output();
+                child.terminate()?;
+                self.orphans.push(Orphan::new(child));
+                Some(output)
+            }
+            false => None,
+        };
+        self.child = self.incoming.take();
+        if let Some(taken) = &mut self.live_taken {
+            *taken = 0;
         }
+        self.finished_partial = None;
+        Ok(replaced)
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        self.child = Some(self.executor.start()?);
+    /// Terminates the incoming run, if there is one.
+    pub fn abandon_incoming(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut child) = self.incoming.take() {
+            child.terminate()?;
+            self.orphans.push(Orphan::new(child));
+        }
         Ok(())
     }
 
+    /// How many children may still be running, counting ones left behind by earlier runs and
+    /// one about to take over.
+    pub fn children(&self) -> usize {
+        self.child.iter().count() + self.orphans.len() + self.incoming.iter().count()
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live_taken.is_some()
+    }
+
+    /// The output written since this was last called, if it's kept live. While the run is
+    /// going only whole lines are taken, and the rest once it has finished.
+    pub fn take_live_output(&mut self) -> Option<String> {
+        let taken = self.live_taken.as_mut()?;
+        let ((partial, dropped), finished) = match (self.finished_partial.take(), &self.child) {
+            (Some(partial), _) => (partial, true),
+            (None, Some(child)) => (
+                (child.partial_output(), child.partial_output_dropped()),
+                false,
+            ),
+            (None, None) => return None,
+        };
+        // Whatever was dropped before it was taken is skipped.
+        let start = taken.saturating_sub(dropped);
+        let new = partial.get(start..)?;
+        let end = match new.rfind('\n') {
+            _ if finished => new.len(),
+            Some(newline) => newline + 1,
+            None => 0,
+        };
+        *taken = dropped + start + end;
+        let mut new = new[..end].to_string();
+        // Whatever's printed next starts on its own line.
+        if finished && !new.is_empty() && !new.ends_with('\n') {
+            new.push('\n');
+        }
+        Some(new).filter(|n| !n.is_empty())
+    }
+
+    /// Polls children left behind by earlier runs, returning how many have now been reaped
+    /// after taking longer than they should have to exit. The ones that exit promptly, like a
+    /// restarted server, are expected.
+    pub fn reap_orphans(&mut self) -> Result<usize, Box<dyn Error>> {
+        let mut left_over = 0;
+        let mut still_running = Vec::new();
+        for mut orphan in self.orphans.drain(..) {
+            match orphan.child.poll()? {
+                Some(_) if orphan.terminated.elapsed() >= self.kill_after => left_over += 1,
+                Some(_) => {}
+                None => {
+                    // It ignored being terminated.
+                    if !orphan.killed && orphan.terminated.elapsed() >= self.kill_after {
+                        orphan.child.kill()?;
+                        orphan.killed = true;
+                    }
+                    still_running.push(orphan);
+                }
+            }
+        }
+        self.orphans = still_running;
+        Ok(left_over)
+    }
+
     pub fn try_finish(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>> {
         if self.is_running()? {
             Ok(None)
@@ -32,6 +212,12 @@ impl<E: Executor> CommandRunner<E> {
     pub fn is_running(&mut self) -> Result<bool, Box<dyn Error>> {
         match self.child.as_mut().map(|c| c.poll()) {
             Some(Ok(Some(output))) => {
+                if self.live_taken.is_some() {
+                    self.finished_partial = self
+                        .child
+                        .as_ref()
+                        .map(|c| (c.partial_output(), c.partial_output_dropped()));
+                }
                 self.child = None;
                 self.child_output = Some(output);
                 Ok(false)
@@ -45,12 +231,26 @@ impl<E: Executor> CommandRunner<E> {
     pub fn terminate(&mut self) -> Result<(), Box<dyn Error>> {
         self.child.as_mut().map(|c| c.terminate()).unwrap_or(Ok(()))
     }
+
+    /// When the current run last wrote any output, if it has.
+    pub fn last_output(&self) -> Option<Instant> {
+        self.child.as_ref()?.last_output()
+    }
+
+    pub fn memory_usage(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        self.child
+            .as_ref()
+            .map(|c| c.memory_usage())
+            .unwrap_or(Ok(None))
+    }
 }
 
 #[cfg(test)]
 #[allow(unused_must_use)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn run_starts_executor() {
@@ -59,11 +259,11 @@ mod tests {
         executor
             .expect_start()
             .times(1)
-            .return_once(move || Ok(child));
+            .return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
     }
 
     #[test]
@@ -72,11 +272,11 @@ mod tests {
         child.expect_poll().return_once(|| Ok(None));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
         assert_eq!(runner.try_finish().unwrap(), None);
     }
 
@@ -88,11 +288,11 @@ mod tests {
             .return_once(|| Ok(Some(CommandOutput::default())));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
         assert_eq!(runner.try_finish().unwrap(), Some(CommandOutput::default()));
     }
 
@@ -113,11 +313,11 @@ mod tests {
             .return_once(|| Ok(Some(CommandOutput::default())));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
         runner.try_finish();
         assert_eq!(runner.try_finish().unwrap(), None);
     }
@@ -128,13 +328,13 @@ mod tests {
         child.expect_poll().return_once(|| Ok(None));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
 
-        assert_eq!(runner.is_running().unwrap(), true);
+        assert!(runner.is_running().unwrap());
     }
 
     #[test]
@@ -143,7 +343,7 @@ mod tests {
 
         let mut runner = CommandRunner::new(executor);
 
-        assert_eq!(runner.is_running().unwrap(), false);
+        assert!(!runner.is_running().unwrap());
     }
 
     #[test]
@@ -154,12 +354,12 @@ mod tests {
             .return_once(|| Ok(Some(CommandOutput::default())));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
-        assert_eq!(runner.is_running().unwrap(), false);
+        runner.run(&[]);
+        assert!(!runner.is_running().unwrap());
     }
 
     #[test]
@@ -170,11 +370,11 @@ mod tests {
             .return_once(|| Ok(Some(CommandOutput::default())));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
         runner.is_running();
 
         assert_eq!(runner.try_finish().unwrap(), Some(CommandOutput::default()));
@@ -186,11 +386,182 @@ mod tests {
         child.expect_terminate().times(1).return_once(|| Ok(()));
 
         let mut executor = MockExecutor::new();
-        executor.expect_start().return_once(move || Ok(child));
+        executor.expect_start().return_once(move |_| Ok(child));
 
         let mut runner = CommandRunner::new(executor);
 
-        runner.run();
+        runner.run(&[]);
         runner.terminate();
     }
+
+    #[test]
+    fn running_again_terminates_and_reaps_unfinished_child() {
+        let mut first = MockChild::new();
+        let mut polls = 0;
+        first.expect_poll().times(3).returning(move || {
+            polls += 1;
+            Ok((polls == 3).then(CommandOutput::default))
+        });
+        first.expect_terminate().times(1).returning(|| Ok(()));
+        let mut second = MockChild::new();
+        second.expect_poll().returning(|| Ok(None));
+
+        let mut executor = MockExecutor::new();
+        let mut children = vec![second, first];
+        executor
+            .expect_start()
+            .times(2)
+            .returning(move |_| Ok(children.pop().unwrap()));
+
+        let mut runner = CommandRunner::new(executor);
+
+        runner.run(&[]).unwrap();
+        runner.run(&[]).unwrap();
+        assert_eq!(runner.children(), 2);
+        runner.reap_orphans().unwrap();
+        runner.reap_orphans().unwrap();
+        assert_eq!(runner.children(), 1);
+        // It exited promptly, so it isn't reported.
+        assert_eq!(runner.reap_orphans().unwrap(), 0);
+        assert!(runner.is_running().unwrap());
+    }
+
+    #[test]
+    fn orphans_reaped_after_the_grace_period_are_reported() {
+        let mut first = MockChild::new();
+        let mut polls = 0;
+        first.expect_poll().returning(move || {
+            polls += 1;
+            Ok((polls > 1).then(CommandOutput::default))
+        });
+        first.expect_terminate().returning(|| Ok(()));
+        let mut second = MockChild::new();
+        second.expect_poll().returning(|| Ok(None));
+
+        let mut executor = MockExecutor::new();
+        let mut children = vec![second, first];
+        executor
+            .expect_start()
+            .returning(move |_| Ok(children.pop().unwrap()));
+
+        let mut runner = CommandRunner::new(executor);
+        runner.kill_after = Duration::ZERO;
+
+        runner.run(&[]).unwrap();
+        runner.run(&[]).unwrap();
+        assert_eq!(runner.reap_orphans().unwrap(), 1);
+    }
+
+    #[test]
+    fn orphans_that_ignore_termination_are_killed() {
+        let killed = Arc::new(AtomicBool::new(false));
+        let polled = killed.clone();
+        let on_kill = killed.clone();
+        let mut first = MockChild::new();
+        first
+            .expect_poll()
+            .returning(move || Ok(polled.load(Ordering::SeqCst).then(CommandOutput::default)));
+        first.expect_terminate().returning(|| Ok(()));
+        first.expect_kill().times(1).returning(move || {
+            on_kill.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+        let mut second = MockChild::new();
+        second.expect_poll().returning(|| Ok(None));
+
+        let mut executor = MockExecutor::new();
+        let mut children = vec![second, first];
+        executor
+            .expect_start()
+            .returning(move |_| Ok(children.pop().unwrap()));
+
+        let mut runner = CommandRunner::new(executor);
+        runner.kill_after = Duration::ZERO;
+
+        runner.run(&[]).unwrap();
+        runner.run(&[]).unwrap();
+        assert_eq!(runner.reap_orphans().unwrap(), 0);
+        assert!(killed.load(Ordering::SeqCst));
+        assert_eq!(runner.reap_orphans().unwrap(), 1);
+        assert_eq!(runner.children(), 1);
+    }
+
+    #[test]
+    fn live_output_takes_whole_lines_until_finished() {
+        let mut partials = vec!["a\nb\nc", "a\nb\nc", "a\nb", "a\nb"];
+        let mut polls = vec![Some(CommandOutput::default()), None];
+        let mut child = MockChild::new();
+        child
+            .expect_partial_output()
+            .returning(move || partials.pop().unwrap().to_string());
+        child.expect_partial_output_dropped().returning(|| 0);
+        child
+            .expect_poll()
+            .returning(move || Ok(polls.pop().unwrap()));
+
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(move |_| Ok(child));
+
+        let mut runner = CommandRunner::new(executor);
+        runner.set_live();
+        runner.run(&[]).unwrap();
+
+        assert_eq!(runner.take_live_output(), Some("a\n".to_string()));
+        assert_eq!(runner.take_live_output(), None);
+        assert!(runner.is_running().unwrap());
+        assert_eq!(runner.take_live_output(), Some("b\n".to_string()));
+        assert!(!runner.is_running().unwrap());
+        assert_eq!(runner.take_live_output(), Some("c\n".to_string()));
+        assert_eq!(runner.take_live_output(), None);
+    }
+
+    #[test]
+    fn live_output_skips_what_was_dropped() {
+        // Of "a\nb\nc\nd\ne\n", with its start dropped as it grows.
+        let mut partials = vec![("e\n", 8), ("b\nc\n", 2), ("a\nb", 0)];
+        let current = Arc::new(std::sync::Mutex::new(0));
+        let dropped = current.clone();
+        let mut child = MockChild::new();
+        child.expect_partial_output().returning(move || {
+            let (partial, dropped) = partials.pop().unwrap();
+            *current.lock().unwrap() = dropped;
+            partial.to_string()
+        });
+        child
+            .expect_partial_output_dropped()
+            .returning(move || *dropped.lock().unwrap());
+
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(move |_| Ok(child));
+
+        let mut runner = CommandRunner::new(executor);
+        runner.set_live();
+        runner.run(&[]).unwrap();
+
+        assert_eq!(runner.take_live_output(), Some("a\n".to_string()));
+        assert_eq!(runner.take_live_output(), Some("b\nc\n".to_string()));
+        assert_eq!(runner.take_live_output(), Some("e\n".to_string()));
+    }
+
+    #[test]
+    fn memory_usage_of_running_child() {
+        let mut child = MockChild::new();
+        child.expect_memory_usage().return_once(|| Ok(Some(42)));
+
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(move |_| Ok(child));
+
+        let mut runner = CommandRunner::new(executor);
+
+        runner.run(&[]);
+        assert_eq!(runner.memory_usage().unwrap(), Some(42));
+    }
+
+    #[test]
+    fn memory_usage_without_child_is_none() {
+        let executor = MockExecutor::new();
+        let runner = CommandRunner::new(executor);
+
+        assert_eq!(runner.memory_usage().unwrap(), None);
+    }
 }
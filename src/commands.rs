@@ -0,0 +1,3268 @@
+use crate::*;
+use colored::{ColoredString, Colorize};
+use globset::GlobMatcher;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Every command watchdo runs, with the state deciding when they run.
+pub struct Commands<E: Executor> {
+    last_request: Option<Instant>,
+    debounce: Duration,
+    tests: Vec<CommandHistory<E>>,
+    servers: Vec<CommandHistory<E>>,
+    max_memory: Option<u64>,
+    // When memory usage was last checked, as that reads every process in /proc.
+    memory_checked: Option<Instant>,
+    // Runs aren't started while this many children are running, as a safety net.
+    max_children: usize,
+    // Whether runs are being held back by max_children, to only warn once.
+    children_capped: bool,
+    timeout: Option<Duration>,
+    // Tests are stopped after going this long without writing any output.
+    inactivity_timeout: Option<Duration>,
+    schedule: Schedule,
+    input_idle: Option<InputIdle>,
+    // Changes seen while runs were held back, to run once they no longer are.
+    queued: Option<Vec<PathBuf>>,
+    // Changes that came in within the debounce of the previous one, to run once it's over.
+    debounced: Option<Vec<PathBuf>>,
+    printed: PrintedOutputs,
+    impact: Option<ImpactMap>,
+    server_enabled: bool,
+    command_outputs: Vec<CommandGlob>,
+    restart_filters: Vec<CommandGlob>,
+    first_failures: Vec<FirstFailure>,
+    diff_only: Vec<DiffOnly>,
+    // Changes only to these files reload the servers' configuration instead of restarting them.
+    server_config: Vec<GlobMatcher>,
+    config_reload: Option<CommandHistory<E>>,
+    loop_guard: Option<LoopGuard>,
+    loop_guard_window: Duration,
+    success_cooldowns: Vec<SuccessCooldown>,
+    // Commands changes came in for during their success cooldown, by index, to run once it ends.
+    stale: Vec<usize>,
+    // When each interval last requested a run.
+    intervals: Vec<(Interval, Instant)>,
+    notifier: Notifier,
+    goldens: Vec<Golden>,
+    command_logs: Vec<CommandLog>,
+    // The phase of each test. Each phase starts once the ones before it are done.
+    phases: Vec<usize>,
+    // Whether each test's phase was given with --phase, rather than going by its position.
+    pinned_phases: Vec<bool>,
+    shuffle: Option<Shuffle>,
+    // The tests in the order they're started this cycle.
+    order: Vec<usize>,
+    // The phase of each server, which restarts once the tests in earlier phases pass.
+    server_phases: Vec<usize>,
+    // For each test that only runs once another exited with a certain code, that test and the
+    // code. These run outside the phases.
+    gates: Vec<Option<(usize, i32)>>,
+    // Start the next phase when the previous one finished, even if it failed.
+    run_after_failure: bool,
+    // Run one test at a time, even within a phase.
+    sequential: bool,
+    metrics: Metrics,
+    // The changes behind the latest run request, and how many requests there have been.
+    last_trigger: Vec<PathBuf>,
+    triggers: usize,
+    show_elapsed: bool,
+    sparkline: bool,
+    // Pad the status bar in the color of the overall health.
+    health_tint: bool,
+    // Nothing runs until the first change, which the status bar shows until then.
+    idle_start: bool,
+    recent_events: Option<RecentEvents>,
+    theme: Theme,
+    // The command whose state alone is the overall status, instead of every test's.
+    primary: Option<String>,
+    // The command pinned by the user, by its index in the status bar, with its latest failing
+    // output.
+    pinned: Option<(usize, Option<String>)>,
+    // Print a summary line for each run's output, except for the commands expanded by the user,
+    // by their index in the status bar, and the expanded ones whose latest output to reprint.
+    collapse_output: bool,
+    expanded: HashSet<usize>,
+    to_expand: Vec<usize>,
+    // Commands that only run when asked for by name.
+    tasks: Vec<CommandHistory<E>>,
+    // Whether each command, by its index in the status bar, is in the current --preset.
+    // Inactive commands don't run, and don't count towards the overall status.
+    presets: Vec<Preset>,
+    active: Vec<bool>,
+    // The test that runs before the others, which are skipped when it fails.
+    canary: Option<usize>,
+    // Runs are held back until resumed.
+    paused: bool,
+    // Where the commands run, removed once they're shut down.
+    cgroup: Option<Cgroup>,
+    // Scripts to run each time every test starts passing, and whether they all were last tick.
+    on_green: Vec<String>,
+    green: bool,
+}
+
+impl Commands<FanOut<SubprocessExecutor>> {
+    /// Applies --override and --disable to the commands and servers, selecting them by their
+    /// position as given. Everything else sees the resulting commands, so positions in other
+    /// options skip disabled commands.
+    pub fn resolve(options: &mut Options) {
+        let resolve = |position: Option<usize>, command: &OsString| {
+            let name = command.to_string_lossy();
+            let selected = |selector: &str| Self::selects(selector, position, &name);
+            if options.disable.iter().any(|d| selected(d)) {
+                return None;
+            }
+            let command = options
+                .override_command
+                .iter()
+                .rev()
+                .find(|o| selected(&o.selector))
+                .map_or(command, |o| &o.command);
+            Some(command.clone())
+        };
+        let command = options
+            .command
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| resolve(Some(i + 1), c))
+            .collect();
+        let server = options
+            .server
+            .iter()
+            .filter_map(|s| resolve(None, s))
+            .collect();
+        options.command = command;
+        options.server = server;
+    }
+
+    pub fn new(options: &Options) -> Result<Self, Box<dyn Error>> {
+        let limits = OutputLimits {
+            out: options.max_stdout_bytes.map(|b| b as usize),
+            err: options.max_stderr_bytes.map(|b| b as usize),
+        };
+        let cgroup = Cgroup::create(CgroupLimits {
+            memory: options.cgroup_memory,
+            cpus: options.cgroup_cpus,
+        })?;
+        let procs = cgroup.as_ref().map(Cgroup::procs);
+        let login_shell = options
+            .login_shell
+            .then(|| std::env::var_os("SHELL").unwrap_or_else(|| "sh".into()));
+        let history =
+            |position: Option<usize>, c: &OsString| -> Result<CommandHistory<_>, Box<dyn Error>> {
+                let name = c.to_string_lossy().into_owned();
+                let selected = |selector: &str| Self::selects(selector, position, &name);
+
+                let variants = options
+                    .variants
+                    .iter()
+                    .filter(|v| selected(&v.selector))
+                    .flat_map(|v| v.values.iter())
+                    .collect::<Vec<_>>();
+                let mut executor = if variants.is_empty() {
+                    FanOut::single(SubprocessExecutor::new(c))
+                } else if !name.contains("{}") {
+                    return Err(format!("{:?} has variants but no {{}} to substitute", name).into());
+                } else {
+                    FanOut::new(
+                        variants
+                            .into_iter()
+                            .map(|v| (v.clone(), SubprocessExecutor::new(name.replace("{}", v))))
+                            .collect(),
+                    )
+                };
+                let stdin = match options
+                    .stdin_file
+                    .iter()
+                    .rev()
+                    .find(|f| selected(&f.selector))
+                {
+                    Some(file) => Stdin::File(file.path.clone()),
+                    None if options.inherit_stdin.iter().any(|s| selected(s)) => Stdin::Inherit,
+                    None => Stdin::Null,
+                };
+                let wrapper = options
+                    .wrapper
+                    .as_ref()
+                    .filter(|_| position.is_some() || options.wrap_server);
+                let live = options.live_output.iter().any(|l| selected(l));
+                let graceful = options
+                    .graceful_restart
+                    .iter()
+                    .rev()
+                    .find(|g| selected(&g.selector))
+                    .filter(|_| position.is_none());
+                for executor in executor.executors_mut() {
+                    executor.set_output_limits(limits);
+                    executor.set_stdin(stdin.clone());
+                    executor.set_env(options.env.clone());
+                    if let Some(procs) = &procs {
+                        executor.set_cgroup(procs.clone());
+                    }
+                    if let Some(wrapper) = wrapper {
+                        executor.set_wrapper(wrapper);
+                    }
+                    if let Some(shell) = &login_shell {
+                        executor.set_login_shell(shell);
+                    }
+                    // Shown as it's written, or watched for the server being ready.
+                    if live || graceful.is_some() {
+                        executor.keep_partial_output();
+                    }
+                }
+
+                let mut history = CommandHistory::new(name.clone(), CommandRunner::new(executor));
+                for code_status in options.code_status.clone() {
+                    history.add_output_check(move |output| code_status.apply(output));
+                }
+                history.set_expect_failure(options.expect_fail.iter().any(|e| selected(e)));
+                history.set_restarts_count_as_failures(options.restarts_count_as_failures);
+                if let Some(pattern) = options.flaky_pattern.clone() {
+                    history.set_retry(options.flaky_retries, move |output| {
+                        pattern.is_match(&output.out) || pattern.is_match(&output.err)
+                    });
+                }
+                for hook in options.on.clone() {
+                    let stream = options.status_stream;
+                    history.add_transition_hook(move |name, state, passed| {
+                        if hook.matches(state, passed) {
+                            hook.run(name, state, passed, stream);
+                        }
+                    });
+                }
+                for parser in options
+                    .format_parser
+                    .iter()
+                    .filter(|p| selected(&p.selector))
+                {
+                    let format = parser.format;
+                    history.add_output_check(move |output| output.counts = format.counts(output));
+                }
+                for golden in options.golden.iter().filter(|g| selected(&g.selector)) {
+                    let golden = golden.clone();
+                    history.add_output_check(move |output| golden.check(output));
+                }
+                for require in options
+                    .require_output
+                    .iter()
+                    .filter(|r| selected(&r.selector))
+                {
+                    let require = require.clone();
+                    history.add_output_check(move |output| require.check(output));
+                }
+                // The server isn't run through the warm-up path, so it can't be warmed up.
+                if position.is_some() && options.warmup.iter().any(|w| selected(w)) {
+                    history.set_warmup();
+                }
+                if let Some(graceful) = graceful {
+                    history.set_graceful_restart(graceful.ready.clone());
+                }
+                if position.is_none() && options.persistent.iter().any(|p| selected(p)) {
+                    history.set_persistent();
+                }
+                if live {
+                    history.set_live_output();
+                }
+                if let Some(interval) = options.min_interval {
+                    history.set_min_interval(interval);
+                }
+                Ok(history)
+            };
+        // The canary goes after the other tests, so it doesn't change their positions.
+        let tests = options
+            .command
+            .iter()
+            .chain(&options.canary)
+            .enumerate()
+            .map(|(i, c)| history(Some(i + 1), c))
+            .collect::<Result<_, _>>()?;
+        let servers = options
+            .server
+            .iter()
+            .map(|s| history(None, s))
+            .collect::<Result<_, _>>()?;
+        let given_phases = options
+            .command
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let name = c.to_string_lossy();
+                options
+                    .phase
+                    .iter()
+                    .rev()
+                    .find(|p| Self::selects(&p.selector, Some(i + 1), &name))
+                    .map(|p| p.phase)
+            })
+            .collect::<Vec<_>>();
+        // The canary runs before every phase.
+        let given_phases = given_phases
+            .into_iter()
+            .chain(options.canary.as_ref().map(|_| Some(0)))
+            .collect::<Vec<_>>();
+        // Commands without a phase run in order, as if each was in its own, ahead of any
+        // commands given that phase's number. Numbering each distinct (phase, turn) in order
+        // keeps the two apart.
+        let keys = given_phases
+            .iter()
+            .enumerate()
+            .map(|(i, p)| match p {
+                _ if i == options.command.len() => (0, 0),
+                Some(p) => (*p, usize::MAX),
+                None => (i + 1, i + 1),
+            })
+            .collect::<Vec<_>>();
+        let mut ordered = keys.clone();
+        ordered.sort_unstable();
+        ordered.dedup();
+        // Where a key is or would be in the order, starting at 1.
+        let number = |key| ordered.binary_search(&key).unwrap_or_else(|i| i) + 1;
+        let phases = keys.iter().map(|&key| number(key)).collect();
+        let position = |selector: &str| {
+            options
+                .command
+                .iter()
+                .enumerate()
+                .position(|(i, c)| Self::selects(selector, Some(i + 1), &c.to_string_lossy()))
+        };
+        let mut gates = vec![None; given_phases.len()];
+        for run_if in &options.run_if {
+            let prerequisite = position(&run_if.prerequisite)
+                .ok_or_else(|| format!("--run-if: no command {}", run_if.prerequisite))?;
+            let gated = position(&run_if.selector)
+                .filter(|g| *g != prerequisite)
+                .ok_or_else(|| format!("--run-if: no other command {}", run_if.selector))?;
+            gates[gated] = Some((prerequisite, run_if.code));
+        }
+        for preset in &options.preset {
+            let server = options
+                .server
+                .iter()
+                .any(|s| Self::selects(&preset.selector, None, &s.to_string_lossy()));
+            if position(&preset.selector).is_none() && !server {
+                return Err(
+                    format!("--preset {}: no command {}", preset.name, preset.selector).into(),
+                );
+            }
+        }
+        // Servers without a phase wait for every test.
+        let server_phases = options
+            .server
+            .iter()
+            .map(|s| {
+                let name = s.to_string_lossy();
+                options
+                    .phase
+                    .iter()
+                    .rev()
+                    .find(|p| Self::selects(&p.selector, None, &name))
+                    // Ahead of every test with that phase, so it only waits for earlier ones.
+                    .map_or(usize::MAX, |p| number((p.phase, 0)))
+            })
+            .collect();
+        let mut theme = Theme::load(&options.theme, options.theme_file.as_deref())?;
+        if let Some(ok_str) = &options.ok_str {
+            theme.set_ok_glyph(ok_str);
+        }
+        for log in &options.command_log {
+            log.start(options.command_log_append)?;
+        }
+        let tasks = options
+            .task
+            .iter()
+            .map(|task| {
+                let mut executor = SubprocessExecutor::new(&task.command);
+                executor.set_output_limits(limits);
+                executor.set_env(options.env.clone());
+                if let Some(procs) = &procs {
+                    executor.set_cgroup(procs.clone());
+                }
+                if let Some(shell) = &login_shell {
+                    executor.set_login_shell(shell);
+                }
+                CommandHistory::new(
+                    task.name.clone(),
+                    CommandRunner::new(FanOut::single(executor)),
+                )
+            })
+            .collect();
+        let config_reload = options.server_config_reload.as_ref().map(|command| {
+            let mut executor = SubprocessExecutor::new(command);
+            executor.set_output_limits(limits);
+            executor.set_env(options.env.clone());
+            if let Some(procs) = &procs {
+                executor.set_cgroup(procs.clone());
+            }
+            if let Some(shell) = &login_shell {
+                executor.set_login_shell(shell);
+            }
+            CommandHistory::new(
+                command.to_string_lossy().into_owned(),
+                CommandRunner::new(FanOut::single(executor)),
+            )
+        });
+        let impact = match &options.impact_map {
+            Some(path) => Some(ImpactMap::load(path, options.impact_fallback)?),
+            None => None,
+        };
+        Ok(Commands {
+            max_memory: options.max_memory,
+            max_children: options.max_children,
+            timeout: options.timeout,
+            inactivity_timeout: options.inactivity_timeout,
+            schedule: Schedule::new(options.active_hours, options.active_days.clone()),
+            #[cfg(feature = "input-idle")]
+            input_idle: options.defer_while_typing.map(InputIdle::new),
+            printed: PrintedOutputs::new(options.collapse_whitespace),
+            impact,
+            command_outputs: options.command_output.clone(),
+            restart_filters: options.restart_on.clone(),
+            first_failures: options.first_failure.clone(),
+            diff_only: options.diff_only.clone(),
+            server_config: options
+                .server_config
+                .iter()
+                .map(Glob::compile_matcher)
+                .collect(),
+            config_reload,
+            loop_guard: options.loop_guard,
+            loop_guard_window: options.loop_guard_window,
+            success_cooldowns: options.success_cooldown.clone(),
+            stale: Vec::new(),
+            intervals: options
+                .interval
+                .iter()
+                .map(|i| (i.clone(), Instant::now()))
+                .collect(),
+            notifier: Notifier::new(
+                options.bell,
+                options.desktop_notify,
+                options.notify_after,
+                options.notify_cooldown,
+                options.status_stream,
+            ),
+            goldens: options.golden.clone(),
+            command_logs: options.command_log.clone(),
+            phases,
+            pinned_phases: given_phases.iter().map(Option::is_some).collect(),
+            shuffle: match options.shuffle_seed {
+                _ if !options.shuffle => None,
+                Some(seed) => Some(Shuffle::new(seed)),
+                None => Some(Shuffle::from_time()),
+            },
+            server_phases,
+            gates,
+            run_after_failure: options.run_after_failure || options.sequential,
+            sequential: options.sequential,
+            show_elapsed: options.show_elapsed,
+            sparkline: options.sparkline,
+            health_tint: options.health_tint,
+            collapse_output: options.collapse_output,
+            idle_start: options.idle_start,
+            recent_events: options
+                .recent_events
+                .map(|shown| RecentEvents::new(shown, options.recent_events_for)),
+            theme,
+            primary: options.primary.clone(),
+            tasks,
+            presets: options.preset.clone(),
+            canary: options.canary.as_ref().map(|_| options.command.len()),
+            cgroup,
+            on_green: options.on_green.clone(),
+            ..Commands::with_commands(tests, servers)
+        })
+    }
+}
+
+impl<E: Executor> Commands<E> {
+    /// Commands with none of the options set.
+    pub fn with_commands(tests: Vec<CommandHistory<E>>, servers: Vec<CommandHistory<E>>) -> Self {
+        Commands {
+            phases: (1..=tests.len()).collect(),
+            pinned_phases: vec![false; tests.len()],
+            shuffle: None,
+            order: (0..tests.len()).collect(),
+            server_phases: vec![usize::MAX; servers.len()],
+            gates: vec![None; tests.len()],
+            active: vec![true; tests.len() + servers.len()],
+            last_request: None,
+            debounce: Duration::from_millis(100),
+            tests,
+            servers,
+            max_memory: None,
+            memory_checked: None,
+            max_children: usize::MAX,
+            children_capped: false,
+            timeout: None,
+            inactivity_timeout: None,
+            schedule: Schedule::default(),
+            input_idle: None,
+            queued: None,
+            debounced: None,
+            printed: PrintedOutputs::new(false),
+            impact: None,
+            server_enabled: true,
+            command_outputs: Vec::new(),
+            restart_filters: Vec::new(),
+            first_failures: Vec::new(),
+            diff_only: Vec::new(),
+            server_config: Vec::new(),
+            config_reload: None,
+            loop_guard: None,
+            loop_guard_window: Duration::from_secs(1),
+            success_cooldowns: Vec::new(),
+            stale: Vec::new(),
+            intervals: Vec::new(),
+            notifier: Notifier::new(false, false, 1, None, Stream::Stdout),
+            run_after_failure: false,
+            sequential: false,
+            metrics: Metrics::default(),
+            last_trigger: Vec::new(),
+            triggers: 0,
+            show_elapsed: false,
+            sparkline: false,
+            health_tint: false,
+            collapse_output: false,
+            expanded: HashSet::new(),
+            to_expand: Vec::new(),
+            idle_start: false,
+            recent_events: None,
+            theme: Theme::default(),
+            primary: None,
+            tasks: Vec::new(),
+            presets: Vec::new(),
+            canary: None,
+            paused: false,
+            cgroup: None,
+            on_green: Vec::new(),
+            green: false,
+            pinned: None,
+            goldens: Vec::new(),
+            command_logs: Vec::new(),
+        }
+    }
+
+    /// `changed` are the paths that changed, relative to the watched directory. An empty list
+    /// means the changes are unknown.
+    pub fn request_run(&mut self, changed: &[PathBuf]) {
+        if self.is_own_output(changed) {
+            return;
+        }
+
+        let window = self.loop_guard_window;
+        let just_finished = |c: &CommandHistory<E>| c.finished_within(window);
+        let others;
+        let changed = if self.loop_guard == Some(LoopGuard::Suppress) {
+            let outputs = self.outputs_of(just_finished);
+            others = changed
+                .iter()
+                .filter(|p| !outputs.iter().any(|o| o.is_match(p)))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !changed.is_empty() && others.is_empty() {
+                return;
+            }
+            &others[..]
+        } else {
+            changed
+        };
+
+        if !self.can_run_now() {
+            hold(&mut self.queued, changed);
+            return;
+        }
+
+        let debouncing = self
+            .last_request
+            .is_some_and(|t| t.elapsed() <= self.debounce);
+        self.last_request = Some(Instant::now());
+        if debouncing {
+            hold(&mut self.debounced, changed);
+            return;
+        }
+
+        let impact = match &self.impact {
+            Some(map) => map.impacted(changed),
+            None => Impact::All,
+        };
+        let selected = match impact {
+            Impact::All => None,
+            Impact::Selected(selectors) => Some(
+                selectors
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>(),
+            ),
+        };
+
+        self.last_trigger = changed.to_vec();
+        self.triggers += 1;
+        self.reshuffle();
+
+        let reload_config = self.is_config_only(changed);
+        if reload_config && self.servers.iter().any(|s| s.running_for().is_some()) {
+            let reload = self.config_reload.as_mut().unwrap();
+            if !reload.has_outstanding_request() {
+                reload.request_run();
+            }
+        }
+        let unaffected_servers = self
+            .servers
+            .iter()
+            .filter(|s| {
+                reload_config || s.survives_restart() || !self.needs_restart(s.name(), changed)
+            })
+            .map(|s| s.name().to_string())
+            .collect::<Vec<_>>();
+        let cooling_down = self
+            .positioned()
+            .map(|(position, command)| self.cooling_down(position, command).is_some())
+            .collect::<Vec<_>>();
+        let attribute = self.loop_guard == Some(LoopGuard::Attribute);
+        let mut stale = std::mem::take(&mut self.stale);
+        for (i, (position, command)) in self.positioned_mut().enumerate() {
+            let requested = match &selected {
+                None => true,
+                Some(selectors) => selectors
+                    .iter()
+                    .any(|s| Self::selects(s, position, command.name())),
+            };
+            let affected =
+                position.is_some() || !unaffected_servers.iter().any(|s| s == command.name());
+            if !requested || !affected || (attribute && just_finished(command)) {
+                continue;
+            }
+            if !cooling_down[i] {
+                command.request_run();
+            } else if !stale.contains(&i) {
+                stale.push(i);
+            }
+        }
+        self.stale = stale;
+        if let Some(canary) = self.canary {
+            let others_requested = self
+                .tests
+                .iter()
+                .enumerate()
+                .any(|(i, t)| i != canary && t.has_outstanding_request());
+            let canary = &mut self.tests[canary];
+            if others_requested && !canary.has_outstanding_request() {
+                canary.request_run();
+            }
+        }
+    }
+
+    /// Whether a change to `path` would run each command, with the reason. `path` should be
+    /// relative to the watched directory.
+    pub fn explain(&self, path: &Path) -> Vec<(&str, Result<String, String>)> {
+        let changed = [path.to_path_buf()];
+        self.positioned()
+            .map(|(position, command)| {
+                let selected_by = |rules: &[(&str, &str)]| {
+                    rules
+                        .iter()
+                        .find(|(_, s)| Self::selects(s, position, command.name()))
+                        .map(|(glob, _)| glob.to_string())
+                };
+                let mut reason = match &self.impact {
+                    None => Ok("there's no impact map".to_string()),
+                    Some(map) => match map.matching_rules(path) {
+                        rules if rules.is_empty() => match map.fallback() {
+                            Fallback::All => {
+                                Ok("no impact rule matches, falling back to all".into())
+                            }
+                            Fallback::None => {
+                                Err("no impact rule matches, falling back to none".into())
+                            }
+                        },
+                        rules => match selected_by(&rules) {
+                            Some(glob) => Ok(format!("impact rule {} selects it", glob)),
+                            None => Err("no matching impact rule selects it".to_string()),
+                        },
+                    },
+                };
+                if let (Ok(_), Some(cooldown)) = (&reason, self.cooling_down(position, command)) {
+                    reason = Err(format!(
+                        "it passed within its {:?} --success-cooldown, it runs once that ends",
+                        cooldown
+                    ));
+                }
+                if reason.is_ok() && position.is_none() {
+                    if command.survives_restart() {
+                        reason = Err("it's --persistent and already started".to_string());
+                    } else if self.is_config_only(&changed) {
+                        reason = Err("--server-config-reload reloads it instead".to_string());
+                    } else if !self.needs_restart(command.name(), &changed) {
+                        reason = Err("no --restart-on glob matches".to_string());
+                    }
+                }
+                (command.name(), reason)
+            })
+            .collect()
+    }
+
+    /// Whether `changed` should restart the server, going by its restart filters.
+    fn needs_restart(&self, server: &str, changed: &[PathBuf]) -> bool {
+        let filters = self
+            .restart_filters
+            .iter()
+            .filter(|f| Self::selects(&f.selector, None, server))
+            .collect::<Vec<_>>();
+        filters.is_empty()
+            || changed.is_empty()
+            || changed
+                .iter()
+                .any(|p| filters.iter().any(|f| f.is_match(p)))
+    }
+
+    /// The command's --success-cooldown, if it passed recently enough to still be in it.
+    fn cooling_down(
+        &self,
+        position: Option<usize>,
+        command: &CommandHistory<E>,
+    ) -> Option<Duration> {
+        self.success_cooldowns
+            .iter()
+            .rev()
+            .find(|c| Self::selects(&c.selector, position, command.name()))
+            .map(|c| c.period)
+            .filter(|period| command.is_passing() && command.finished_within(*period))
+    }
+
+    /// Whether `changed` are all server configuration files, which are reloaded rather than
+    /// restarting the servers.
+    fn is_config_only(&self, changed: &[PathBuf]) -> bool {
+        self.config_reload.is_some()
+            && !changed.is_empty()
+            && changed
+                .iter()
+                .all(|p| self.server_config.iter().any(|g| g.is_match(p)))
+    }
+
+    /// Whether runs are allowed now, rather than held back until later.
+    fn can_run_now(&mut self) -> bool {
+        !self.paused
+            && self.schedule.is_active_now()
+            && self.input_idle.as_mut().is_none_or(InputIdle::is_idle)
+    }
+
+    /// Whether every changed path was just written by the command that produces it.
+    fn is_own_output(&self, changed: &[PathBuf]) -> bool {
+        let window = self.loop_guard_window;
+        let recent_outputs = self.outputs_of(|c| c.ran_within(window));
+
+        !recent_outputs.is_empty()
+            && !changed.is_empty()
+            && changed
+                .iter()
+                .all(|p| recent_outputs.iter().any(|o| o.is_match(p)))
+    }
+
+    /// The --command-output files of the commands `of` picks.
+    fn outputs_of(&self, of: impl Fn(&CommandHistory<E>) -> bool) -> Vec<&CommandGlob> {
+        self.positioned()
+            .filter(|(_, command)| of(command))
+            .flat_map(|(position, command)| {
+                self.command_outputs
+                    .iter()
+                    .filter(move |o| Self::selects(&o.selector, position, command.name()))
+            })
+            .collect()
+    }
+
+    pub fn control(&mut self, control: Control) -> Result<(), Box<dyn Error>> {
+        match control {
+            Control::ToggleServer => self.toggle_server(),
+            Control::ClearHistory => {
+                for command in self.commands_mut() {
+                    command.clear_history();
+                }
+                Ok(())
+            }
+            Control::UpdateGolden => self.update_goldens(),
+            Control::FailSummary => {
+                self.notifier.stream.print(self.fail_summary(false));
+                Ok(())
+            }
+            Control::RunAll => {
+                self.request_full_run();
+                Ok(())
+            }
+            Control::Pause => {
+                if !self.paused {
+                    self.paused = true;
+                    self.notifier
+                        .stream
+                        .println("watchdo: paused, changes wait until resumed");
+                }
+                Ok(())
+            }
+            Control::Resume => {
+                self.paused = false;
+                Ok(())
+            }
+            Control::RestartServers => {
+                for server in &mut self.servers {
+                    if !server.has_outstanding_request() && !server.survives_restart() {
+                        server.request_run();
+                    }
+                }
+                Ok(())
+            }
+            Control::Pin(n) => {
+                let failing = self
+                    .commands()
+                    .nth(n.wrapping_sub(1))
+                    .map(last_failing_output);
+                match failing {
+                    Some(failing) => self.pinned = Some((n - 1, failing)),
+                    None => self
+                        .notifier
+                        .stream
+                        .println(format!("watchdo: no command {} to pin", n)),
+                }
+                Ok(())
+            }
+            Control::Unpin => {
+                self.pinned = None;
+                Ok(())
+            }
+            Control::SwitchPreset(name) => self.switch_preset(name.as_deref()),
+            Control::Expand(n) => {
+                let name = match self.commands().nth(n.wrapping_sub(1)) {
+                    Some(command) => command.name().to_string(),
+                    None => {
+                        self.notifier
+                            .stream
+                            .println(format!("watchdo: no command {} to expand", n));
+                        return Ok(());
+                    }
+                };
+                if !self.collapse_output {
+                    self.notifier
+                        .stream
+                        .println("watchdo: output is only collapsed with --collapse-output");
+                } else if self.expanded.remove(&(n - 1)) {
+                    self.notifier
+                        .stream
+                        .println(format!("watchdo: collapsed the output of {}", name));
+                } else {
+                    self.expanded.insert(n - 1);
+                    self.to_expand.push(n - 1);
+                }
+                Ok(())
+            }
+            Control::RunTask(name) => {
+                match self.tasks.iter_mut().find(|t| t.name() == name) {
+                    Some(task)
+                        if task.running_for().is_some() || task.has_outstanding_request() =>
+                    {
+                        self.notifier
+                            .stream
+                            .println(format!("watchdo: task {} is already running", name))
+                    }
+                    Some(task) => {
+                        self.notifier
+                            .stream
+                            .println(format!("watchdo: running task {}", name));
+                        task.request_run();
+                    }
+                    None => self
+                        .notifier
+                        .stream
+                        .println(format!("watchdo: no task named {}", name)),
+                }
+                Ok(())
+            }
+            // Only meaningful with --panes, which handles them itself.
+            Control::SelectPane(_)
+            | Control::NextPane
+            | Control::PrevPane
+            | Control::ScrollUp
+            | Control::ScrollDown => Ok(()),
+        }
+    }
+
+    /// Terminates every running command and waits a while for them to exit.
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        let commands = self.tests.iter_mut().chain(&mut self.servers);
+        let commands = commands
+            .chain(&mut self.tasks)
+            .chain(&mut self.config_reload);
+        for command in commands {
+            command.kill(
+                "watchdo: shutting down".to_string(),
+                Termination::Intentional,
+            )?;
+        }
+
+        let started = Instant::now();
+        while started.elapsed() < KILL_AFTER {
+            let mut running = false;
+            let commands = self.tests.iter_mut().chain(&mut self.servers);
+            let commands = commands
+                .chain(&mut self.tasks)
+                .chain(&mut self.config_reload);
+            for command in commands {
+                running |= command.is_running()?;
+            }
+            if !running {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Removing the cgroup needs everything in it to have exited.
+        drop(self.cgroup.take());
+        Ok(())
+    }
+
+    fn toggle_server(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.servers.is_empty() {
+            return Ok(());
+        }
+
+        self.server_enabled = !self.server_enabled;
+        for server in &mut self.servers {
+            if server.survives_restart() {
+                continue;
+            }
+            if !self.server_enabled {
+                server.kill(
+                    "watchdo: server disabled".to_string(),
+                    Termination::Intentional,
+                )?;
+            } else if !server.has_outstanding_request() {
+                server.request_run();
+            }
+        }
+        Ok(())
+    }
+
+    /// Only keeps the commands of the preset active, stopping the others and running the ones
+    /// that weren't active. Every command is active again without a preset.
+    fn switch_preset(&mut self, name: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let active = match name {
+            None => vec![true; self.active.len()],
+            Some(name) => {
+                let selectors = self
+                    .presets
+                    .iter()
+                    .filter(|p| p.name == name)
+                    .map(|p| p.selector.as_str())
+                    .collect::<Vec<_>>();
+                if selectors.is_empty() {
+                    self.notifier
+                        .stream
+                        .println(format!("watchdo: no preset named {}", name));
+                    return Ok(());
+                }
+                self.positioned()
+                    .map(|(position, c)| {
+                        selectors
+                            .iter()
+                            .any(|s| Self::selects(s, position, c.name()))
+                    })
+                    .collect()
+            }
+        };
+
+        let commands = self.tests.iter_mut().chain(&mut self.servers);
+        for ((command, was), &now) in commands.zip(&self.active).zip(&active) {
+            if *was && !now {
+                command.cancel_request();
+                command.kill(
+                    "watchdo: not in the preset".to_string(),
+                    Termination::Intentional,
+                )?;
+            } else if !was && now && !command.has_outstanding_request() {
+                command.request_run();
+            }
+        }
+        self.active = active;
+        self.notifier.stream.println(format!(
+            "watchdo: switched to {}, {} of {} commands active",
+            name.map_or("every command".to_string(), |n| format!("preset {}", n)),
+            self.active.iter().filter(|a| **a).count(),
+            self.active.len()
+        ));
+        Ok(())
+    }
+
+    /// Replaces the golden files with the latest output of their commands.
+    fn update_goldens(&mut self) -> Result<(), Box<dyn Error>> {
+        for golden in &self.goldens {
+            for (position, command) in self.positioned() {
+                if !Self::selects(&golden.selector, position, command.name()) {
+                    continue;
+                }
+                if let Some(output) = command.last_output() {
+                    golden.update(output)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `selector` refers to the command, by its text, its position or as a server.
+    fn selects(selector: &str, position: Option<usize>, name: &str) -> bool {
+        match position {
+            Some(p) => selector == name || selector == p.to_string(),
+            None => selector == name || selector == "server",
+        }
+    }
+
+    /// Commands with their position, starting at 1. Servers have no position.
+    fn positioned(&self) -> impl Iterator<Item = (Option<usize>, &CommandHistory<E>)> {
+        self.tests
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (Some(i + 1), t))
+            .chain(self.servers.iter().map(|s| (None, s)))
+    }
+
+    /// Requests a run of every command right away, regardless of the impact map, loop guard,
+    /// schedule or anything else that would hold some back. Persistent servers keep running.
+    pub fn request_full_run(&mut self) {
+        self.queued = None;
+        self.debounced = None;
+        self.stale.clear();
+        self.last_request = Some(Instant::now());
+        self.last_trigger = Vec::new();
+        self.triggers += 1;
+        self.reshuffle();
+        for command in self.commands_mut() {
+            if !command.has_outstanding_request() && !command.survives_restart() {
+                command.request_run();
+            }
+        }
+    }
+
+    /// Requests a run of the commands `selector` refers to, returning whether there were any.
+    pub fn request_selected(&mut self, selector: &str) -> bool {
+        let mut found = false;
+        for (position, command) in self.positioned_mut() {
+            if Self::selects(selector, position, command.name()) {
+                found = true;
+                if !command.has_outstanding_request() {
+                    command.request_run();
+                }
+            }
+        }
+        found
+    }
+
+    /// The seed of the run order, when it's shuffled.
+    pub fn shuffle_seed(&self) -> Option<u64> {
+        self.shuffle.as_ref().map(Shuffle::seed)
+    }
+
+    /// Picks a new order to start the tests in. Tests without a phase trade places in the
+    /// order they run one after another.
+    fn reshuffle(&mut self) {
+        let shuffle = match &mut self.shuffle {
+            Some(shuffle) => shuffle,
+            None => return,
+        };
+        shuffle.shuffle(&mut self.order);
+
+        // They only trade their own phases, so none lands in a given phase.
+        let pinned = &self.pinned_phases;
+        let mut turns = (0..self.tests.len())
+            .filter(|i| !pinned[*i])
+            .map(|i| self.phases[i])
+            .collect::<Vec<_>>();
+        turns.sort_unstable();
+        for (&i, turn) in self.order.iter().filter(|i| !pinned[**i]).zip(turns) {
+            self.phases[i] = turn;
+        }
+    }
+
+    fn positioned_mut(&mut self) -> impl Iterator<Item = (Option<usize>, &mut CommandHistory<E>)> {
+        self.tests
+            .iter_mut()
+            .enumerate()
+            .map(|(i, t)| (Some(i + 1), t))
+            .chain(self.servers.iter_mut().map(|s| (None, s)))
+    }
+
+    fn commands(&self) -> impl Iterator<Item = &CommandHistory<E>> {
+        self.tests.iter().chain(self.servers.iter())
+    }
+
+    fn commands_mut(&mut self) -> impl Iterator<Item = &mut CommandHistory<E>> {
+        self.tests.iter_mut().chain(self.servers.iter_mut())
+    }
+
+    /// `print_output` gets the name and output of each command whose output should be shown.
+    pub fn tick(
+        &mut self,
+        mut print_output: impl FnMut(&str, &CommandOutput),
+    ) -> Result<(), Box<dyn Error>> {
+        if self.queued.is_some() && self.can_run_now() {
+            let queued = self.queued.take().unwrap();
+            self.request_run(&queued);
+        }
+        let debounced = self
+            .last_request
+            .is_some_and(|t| t.elapsed() > self.debounce);
+        if self.debounced.is_some() && debounced {
+            let changed = self.debounced.take().unwrap();
+            self.last_request = None;
+            self.request_run(&changed);
+        }
+        self.notifier.tick();
+
+        for i in std::mem::take(&mut self.stale) {
+            let (position, command) = self.positioned().nth(i).unwrap();
+            if self.cooling_down(position, command).is_some() || !self.can_run_now() {
+                self.stale.push(i);
+                continue;
+            }
+            let (_, command) = self.positioned_mut().nth(i).unwrap();
+            if !command.has_outstanding_request() {
+                command.request_run();
+            }
+        }
+
+        for i in std::mem::take(&mut self.to_expand) {
+            let position = Some(i + 1).filter(|_| i < self.tests.len());
+            let command = self.commands().nth(i).unwrap();
+            if let Some(output) = command.last_output() {
+                let shown = Self::condensed(
+                    &self.first_failures,
+                    &self.diff_only,
+                    position,
+                    command.name(),
+                    output,
+                );
+                print_output(command.name(), shown.as_ref().unwrap_or(output));
+            }
+        }
+
+        for i in 0..self.intervals.len() {
+            let (interval, last) = &self.intervals[i];
+            if last.elapsed() < interval.period || !self.schedule.is_active_now() {
+                continue;
+            }
+
+            let selector = interval.selector.clone();
+            self.intervals[i].1 = Instant::now();
+            self.request_selected(&selector);
+        }
+
+        for command in self.tests.iter_mut().chain(self.servers.iter_mut()) {
+            let reaped = command.reap_orphans()?;
+            if reaped > 0 {
+                self.notifier.stream.println(format!(
+                    "watchdo: reaped {} leftover process(es) of {}",
+                    reaped,
+                    command.name()
+                ));
+            }
+        }
+
+        let check_memory = self
+            .memory_checked
+            .is_none_or(|t| t.elapsed() >= Duration::from_secs(1));
+        if let (Some(max_memory), true) = (self.max_memory, check_memory) {
+            self.memory_checked = Some(Instant::now());
+            for command in self.commands_mut() {
+                if let Some(usage) = command.memory_usage()? {
+                    if usage > max_memory {
+                        command.kill(
+                            format!(
+                                "watchdo: terminated, using {} bytes of memory (limit {})",
+                                usage, max_memory
+                            ),
+                            Termination::Limit,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if let Some(timeout) = self.timeout {
+            for test in &mut self.tests {
+                if test.running_for().is_some_and(|d| d > timeout) {
+                    test.kill(
+                        format!("watchdo: terminated, running for longer than {:?}", timeout),
+                        Termination::Limit,
+                    )?;
+                }
+            }
+        }
+
+        let mut canary_failed = false;
+        if let Some(timeout) = self.inactivity_timeout {
+            for test in &mut self.tests {
+                if test.quiet_for().is_some_and(|d| d > timeout) {
+                    test.kill(
+                        format!(
+                            "watchdo: terminated, no output for longer than {:?}",
+                            timeout
+                        ),
+                        Termination::Limit,
+                    )?;
+                }
+            }
+        }
+
+        for (i, test) in self.tests.iter_mut().enumerate() {
+            let name = test.name().to_string();
+            // Live output has been shown as it was written.
+            let shown = test.is_live_output();
+            let finished = match test.try_finish()? {
+                Some(output) => {
+                    // Passing runs are recorded too, so a failure coming back is printed again.
+                    let new = self.printed.is_new(i, output);
+                    if !output.success && !shown && new {
+                        let shown = Self::condensed(
+                            &self.first_failures,
+                            &self.diff_only,
+                            Some(i + 1),
+                            &name,
+                            output,
+                        );
+                        Self::print_collapsible(
+                            self.collapse_output && !self.expanded.contains(&i),
+                            self.notifier.stream,
+                            i + 1,
+                            &name,
+                            shown.as_ref().unwrap_or(output),
+                            &mut print_output,
+                        );
+                        Self::hint_not_found(self.notifier.stream, &name, output);
+                    }
+                    Self::write_logs(
+                        &self.command_logs,
+                        self.notifier.stream,
+                        Some(i + 1),
+                        &name,
+                        output,
+                    );
+                    Self::update_pin(&mut self.pinned, i, output);
+                    true
+                }
+                None => {
+                    if test.take_retried() {
+                        self.metrics.retried(test.name(), test.last_duration());
+                    }
+                    false
+                }
+            };
+            let headline = match &self.primary {
+                Some(primary) => Self::selects(primary, Some(i + 1), test.name()),
+                None => true,
+            };
+            // Intentional terminations aren't passes or failures.
+            let finished = finished && test.last_run_counts();
+            if finished && headline {
+                self.notifier
+                    .finished(test.name(), test.consecutive_failures());
+            }
+            if finished {
+                self.metrics
+                    .finished(test.name(), test.is_passing(), test.last_duration());
+                if let Some(events) = &mut self.recent_events {
+                    events.finished(test.name(), test.is_passing());
+                }
+            }
+            // When the canary is about to run again, the others wait for that run instead.
+            canary_failed |= finished
+                && self.canary == Some(i)
+                && !test.is_passing()
+                && !test.has_outstanding_request();
+        }
+
+        // Only the edge counts, so the scripts run once for however long it stays green.
+        let active_tests = self
+            .tests
+            .iter()
+            .zip(&self.active)
+            .filter(|(_, active)| **active)
+            .map(|(test, _)| test)
+            .collect::<Vec<_>>();
+        let green = !active_tests.is_empty() && active_tests.iter().all(|t| t.is_passing());
+        if green && !self.green {
+            let passing = active_tests.iter().map(|t| t.name()).collect::<Vec<_>>();
+            for script in &self.on_green {
+                run_on_green(script, &passing, self.notifier.stream);
+            }
+        }
+        self.green = green;
+
+        if canary_failed {
+            self.skip_after_canary();
+        }
+
+        // Whatever asked for them, inactive commands don't run.
+        let commands = self.tests.iter_mut().chain(&mut self.servers);
+        for (command, _) in commands.zip(&self.active).filter(|(_, active)| !**active) {
+            command.cancel_request();
+        }
+
+        let mut phases = self.phases.clone();
+        phases.sort_unstable();
+        phases.dedup();
+        let mut children = self.commands().map(CommandHistory::children).sum::<usize>();
+        let mut capped = false;
+        let test_phases = &self.phases;
+        let gates = &self.gates;
+        let active = &self.active;
+        for phase in phases {
+            let mut done = true;
+            for &i in self
+                .order
+                .iter()
+                .filter(|i| test_phases[**i] == phase && gates[**i].is_none() && active[**i])
+            {
+                let test = &mut self.tests[i];
+                if test.children() == 0 && children >= self.max_children {
+                    capped = true;
+                    done = false;
+                    continue;
+                }
+                let before = test.children();
+                test.run_if_needed()?;
+                children = children - before + test.children();
+                done &= if self.run_after_failure {
+                    test.is_finished()
+                } else {
+                    test.is_passing()
+                };
+                // The rest of the phase waits too.
+                if self.sequential && !test.is_finished() {
+                    break;
+                }
+            }
+            if !done {
+                break;
+            }
+        }
+
+        for i in 0..self.tests.len() {
+            let (prerequisite, code) = match self.gates[i] {
+                Some(gate) if self.tests[i].has_outstanding_request() => gate,
+                _ => continue,
+            };
+            let prerequisite = &self.tests[prerequisite];
+            if !prerequisite.is_finished() {
+                continue;
+            }
+            let exited = prerequisite.last_output().and_then(|o| o.code);
+            if exited != Some(code) {
+                let exited =
+                    exited.map_or("didn't exit".to_string(), |c| format!("exited with {}", c));
+                let message = format!(
+                    "watchdo: {}: skipped, {} {}, not {}",
+                    self.tests[i].name(),
+                    prerequisite.name(),
+                    exited,
+                    code
+                );
+                self.tests[i].cancel_request();
+                self.notifier.stream.println(message);
+                continue;
+            }
+            let test = &mut self.tests[i];
+            if test.children() == 0 && children >= self.max_children {
+                capped = true;
+                continue;
+            }
+            let before = test.children();
+            test.run_if_needed()?;
+            children = children - before + test.children();
+        }
+
+        for (i, server_history) in self.servers.iter_mut().enumerate() {
+            let server_phase = self.server_phases[i];
+            let earlier_tests_succeeded =
+                self.tests.iter().zip(&self.phases).zip(&self.active).all(
+                    |((test, phase), active)| {
+                        *phase >= server_phase || !active || test.is_passing()
+                    },
+                );
+
+            if self.server_enabled
+                && server_history.has_outstanding_request()
+                && earlier_tests_succeeded
+            {
+                if server_history.children() == 0 && children >= self.max_children {
+                    capped = true;
+                    continue;
+                }
+                let before = server_history.children();
+                let restarting = server_history.running_for().is_some();
+                server_history.restart()?;
+                children = children - before + server_history.children();
+                if let (true, Some(events)) = (restarting, &mut self.recent_events) {
+                    events.push(format!("{} restarted", server_history.name()));
+                }
+                // A graceful restart whose new run exited before it was ready.
+                if let Some(CommandState::Completed(output)) = server_history.last() {
+                    let name = server_history.name();
+                    let index = self.tests.len() + i;
+                    if self.printed.is_new(index, output) {
+                        Self::print_collapsible(
+                            self.collapse_output && !self.expanded.contains(&index),
+                            self.notifier.stream,
+                            index + 1,
+                            name,
+                            output,
+                            &mut print_output,
+                        );
+                        Self::hint_not_found(self.notifier.stream, name, output);
+                    }
+                }
+                continue;
+            }
+
+            let name = server_history.name().to_string();
+            let shown = server_history.is_live_output();
+            if let Some(output) = server_history.try_finish()? {
+                let index = self.tests.len() + i;
+                if !shown && self.printed.is_new(index, output) {
+                    let shown =
+                        Self::condensed(&self.first_failures, &self.diff_only, None, &name, output);
+                    Self::print_collapsible(
+                        self.collapse_output && !self.expanded.contains(&index),
+                        self.notifier.stream,
+                        index + 1,
+                        &name,
+                        shown.as_ref().unwrap_or(output),
+                        &mut print_output,
+                    );
+                    Self::hint_not_found(self.notifier.stream, &name, output);
+                }
+                Self::write_logs(
+                    &self.command_logs,
+                    self.notifier.stream,
+                    None,
+                    &name,
+                    output,
+                );
+                Self::update_pin(&mut self.pinned, self.tests.len() + i, output);
+                // Restarts aren't passes or failures.
+                if !server_history.last_run_counts() {
+                    continue;
+                }
+                if self
+                    .primary
+                    .as_ref()
+                    .is_some_and(|p| Self::selects(p, None, &name))
+                {
+                    self.notifier
+                        .finished(&name, server_history.consecutive_failures());
+                }
+                self.metrics.finished(
+                    server_history.name(),
+                    server_history.is_passing(),
+                    server_history.last_duration(),
+                );
+                if let Some(events) = &mut self.recent_events {
+                    let how = if server_history.is_passing() {
+                        "exited"
+                    } else {
+                        "crashed"
+                    };
+                    events.push(format!("{} {}", name, how));
+                }
+            } else if server_history.take_retried() {
+                self.metrics
+                    .retried(server_history.name(), server_history.last_duration());
+            }
+        }
+
+        if let Some(reload) = &mut self.config_reload {
+            let name = reload.name().to_string();
+            if let Some(output) = reload.try_finish()? {
+                if !output.success {
+                    print_output(&name, output);
+                }
+            }
+            reload.run_if_needed()?;
+        }
+
+        // Tasks always show their output, since they were asked for.
+        for task in &mut self.tasks {
+            let name = task.name().to_string();
+            if let Some(output) = task.try_finish()? {
+                print_output(&name, output);
+            }
+            task.run_if_needed()?;
+        }
+
+        if capped && !self.children_capped {
+            self.notifier.stream.println(format!(
+                "watchdo: {} children running, holding back new runs (--max-children)",
+                children
+            ));
+        }
+        self.children_capped = capped;
+
+        Ok(())
+    }
+
+    /// Drops the other tests' requested runs, since the canary failed.
+    fn skip_after_canary(&mut self) {
+        let canary = self.canary;
+        let skipped = self
+            .tests
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, t)| Some(*i) != canary && t.has_outstanding_request())
+            .map(|(_, t)| {
+                t.cancel_request();
+                t.name().to_string()
+            })
+            .collect::<Vec<_>>();
+        if !skipped.is_empty() {
+            self.notifier.stream.println(format!(
+                "watchdo: {}: skipped, canary failed",
+                skipped.join(", ")
+            ));
+        }
+    }
+
+    /// The output to show in place of a failed run's, with only its diff or its first failure.
+    fn condensed(
+        first_failures: &[FirstFailure],
+        diff_only: &[DiffOnly],
+        position: Option<usize>,
+        name: &str,
+        output: &CommandOutput,
+    ) -> Option<CommandOutput> {
+        let diff = diff_only
+            .iter()
+            .rev()
+            .find(|d| Self::selects(&d.selector, position, name))
+            .and_then(|d| d.apply(output));
+        diff.or_else(|| {
+            first_failures
+                .iter()
+                .rev()
+                .find(|f| Self::selects(&f.selector, position, name))
+                .and_then(|f| f.apply(output))
+        })
+    }
+
+    /// Prints a run's output, or only how much of it there is while it's collapsed.
+    fn print_collapsible(
+        collapsed: bool,
+        stream: Stream,
+        position: usize,
+        name: &str,
+        output: &CommandOutput,
+        print_output: &mut impl FnMut(&str, &CommandOutput),
+    ) {
+        if collapsed {
+            stream.println(collapsed_output(position, name, output));
+        } else {
+            print_output(name, output);
+        }
+    }
+
+    /// Points out a command that failed because its program wasn't found, which otherwise looks
+    /// like any other failure.
+    fn hint_not_found(stream: Stream, name: &str, output: &CommandOutput) {
+        if output.is_not_found() {
+            stream.println(format!(
+                "watchdo: {}: command not found, is it misspelled or not installed?",
+                name
+            ));
+        }
+    }
+
+    /// Appends a finished run's output to the command's logs. A log that can't be written is
+    /// reported rather than stopping the watch.
+    fn write_logs(
+        logs: &[CommandLog],
+        stream: Stream,
+        position: Option<usize>,
+        name: &str,
+        output: &CommandOutput,
+    ) {
+        for log in logs
+            .iter()
+            .filter(|l| Self::selects(&l.selector, position, name))
+        {
+            if let Err(e) = log.write(name, output) {
+                stream.println(format!(
+                    "watchdo: couldn't write {}: {}",
+                    log.path.display(),
+                    e
+                ));
+            }
+        }
+    }
+
+    /// Keeps the pinned command's output if it failed.
+    fn update_pin(
+        pinned: &mut Option<(usize, Option<String>)>,
+        index: usize,
+        output: &CommandOutput,
+    ) {
+        match pinned {
+            Some((pinned, failing)) if *pinned == index && !output.success => {
+                *failing = Some(format!("{}{}", output.err, output.out));
+            }
+            _ => {}
+        }
+    }
+
+    /// The pinned command's name and its latest failing output, if it has failed.
+    pub fn pinned_output(&self) -> Option<(&str, Option<&str>)> {
+        let (index, failing) = self.pinned.as_ref()?;
+        let command = self.commands().nth(*index)?;
+        Some((command.name(), failing.as_deref()))
+    }
+
+    /// The output each command kept live has written since this was last called.
+    pub fn take_live_output(&mut self) -> Vec<(String, String)> {
+        self.commands_mut()
+            .filter_map(|c| c.take_live_output().map(|o| (c.name().to_string(), o)))
+            .collect()
+    }
+
+    /// Every command whose latest run failed, with the first line of its output, or all of it
+    /// with `full`.
+    pub fn fail_summary(&self, full: bool) -> String {
+        let failing = self
+            .commands()
+            .filter(|c| c.consecutive_failures() > 0)
+            .filter_map(|c| c.last_output().map(|o| (c.name(), o)))
+            .collect::<Vec<_>>();
+
+        let mut summary = format!("== {} failing ==\n", failing.len());
+        for (name, output) in failing {
+            let name = match output.counts {
+                Some(counts) => format!("{} ({})", name, counts),
+                None => name.to_string(),
+            };
+            if full {
+                summary.push_str(&format!("-- {} --\n{}{}", name, output.err, output.out));
+                if !summary.ends_with('\n') {
+                    summary.push('\n');
+                }
+            } else {
+                let first_line = output
+                    .err
+                    .lines()
+                    .chain(output.out.lines())
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or("");
+                summary.push_str(&format!("{}: {}\n", name, first_line));
+            }
+        }
+        summary
+    }
+
+    /// The changed paths behind the latest run request, numbered so new requests can be told
+    /// apart. Empty paths mean the changes are unknown.
+    pub fn last_trigger(&self) -> (usize, &[PathBuf]) {
+        (self.triggers, &self.last_trigger)
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Whether no test is running, so nothing more will run until something changes.
+    pub fn is_settled(&self) -> bool {
+        !self.tests.iter().any(|t| t.running_for().is_some())
+    }
+
+    pub fn aggregate(&self) -> Aggregate {
+        let headline = self.headline();
+        if headline.iter().all(|c| c.is_passing()) {
+            Aggregate::Passing
+        } else if headline.iter().any(|c| is_busy(c)) {
+            Aggregate::Running
+        } else {
+            Aggregate::Failing
+        }
+    }
+
+    /// The overall status and each command's latest state, for --rpc clients.
+    pub fn status(&self) -> Json {
+        let commands = self
+            .positioned()
+            .enumerate()
+            .map(|(i, (position, c))| {
+                let waiting_for = self.waiting_for(i);
+                let state = match c.last() {
+                    None => "idle",
+                    Some(CommandState::Requested) if !waiting_for.is_empty() => "waiting",
+                    Some(CommandState::Requested) => "requested",
+                    Some(CommandState::Running) => "running",
+                    Some(CommandState::Retried(_)) => "retried",
+                    Some(CommandState::Terminated(..)) => "terminated",
+                    Some(CommandState::Completed(_)) if c.is_passing() => "passed",
+                    Some(CommandState::Completed(o)) if o.is_not_found() => "not-found",
+                    Some(CommandState::Completed(_)) => "failed",
+                };
+                Json::object(vec![
+                    ("name", c.name().into()),
+                    ("server", Json::Bool(position.is_none())),
+                    ("state", state.into()),
+                    (
+                        "waiting_for",
+                        Json::Array(waiting_for.into_iter().map(Json::from).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        Json::object(vec![
+            ("status", self.aggregate().message().into()),
+            ("paused", Json::Bool(self.paused)),
+            ("commands", Json::Array(commands)),
+        ])
+    }
+
+    /// A short status line counting passing, failing and running commands, like `watchdo: 3✓ 1x`.
+    pub fn oneline(&self) -> String {
+        let headline = self.headline();
+        let running = headline.iter().filter(|c| is_busy(c)).count();
+        let passing = headline
+            .iter()
+            .filter(|c| !is_busy(c) && c.is_passing())
+            .count();
+        let failing = headline.len() - running - passing;
+
+        let mut line = format!(
+            "watchdo: {}{} {}{}",
+            passing, self.theme.passed.glyph, failing, self.theme.failed.glyph
+        );
+        if running > 0 {
+            line.push_str(&format!(" {}{}", running, self.theme.running.glyph));
+        }
+        line
+    }
+
+    /// The commands deciding the overall status: the primary one, or else every active test.
+    fn headline(&self) -> Vec<&CommandHistory<E>> {
+        self.positioned()
+            .zip(&self.active)
+            .filter(|(_, active)| **active)
+            .map(|(positioned, _)| positioned)
+            .filter(|(position, c)| match &self.primary {
+                Some(primary) => Self::selects(primary, *position, c.name()),
+                None => position.is_some(),
+            })
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    pub fn panes(&self) -> Vec<Pane> {
+        self.commands()
+            .map(|c| {
+                let state = print(c, 1, &self.theme, false, false, None, false)
+                    .map(|s| s.to_string())
+                    .collect::<String>();
+                let output = c
+                    .last_output()
+                    .map(|o| format!("{}{}", o.err, o.out))
+                    .unwrap_or_default();
+                Pane {
+                    tab: format!("{} {}", state, c.name()),
+                    output,
+                }
+            })
+            .collect()
+    }
+
+    pub fn print(&self, width: usize) -> Vec<ColoredString> {
+        let recent_events = self
+            .recent_events
+            .as_ref()
+            .and_then(|e| e.line(width))
+            .map(|line| line.dimmed());
+        let tinted;
+        let theme = if self.health_tint {
+            tinted = self.theme.tinted(self.aggregate());
+            &tinted
+        } else {
+            &self.theme
+        };
+        self.commands()
+            .enumerate()
+            .flat_map(|(i, c)| {
+                let waiting_for = self.waiting_for(i);
+                let waiting_for = Some(waiting_for.join(", ")).filter(|w| !w.is_empty());
+                print(
+                    c,
+                    width,
+                    theme,
+                    self.show_elapsed,
+                    self.sparkline,
+                    waiting_for,
+                    self.idle_start && self.triggers == 0,
+                )
+            })
+            .chain(recent_events)
+            .collect()
+    }
+
+    /// The earlier tests a command's requested run is waiting for, by their names, going by
+    /// `commands()` order. Empty when it's free to start, or nothing is requested.
+    fn waiting_for(&self, command: usize) -> Vec<&str> {
+        let (phase, done): (usize, fn(&CommandHistory<E>) -> bool) = if command < self.tests.len() {
+            let done = if self.run_after_failure {
+                CommandHistory::is_finished
+            } else {
+                CommandHistory::is_passing
+            };
+            (self.phases[command], done)
+        } else {
+            let server = command - self.tests.len();
+            (self.server_phases[server], CommandHistory::is_passing)
+        };
+        match self.commands().nth(command) {
+            Some(c) if c.has_outstanding_request() => {}
+            _ => return Vec::new(),
+        }
+        if let Some(Some((prerequisite, _))) = self.gates.get(command) {
+            let prerequisite = &self.tests[*prerequisite];
+            if prerequisite.is_finished() {
+                return Vec::new();
+            }
+            return vec![prerequisite.name()];
+        }
+
+        // When run one at a time, tests also wait for the ones before them in their phase.
+        let turn = |i: usize| self.order.iter().position(|o| *o == i);
+        let earlier = |i: usize, p: usize| {
+            p < phase
+                || (self.sequential
+                    && command < self.tests.len()
+                    && p == phase
+                    && turn(i) < turn(command))
+        };
+        self.tests
+            .iter()
+            .zip(&self.phases)
+            .enumerate()
+            .filter(|(i, (test, p))| earlier(*i, **p) && !done(test))
+            .map(|(_, (test, _))| test.name())
+            .collect()
+    }
+}
+
+struct PrintedOutputs {
+    collapse_whitespace: bool,
+    last: HashMap<usize, String>,
+}
+
+impl PrintedOutputs {
+    fn new(collapse_whitespace: bool) -> Self {
+        PrintedOutputs {
+            collapse_whitespace,
+            last: HashMap::new(),
+        }
+    }
+
+    fn is_new(&mut self, command: usize, output: &CommandOutput) -> bool {
+        if !self.collapse_whitespace {
+            return true;
+        }
+
+        let normalized = format!("{}\0{}", normalize(&output.err), normalize(&output.out));
+        self.last.insert(command, normalized.clone()) != Some(normalized)
+    }
+}
+
+/// Adds changes to the ones held back. Unknown changes, an empty list, stay unknown.
+fn hold(held: &mut Option<Vec<PathBuf>>, changed: &[PathBuf]) {
+    *held = Some(match held.take() {
+        Some(mut held) if !held.is_empty() && !changed.is_empty() => {
+            held.extend_from_slice(changed);
+            held
+        }
+        Some(_) => Vec::new(),
+        None => changed.to_vec(),
+    });
+}
+
+fn normalize(s: &str) -> String {
+    s.lines()
+        .map(str::trim_end)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The line standing in for a collapsed run's output.
+fn collapsed_output(position: usize, name: &str, output: &CommandOutput) -> String {
+    let lines = output.err.lines().count() + output.out.lines().count();
+    format!(
+        "watchdo: {} {}, {} line{} of output, e{} to expand",
+        name,
+        if output.success { "passed" } else { "failed" },
+        lines,
+        if lines == 1 { "" } else { "s" },
+        position
+    )
+}
+
+fn last_failing_output<E: Executor>(command: &CommandHistory<E>) -> Option<String> {
+    command
+        .iter()
+        .filter_map(|state| match state {
+            CommandState::Completed(output) | CommandState::Terminated(output, _)
+                if !output.success =>
+            {
+                Some(format!("{}{}", output.err, output.out))
+            }
+            _ => None,
+        })
+        .last()
+}
+
+/// Whether the command is running or about to.
+fn is_busy<E: Executor>(command: &CommandHistory<E>) -> bool {
+    matches!(
+        command.last(),
+        Some(CommandState::Requested | CommandState::Running | CommandState::Retried(_))
+    )
+}
+
+/// How many of the latest runs the sparkline shows.
+const SPARKLINE_RUNS: usize = 8;
+
+fn print<'c, E: Executor>(
+    command_history: &'c CommandHistory<E>,
+    width: usize,
+    theme: &'c Theme,
+    show_elapsed: bool,
+    show_sparkline: bool,
+    waiting_for: Option<String>,
+    idle: bool,
+) -> impl Iterator<Item = ColoredString> + 'c {
+    let expect_failure = command_history.expects_failure();
+    let last = command_history.iter().count().saturating_sub(1);
+    // Whole seconds, so the status bar only changes once a second.
+    let elapsed = command_history
+        .running_for()
+        .filter(|_| show_elapsed)
+        .map(|d| format!(" {}s", d.as_secs()))
+        .unwrap_or_default();
+    let is_waiting = waiting_for.is_some();
+    let chars = command_history
+        .iter()
+        .enumerate()
+        .flat_map(move |(i, state)| match state {
+            CommandState::Requested if is_waiting && i == last => vec![theme.waiting.paint()],
+            CommandState::Requested => vec![theme.requested.paint()],
+            CommandState::Running => std::iter::once(theme.running.paint())
+                .chain(
+                    elapsed
+                        .chars()
+                        .map(|c| theme.running.paint_glyph(&c.to_string())),
+                )
+                .collect(),
+            CommandState::Completed(output) => vec![match (output.success, expect_failure) {
+                (true, false) if output.warning => theme.warning.paint(),
+                (true, false) => theme.passed.paint(),
+                (false, false) if output.is_not_found() => theme.not_found.paint(),
+                (false, false) => theme.failed.paint(),
+                // Passing when a failure was expected is worth a warning.
+                (true, true) => theme.unexpected_pass.paint(),
+                (false, true) => theme.expected_failure.paint(),
+            }],
+            CommandState::Retried(_) => vec![theme.retried.paint()],
+            CommandState::Terminated(output, _) => {
+                if output.success {
+                    vec![theme.terminated_passed.paint()]
+                } else {
+                    vec![theme.terminated_failed.paint()]
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let chars = if idle && chars.is_empty() {
+        vec![theme.idle.paint()]
+    } else {
+        chars
+    };
+    let durations = command_history.durations().collect::<Vec<_>>();
+    let recent = &durations[durations.len().saturating_sub(SPARKLINE_RUNS)..];
+    let spark = if show_sparkline && !recent.is_empty() {
+        format!(" {}", sparkline(recent))
+    } else {
+        String::new()
+    };
+    let counts = command_history
+        .last_output()
+        .and_then(|o| o.counts)
+        .map(|c| {
+            let mut counts = format!(
+                " {}{} {}{}",
+                c.passed, theme.passed.glyph, c.failed, theme.failed.glyph
+            );
+            if c.skipped > 0 {
+                counts.push_str(&format!(" {} skipped", c.skipped));
+            }
+            counts
+        })
+        .unwrap_or_default();
+    let waiting_for = waiting_for
+        .map(|w| format!(" waiting for {}", w))
+        .unwrap_or_default();
+    let suffix = spark
+        .chars()
+        .chain(counts.chars())
+        .chain(waiting_for.chars())
+        .map(|c| c.to_string().normal())
+        .collect::<Vec<_>>();
+    let spaces = std::iter::repeat_n(theme.padding.paint(), width);
+    let whole_print = spaces.chain(chars).chain(suffix);
+    match whole_print.size_hint() {
+        (min, Some(max)) => {
+            assert_eq!(min, max);
+            Box::new(whole_print.skip(min - width).take(width))
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn finishing(success: bool) -> CommandHistory<MockExecutor> {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        CommandHistory::new("test", CommandRunner::new(executor))
+    }
+
+    fn never_finishing() -> CommandHistory<MockExecutor> {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(|_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(|| Ok(None));
+            Ok(child)
+        });
+        CommandHistory::new("server", CommandRunner::new(executor))
+    }
+
+    /// A child that sets `terminated` when it's terminated. `poll` is told whether it has been.
+    pub(crate) fn terminable(
+        terminated: Arc<AtomicBool>,
+        mut poll: impl FnMut(bool) -> Option<CommandOutput> + Send + 'static,
+    ) -> MockChild {
+        let mut child = MockChild::new();
+        let polled = terminated.clone();
+        child
+            .expect_poll()
+            .returning(move || Ok(poll(polled.load(Ordering::SeqCst))));
+        child.expect_terminate().returning(move || {
+            terminated.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+        child
+    }
+
+    fn start(commands: &mut Commands<MockExecutor>) {
+        commands.request_run(&[]);
+        for _ in 0..5 {
+            commands.tick(|_, _| {}).unwrap();
+        }
+    }
+
+    fn last_states(commands: &Commands<MockExecutor>) -> Vec<&CommandState> {
+        commands
+            .commands()
+            .filter_map(CommandHistory::last)
+            .collect()
+    }
+
+    #[test]
+    fn commands_without_a_phase_stay_apart_from_given_phases() {
+        let options = Options::from_iter(vec![
+            "watchdo", "a", "b", "c", "d", "--phase", "c:2", "--phase", "d:2", "--server", "s",
+            "--phase", "s:2",
+        ]);
+        let commands = Commands::new(&options).unwrap();
+
+        // b would be in phase 2 by its position, but runs on its own before c and d.
+        assert_eq!(commands.phases, vec![1, 2, 3, 3]);
+        assert_eq!(commands.server_phases, vec![2]);
+    }
+
+    #[test]
+    fn resolve_overrides_and_disables_by_given_position() {
+        let mut options = Options::from_iter(vec![
+            "watchdo",
+            "cargo check",
+            "cargo test",
+            "cargo bench",
+            "--server",
+            "cargo run",
+            "--disable",
+            "1",
+            "--override",
+            "2:cargo test --locked",
+            "--override",
+            "server:cargo run --release",
+        ]);
+
+        Commands::resolve(&mut options);
+
+        assert_eq!(options.command, vec!["cargo test --locked", "cargo bench"]);
+        assert_eq!(options.server, vec!["cargo run --release"]);
+    }
+
+    #[test]
+    fn startup_runs_every_passing_test() {
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(true)], vec![]);
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Completed(_)]
+        ));
+    }
+
+    #[test]
+    fn server_never_finishing_does_not_hold_back_tests() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(true), finishing(true)],
+            vec![never_finishing()],
+        );
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Completed(_),
+                CommandState::Completed(_),
+                CommandState::Running
+            ]
+        ));
+    }
+
+    #[test]
+    fn shows_elapsed_time_of_running_commands() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![never_finishing()]);
+        commands.show_elapsed = true;
+        commands.theme.set_ok_glyph("+");
+
+        start(&mut commands);
+
+        let printed =
+            |c: &Commands<MockExecutor>| c.print(4).iter().map(|s| &**s).collect::<String>();
+        assert_eq!(printed(&commands), "   +? 0s");
+        commands.show_elapsed = false;
+        assert_eq!(printed(&commands), "   +   ?");
+    }
+
+    #[test]
+    fn shows_commands_that_were_not_found() {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(|_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(|| {
+                Ok(Some(CommandOutput {
+                    code: Some(127),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let mut commands = Commands::with_commands(
+            vec![CommandHistory::new(
+                "carg test",
+                CommandRunner::new(executor),
+            )],
+            vec![],
+        );
+        commands.theme = Theme::monochrome();
+
+        start(&mut commands);
+
+        let printed = commands.print(1).iter().map(|s| &**s).collect::<String>();
+        assert_eq!(printed, "¿");
+    }
+
+    #[test]
+    fn shows_what_requested_runs_wait_for() {
+        let mut commands =
+            Commands::with_commands(vec![never_finishing(), finishing(true)], vec![]);
+
+        start(&mut commands);
+
+        let printed = commands.print(25).iter().map(|s| &**s).collect::<String>();
+        assert!(printed.ends_with("… waiting for server"));
+        assert_eq!(commands.waiting_for(0), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn shows_test_counts_of_latest_run() {
+        let mut test = finishing(false);
+        test.add_output_check(|output| {
+            output.counts = Some(TestCounts {
+                passed: 3,
+                failed: 1,
+                skipped: 2,
+            })
+        });
+        let mut commands = Commands::with_commands(vec![test], vec![]);
+
+        start(&mut commands);
+
+        let printed = commands.print(17).iter().map(|s| &**s).collect::<String>();
+        assert_eq!(printed, "x 3✓ 1x 2 skipped");
+        assert_eq!(
+            commands.fail_summary(false),
+            "== 1 failing ==\ntest (3 passed, 1 failed, 2 skipped): \n"
+        );
+    }
+
+    #[test]
+    fn terminates_tests_running_past_timeout() {
+        let terminated = Arc::new(AtomicBool::new(false));
+        let mut executor = MockExecutor::new();
+        let on_terminate = terminated.clone();
+        executor.expect_start().return_once(move |_| {
+            Ok(terminable(on_terminate, |terminated| {
+                terminated.then(|| CommandOutput {
+                    success: false,
+                    ..CommandOutput::default()
+                })
+            }))
+        });
+        let stuck = CommandHistory::new("stuck", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![stuck], vec![]);
+        commands.timeout = Some(Duration::ZERO);
+
+        start(&mut commands);
+
+        assert!(terminated.load(Ordering::SeqCst));
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Terminated(output, Termination::Limit)] if output.err.contains("running for longer than")
+        ));
+    }
+
+    #[test]
+    fn terminates_tests_that_stop_writing_output() {
+        let terminated = Arc::new(AtomicBool::new(false));
+        let mut executor = MockExecutor::new();
+        let on_terminate = terminated.clone();
+        executor.expect_start().return_once(move |_| {
+            let mut child = terminable(on_terminate, |terminated| {
+                terminated.then(CommandOutput::default)
+            });
+            let wrote = Instant::now();
+            child.expect_last_output().returning(move || Some(wrote));
+            Ok(child)
+        });
+        let quiet = CommandHistory::new("quiet", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![quiet], vec![]);
+        commands.inactivity_timeout = Some(Duration::from_millis(30));
+
+        start(&mut commands);
+        assert!(!terminated.load(Ordering::SeqCst));
+        std::thread::sleep(Duration::from_millis(40));
+        for _ in 0..5 {
+            commands.tick(|_, _| {}).unwrap();
+        }
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Terminated(output, Termination::Limit)] if output.err.contains("no output for longer than")
+        ));
+    }
+
+    #[test]
+    fn full_run_ignores_impact_map() {
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(true)], vec![]);
+        commands.impact = Some(ImpactMap::parse("docs/* 2", Fallback::None).unwrap());
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        assert!(last_states(&commands).is_empty());
+
+        commands.request_full_run();
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Requested]
+        ));
+    }
+
+    #[test]
+    fn own_output_does_not_trigger_a_run() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.debounce = Duration::ZERO;
+        commands.command_outputs = vec!["1:target/**".parse().unwrap()];
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("target/out")]);
+        assert!(!commands.tests[0].has_outstanding_request());
+
+        commands.request_run(&[PathBuf::from("target/out"), PathBuf::from("src/main.rs")]);
+        assert!(commands.tests[0].has_outstanding_request());
+    }
+
+    #[test]
+    fn other_commands_output_triggers_a_run() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.debounce = Duration::ZERO;
+        commands.command_outputs = vec!["2:target/**".parse().unwrap()];
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("target/out")]);
+
+        assert!(commands.tests[0].has_outstanding_request());
+    }
+
+    #[test]
+    fn suppress_only_ignores_what_the_finished_command_wrote() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.debounce = Duration::ZERO;
+        commands.loop_guard = Some(LoopGuard::Suppress);
+        commands.loop_guard_window = Duration::from_secs(60);
+        commands.command_outputs = vec!["1:target/**".parse().unwrap()];
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("target/out")]);
+        assert!(!commands.tests[0].has_outstanding_request());
+
+        commands.request_run(&[PathBuf::from("target/out"), PathBuf::from("src/main.rs")]);
+
+        assert_eq!(commands.last_trigger().1, [PathBuf::from("src/main.rs")]);
+        assert!(commands.tests[0].has_outstanding_request());
+    }
+
+    #[test]
+    fn changes_within_the_debounce_run_once_it_is_over() {
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(true)], vec![]);
+        commands.impact = Some(ImpactMap::parse("src/* 1\ndocs/* 2", Fallback::None).unwrap());
+        commands.debounce = Duration::from_millis(50);
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        commands.request_run(&[PathBuf::from("docs/README.md")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested]
+        ));
+
+        std::thread::sleep(Duration::from_millis(60));
+        commands.tick(|_, _| {}).unwrap();
+        assert_eq!(commands.last_trigger().1, [PathBuf::from("docs/README.md")]);
+        assert!(commands.tests[1].iter().next().is_some());
+    }
+
+    #[test]
+    fn servers_wait_for_tests_in_earlier_phases() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(true), finishing(false)],
+            vec![never_finishing(), never_finishing()],
+        );
+        commands.server_phases = vec![2, usize::MAX];
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Completed(_),
+                CommandState::Completed(_),
+                CommandState::Running,
+                CommandState::Requested
+            ]
+        ));
+    }
+
+    #[test]
+    fn restart_filter_leaves_server_running() {
+        let with_filter = || {
+            let mut commands =
+                Commands::with_commands(vec![finishing(true)], vec![never_finishing()]);
+            commands.restart_filters = vec!["server:backend/**".parse().unwrap()];
+            commands
+        };
+
+        let mut commands = with_filter();
+        commands.request_run(&[PathBuf::from("frontend/app.js")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested]
+        ));
+
+        let mut commands = with_filter();
+        commands.request_run(&[PathBuf::from("backend/main.rs")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Requested]
+        ));
+    }
+
+    #[test]
+    fn sequential_runs_one_test_at_a_time_through_failures() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(false), never_finishing(), finishing(true)],
+            vec![],
+        );
+        commands.phases = vec![1, 2, 2];
+        commands.sequential = true;
+        commands.run_after_failure = true;
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Completed(_),
+                CommandState::Running,
+                CommandState::Requested
+            ]
+        ));
+        assert_eq!(commands.waiting_for(2), vec!["server"]);
+    }
+
+    #[test]
+    fn success_cooldown_holds_back_only_that_command() {
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(true)], vec![]);
+        commands.success_cooldowns = vec!["1:1h".parse().unwrap()];
+        commands.debounce = Duration::ZERO;
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Requested]
+        ));
+
+        commands.request_full_run();
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Requested]
+        ));
+    }
+
+    #[test]
+    fn changes_during_a_success_cooldown_run_once_it_ends() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.success_cooldowns = vec!["1:100ms".parse().unwrap()];
+        commands.debounce = Duration::ZERO;
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        commands.tick(|_, _| {}).unwrap();
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_)]
+        ));
+
+        std::thread::sleep(Duration::from_millis(150));
+        commands.tick(|_, _| {}).unwrap();
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested | CommandState::Running]
+        ));
+    }
+
+    #[test]
+    fn server_config_changes_reload_instead_of_restarting() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![never_finishing()]);
+        commands.server_config = vec![Glob::new("*.env").unwrap().compile_matcher()];
+        commands.config_reload = Some(finishing(true));
+        commands.debounce = Duration::ZERO;
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("app.env")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Running]
+        ));
+        commands.tick(|_, _| {}).unwrap();
+        commands.tick(|_, _| {}).unwrap();
+        let reload = commands.config_reload.as_ref().unwrap();
+        assert!(matches!(reload.last(), Some(CommandState::Completed(_))));
+
+        commands.request_run(&[PathBuf::from("app.env"), PathBuf::from("src/main.rs")]);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Requested]
+        ));
+    }
+
+    #[test]
+    fn persistent_servers_keep_running_through_restarts() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![never_finishing()]);
+        commands.servers[0].set_persistent();
+        commands.debounce = Duration::ZERO;
+        start(&mut commands);
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        commands.control(Control::RestartServers).unwrap();
+        commands.control(Control::ToggleServer).unwrap();
+        commands.request_full_run();
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested, CommandState::Running]
+        ));
+        assert_eq!(
+            commands.explain(Path::new("src/main.rs"))[1],
+            (
+                "server",
+                Err("it's --persistent and already started".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn requests_selected_commands() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(true), finishing(true)],
+            vec![never_finishing()],
+        );
+
+        assert!(commands.request_selected("2"));
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Requested]
+        ));
+        assert!(commands.request_selected("server"));
+        assert_eq!(last_states(&commands).len(), 2);
+        assert!(!commands.request_selected("3"));
+    }
+
+    #[test]
+    fn on_green_runs_once_per_green_edge() {
+        let dir = TestDir::new();
+        let path = dir.join("greens");
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.on_green = vec![format!("echo \"$WATCHDO_PASSING\" >> {}", path.display())];
+        commands.debounce = Duration::ZERO;
+
+        start(&mut commands);
+        start(&mut commands);
+        let started = Instant::now();
+        let greens = loop {
+            let greens = std::fs::read_to_string(&path).unwrap_or_default();
+            if greens.lines().count() >= 2 || started.elapsed() > Duration::from_secs(5) {
+                break greens;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(greens, "test\ntest\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), greens);
+    }
+
+    #[test]
+    fn recent_events_show_under_the_commands() {
+        let mut commands = Commands::with_commands(vec![finishing(false)], vec![]);
+        commands.recent_events = Some(RecentEvents::new(3, Duration::from_secs(60)));
+        assert_eq!(commands.print(40).len(), 40);
+
+        start(&mut commands);
+        let printed = commands.print(40);
+        assert_eq!(printed.len(), 41);
+        assert_eq!(printed[40].chars().count(), 40);
+        assert!(printed[40].trim_end().ends_with(" test failed"));
+    }
+
+    #[test]
+    fn run_if_waits_for_the_prerequisites_exit_code() {
+        let exiting = |code| {
+            let mut executor = MockExecutor::new();
+            executor.expect_start().returning(move |_| {
+                let mut child = MockChild::new();
+                child.expect_poll().returning(move || {
+                    Ok(Some(CommandOutput {
+                        code: Some(code),
+                        ..CommandOutput::default()
+                    }))
+                });
+                Ok(child)
+            });
+            CommandHistory::new("check", CommandRunner::new(executor))
+        };
+        let mut commands =
+            Commands::with_commands(vec![exiting(2), finishing(true), finishing(true)], vec![]);
+        commands.gates = vec![None, Some((0, 2)), Some((0, 3))];
+
+        commands.request_run(&[]);
+        assert_eq!(commands.waiting_for(1), ["check"]);
+        start(&mut commands);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Completed(_)]
+        ));
+        assert!(commands.tests[1].is_passing());
+    }
+
+    #[test]
+    fn idle_until_the_first_change() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.theme = Theme::monochrome();
+        commands.idle_start = true;
+        let printed = |commands: &Commands<_>| {
+            commands
+                .print(3)
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<String>()
+        };
+        assert_eq!(printed(&commands), "  -");
+
+        commands.request_run(&[PathBuf::from("src/main.rs")]);
+        assert_eq!(printed(&commands), "  .");
+    }
+
+    #[test]
+    fn explains_impact_rules() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(true), finishing(true)],
+            vec![never_finishing()],
+        );
+        commands.impact = Some(ImpactMap::parse("src/** 1", Fallback::None).unwrap());
+
+        assert_eq!(
+            commands.explain(Path::new("src/main.rs")),
+            vec![
+                ("test", Ok("impact rule src/** selects it".to_string())),
+                (
+                    "test",
+                    Err("no matching impact rule selects it".to_string())
+                ),
+                (
+                    "server",
+                    Err("no matching impact rule selects it".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            commands.explain(Path::new("README.md"))[0],
+            (
+                "test",
+                Err("no impact rule matches, falling back to none".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn settles_once_no_test_is_running() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(false), finishing(true)],
+            vec![never_finishing()],
+        );
+        start(&mut commands);
+        assert!(commands.is_settled());
+
+        let mut commands = Commands::with_commands(vec![never_finishing()], vec![]);
+        start(&mut commands);
+        assert!(!commands.is_settled());
+    }
+
+    #[test]
+    fn primary_command_decides_aggregate() {
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(false)], vec![]);
+        commands.run_after_failure = true;
+        start(&mut commands);
+        assert_eq!(commands.aggregate(), Aggregate::Failing);
+
+        commands.primary = Some("1".to_string());
+        assert_eq!(commands.aggregate(), Aggregate::Passing);
+    }
+
+    #[test]
+    fn presets_switch_the_active_commands() {
+        let terminated = Arc::new(AtomicBool::new(false));
+        let on_terminate = terminated.clone();
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_start()
+            .returning(move |_| Ok(terminable(on_terminate.clone(), |_| None)));
+        let server = CommandHistory::new("server", CommandRunner::new(executor));
+        let mut commands =
+            Commands::with_commands(vec![finishing(true), finishing(false)], vec![server]);
+        commands.run_after_failure = true;
+        commands.presets = ["api:1", "api:server", "docs:1"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        start(&mut commands);
+        assert!(matches!(
+            commands.servers[0].last(),
+            Some(CommandState::Requested)
+        ));
+
+        // The failing test is left out, so it doesn't hold the server back anymore.
+        commands
+            .control(Control::SwitchPreset(Some("api".to_string())))
+            .unwrap();
+        commands.tick(|_, _| {}).unwrap();
+        assert_eq!(commands.aggregate(), Aggregate::Passing);
+        assert!(matches!(
+            commands.servers[0].last(),
+            Some(CommandState::Running)
+        ));
+
+        commands
+            .control(Control::SwitchPreset(Some("docs".to_string())))
+            .unwrap();
+        assert!(terminated.load(Ordering::SeqCst));
+        commands.request_run(&[]);
+        commands.tick(|_, _| {}).unwrap();
+        assert!(!commands.servers[0].has_outstanding_request());
+
+        commands.control(Control::SwitchPreset(None)).unwrap();
+        assert!(commands.tests[1].has_outstanding_request());
+        assert!(commands.servers[0].has_outstanding_request());
+    }
+
+    #[test]
+    fn logs_each_command_to_its_own_file() {
+        let dir = TestDir::new();
+        let log = |selector: &str| CommandLog {
+            selector: selector.to_string(),
+            path: dir.join(format!("{}.log", selector)),
+        };
+        let mut commands = Commands::with_commands(vec![finishing(true), finishing(false)], vec![]);
+        commands.run_after_failure = true;
+        commands.command_logs = vec![log("1"), log("2")];
+        for log in &commands.command_logs {
+            log.start(false).unwrap();
+        }
+
+        start(&mut commands);
+
+        let first = std::fs::read_to_string(&log("1").path).unwrap();
+        let second = std::fs::read_to_string(&log("2").path).unwrap();
+        assert!(first.contains(" test passed in "), "{:?}", first);
+        assert!(second.contains(" test failed in "), "{:?}", second);
+    }
+
+    #[test]
+    fn unwritable_log_does_not_stop_the_watch() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+        commands.command_logs = vec![CommandLog {
+            selector: "1".to_string(),
+            path: PathBuf::from("/nonexistent/watchdo.log"),
+        }];
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands)[..],
+            [CommandState::Completed(_)]
+        ));
+    }
+
+    #[test]
+    fn shuffle_reorders_tests_without_a_phase() {
+        let tests = (0..4).map(|_| finishing(true)).collect();
+        let mut commands = Commands::with_commands(tests, vec![]);
+        // The unpinned tests' phases don't match their positions, like after a given phase.
+        commands.phases = vec![1, 3, 4, 5];
+        commands.pinned_phases = vec![false, true, false, false];
+        commands.shuffle = Some(Shuffle::new(0));
+
+        let mut orders = Vec::new();
+        for _ in 0..10 {
+            commands.request_full_run();
+            let mut unpinned = vec![commands.phases[0], commands.phases[2], commands.phases[3]];
+            assert_eq!(commands.phases[1], 3);
+            orders.push(unpinned.clone());
+            unpinned.sort_unstable();
+            assert_eq!(unpinned, vec![1, 4, 5]);
+        }
+        assert!(orders.iter().any(|o| *o != vec![1, 4, 5]));
+    }
+
+    #[test]
+    fn max_children_holds_back_runs() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let never_finishing = || {
+            let started = started.clone();
+            let mut executor = MockExecutor::new();
+            executor.expect_start().returning(move |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+                let mut child = MockChild::new();
+                child.expect_poll().returning(|| Ok(None));
+                Ok(child)
+            });
+            CommandHistory::new("test", CommandRunner::new(executor))
+        };
+        let tests = (0..3).map(|_| never_finishing()).collect();
+        let mut commands = Commands::with_commands(tests, vec![]);
+        commands.phases = vec![1, 1, 1];
+        commands.max_children = 2;
+
+        start(&mut commands);
+
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Running,
+                CommandState::Running,
+                CommandState::Requested
+            ]
+        ));
+    }
+
+    #[test]
+    fn pinned_output_outlasts_passing_runs() {
+        let mut runs = 0;
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            runs += 1;
+            let success = runs > 1;
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    out: format!("run {}\n", runs),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let test = CommandHistory::new("test", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![test], vec![]);
+
+        commands.control(Control::Pin(1)).unwrap();
+        assert_eq!(commands.pinned_output(), Some(("test", None)));
+        start(&mut commands);
+        assert_eq!(commands.pinned_output(), Some(("test", Some("run 1\n"))));
+
+        std::thread::sleep(Duration::from_millis(150));
+        start(&mut commands);
+        assert!(commands.tests[0].is_passing());
+        assert_eq!(commands.pinned_output(), Some(("test", Some("run 1\n"))));
+
+        commands.control(Control::Unpin).unwrap();
+        assert_eq!(commands.pinned_output(), None);
+    }
+
+    #[test]
+    fn collapsed_output_expands_on_request() {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(|_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(|| {
+                Ok(Some(CommandOutput {
+                    out: "a\nb\n".to_string(),
+                    err: "c\n".to_string(),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let test = CommandHistory::new("test", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![test], vec![]);
+        commands.collapse_output = true;
+
+        let mut printed = Vec::new();
+        let mut tick = |commands: &mut Commands<MockExecutor>| {
+            for _ in 0..5 {
+                commands
+                    .tick(|name, output| printed.push(format!("{}: {}", name, output.out)))
+                    .unwrap();
+            }
+        };
+        commands.request_run(&[]);
+        tick(&mut commands);
+        assert_eq!(
+            collapsed_output(1, "test", commands.tests[0].last_output().unwrap()),
+            "watchdo: test failed, 3 lines of output, e1 to expand"
+        );
+
+        commands.control(Control::Expand(1)).unwrap();
+        tick(&mut commands);
+        commands.control(Control::Expand(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        commands.request_run(&[]);
+        tick(&mut commands);
+        assert_eq!(commands.tests[0].iter().count(), 2);
+        assert_eq!(printed, vec!["test: a\nb\n"]);
+    }
+
+    fn with_canary(
+        canary: CommandHistory<MockExecutor>,
+        test: CommandHistory<MockExecutor>,
+    ) -> Commands<MockExecutor> {
+        let mut commands = Commands::with_commands(vec![test, canary], vec![]);
+        commands.phases = vec![1, 0];
+        commands.pinned_phases = vec![false, true];
+        commands.canary = Some(1);
+        commands.run_after_failure = true;
+        commands
+    }
+
+    #[test]
+    fn failing_canary_skips_the_others() {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().never();
+        let test = CommandHistory::new("test", CommandRunner::new(executor));
+        let mut commands = with_canary(finishing(false), test);
+
+        start(&mut commands);
+
+        assert!(commands.tests[0].last().is_none());
+        assert!(!commands.tests[1].is_passing());
+    }
+
+    #[test]
+    fn passing_canary_runs_the_others() {
+        let mut commands = with_canary(finishing(true), finishing(true));
+
+        start(&mut commands);
+
+        assert!(commands.tests.iter().all(CommandHistory::is_passing));
+    }
+
+    #[test]
+    fn paused_runs_wait_until_resumed() {
+        let mut commands = Commands::with_commands(vec![finishing(true)], vec![]);
+
+        commands.control(Control::Pause).unwrap();
+        start(&mut commands);
+        assert!(last_states(&commands).is_empty());
+        assert_eq!(commands.status().get("paused"), Some(&Json::Bool(true)));
+
+        commands.control(Control::Resume).unwrap();
+        for _ in 0..5 {
+            commands.tick(|_, _| {}).unwrap();
+        }
+        assert!(commands.tests[0].is_passing());
+    }
+
+    #[test]
+    fn tasks_only_run_when_asked_for() {
+        let mut commands = Commands::with_commands(vec![], vec![]);
+        commands.tasks = vec![finishing(true)];
+        let mut shown = Vec::new();
+
+        start(&mut commands);
+        commands
+            .control(Control::RunTask("seed".to_string()))
+            .unwrap();
+        for _ in 0..5 {
+            commands
+                .tick(|name, _| shown.push(name.to_string()))
+                .unwrap();
+        }
+        assert!(shown.is_empty());
+
+        commands
+            .control(Control::RunTask("test".to_string()))
+            .unwrap();
+        for _ in 0..5 {
+            commands
+                .tick(|name, _| shown.push(name.to_string()))
+                .unwrap();
+        }
+        assert_eq!(shown, vec!["test"]);
+    }
+
+    #[test]
+    fn every_started_child_is_reaped() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let reaped = Arc::new(AtomicUsize::new(0));
+        let counting = || {
+            let (started, reaped) = (started.clone(), reaped.clone());
+            let mut executor = MockExecutor::new();
+            executor.expect_start().returning(move |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+                let reaped = reaped.clone();
+                let mut polls = 0;
+                let mut child = terminable(Arc::new(AtomicBool::new(false)), move |terminated| {
+                    polls += 1;
+                    if polls < 3 && !terminated {
+                        return None;
+                    }
+                    reaped.fetch_add(1, Ordering::SeqCst);
+                    Some(CommandOutput {
+                        success: true,
+                        ..CommandOutput::default()
+                    })
+                });
+                child.expect_memory_usage().returning(|| Ok(None));
+                Ok(child)
+            });
+            CommandHistory::new("test", CommandRunner::new(executor))
+        };
+        let mut commands = Commands::with_commands(vec![counting(), counting()], vec![counting()]);
+        commands.debounce = Duration::ZERO;
+
+        for _ in 0..5 {
+            commands.request_run(&[]);
+            commands.tick(|_, _| {}).unwrap();
+        }
+        for _ in 0..10 {
+            commands.tick(|_, _| {}).unwrap();
+        }
+
+        assert!(started.load(Ordering::SeqCst) > 3);
+        assert_eq!(
+            reaped.load(Ordering::SeqCst),
+            started.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn oneline_counts_states() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(true), finishing(false), never_finishing()],
+            vec![],
+        );
+        commands.run_after_failure = true;
+        start(&mut commands);
+
+        assert_eq!(commands.oneline(), "watchdo: 1✓ 1x 1?");
+    }
+
+    #[test]
+    fn failing_test_holds_back_later_tests() {
+        let mut commands = Commands::with_commands(
+            vec![finishing(false), finishing(true)],
+            vec![never_finishing()],
+        );
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Completed(_),
+                CommandState::Requested,
+                CommandState::Requested
+            ]
+        ));
+    }
+
+    #[test]
+    fn expected_failure_lets_later_tests_run() {
+        let mut expected = finishing(false);
+        expected.set_expect_failure(true);
+        let mut commands = Commands::with_commands(vec![expected, finishing(true)], vec![]);
+        commands.theme = Theme::monochrome();
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Completed(_)]
+        ));
+        let printed = commands.print(1).iter().map(|s| &**s).collect::<String>();
+        assert_eq!(printed, "-✓");
+    }
+
+    #[test]
+    fn unexpected_pass_holds_back_later_tests() {
+        let mut expected = finishing(true);
+        expected.set_expect_failure(true);
+        let mut commands = Commands::with_commands(vec![expected, finishing(true)], vec![]);
+        commands.theme = Theme::monochrome();
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Requested]
+        ));
+        let printed = commands.print(1).iter().map(|s| &**s).collect::<String>();
+        assert_eq!(printed, "+t");
+    }
+
+    #[test]
+    fn phase_runs_together_and_holds_back_later_phases() {
+        let mut commands = Commands::with_commands(
+            vec![never_finishing(), finishing(true), finishing(true)],
+            vec![],
+        );
+        commands.phases = vec![1, 1, 2];
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [
+                CommandState::Running,
+                CommandState::Completed(_),
+                CommandState::Requested
+            ]
+        ));
+    }
+
+    #[test]
+    fn run_after_failure_starts_next_phase() {
+        let mut commands = Commands::with_commands(vec![finishing(false), finishing(true)], vec![]);
+        commands.run_after_failure = true;
+
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Completed(_)]
+        ));
+    }
+
+    #[test]
+    fn fail_summary_lists_failing_commands() {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(|_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(|| {
+                Ok(Some(CommandOutput {
+                    success: false,
+                    out: "\nfirst\nsecond\n".to_string(),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let failing = CommandHistory::new("failing", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![failing], vec![never_finishing()]);
+        commands.run_after_failure = true;
+
+        start(&mut commands);
+
+        assert_eq!(
+            commands.fail_summary(false),
+            "== 1 failing ==\nfailing: first\n"
+        );
+        assert_eq!(
+            commands.fail_summary(true),
+            "== 1 failing ==\n-- failing --\n\nfirst\nsecond\n"
+        );
+    }
+
+    #[test]
+    fn held_back_test_runs_once_earlier_test_passes() {
+        let mut executor = MockExecutor::new();
+        let mut results = vec![false, true].into_iter();
+        executor.expect_start().returning(move |_| {
+            let success = results.next().unwrap();
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let first = CommandHistory::new("first", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![first, finishing(true)], vec![]);
+        start(&mut commands);
+
+        // Past the debounce, like a later change would be.
+        commands.last_request = None;
+        start(&mut commands);
+
+        assert!(matches!(
+            last_states(&commands).as_slice(),
+            [CommandState::Completed(_), CommandState::Completed(_)]
+        ));
+    }
+
+    #[test]
+    fn normalize_ignores_trailing_whitespace_and_blank_lines() {
+        assert_eq!(normalize("a  \n\n\tb\t\r\n\n"), "a\n\tb");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn printed_outputs_skip_repeats_that_only_differ_in_whitespace() {
+        let output = |out: &str| CommandOutput {
+            out: out.to_string(),
+            ..CommandOutput::default()
+        };
+        let mut printed = PrintedOutputs::new(true);
+
+        assert!(printed.is_new(0, &output("ok\n")));
+        assert!(!printed.is_new(0, &output("ok  \n\n")));
+        assert!(printed.is_new(1, &output("ok\n")));
+        assert!(printed.is_new(0, &output("failed\n")));
+        assert!(printed.is_new(0, &output("ok\n")));
+    }
+
+    #[test]
+    fn same_failure_after_a_pass_is_printed_again() {
+        let mut runs = 0;
+        let mut executor = MockExecutor::new();
+        executor.expect_start().returning(move |_| {
+            runs += 1;
+            let success = runs == 2;
+            let mut child = MockChild::new();
+            child.expect_poll().returning(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    out: if success { "ok\n" } else { "broken\n" }.to_string(),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        let test = CommandHistory::new("test", CommandRunner::new(executor));
+        let mut commands = Commands::with_commands(vec![test], vec![]);
+        commands.printed = PrintedOutputs::new(true);
+        commands.debounce = Duration::ZERO;
+
+        let mut printed = Vec::new();
+        for _ in 0..3 {
+            commands.request_run(&[]);
+            for _ in 0..5 {
+                commands
+                    .tick(|_, output| printed.push(output.out.clone()))
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(commands.tests[0].iter().count(), 3);
+        assert_eq!(printed, vec!["broken\n", "broken\n"]);
+    }
+
+    #[test]
+    fn printed_outputs_tell_stdout_from_stderr() {
+        let mut printed = PrintedOutputs::new(true);
+
+        assert!(printed.is_new(
+            0,
+            &CommandOutput {
+                out: "a".to_string(),
+                ..CommandOutput::default()
+            }
+        ));
+        assert!(printed.is_new(
+            0,
+            &CommandOutput {
+                err: "a".to_string(),
+                ..CommandOutput::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn printed_outputs_are_all_new_without_collapsing() {
+        let output = CommandOutput::default();
+        let mut printed = PrintedOutputs::new(false);
+
+        assert!(printed.is_new(0, &output));
+        assert!(printed.is_new(0, &output));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::args::{split_selector, Colons};
+use crate::*;
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Reads arguments from a config file, as if they had been given before the command line ones.
+///
+/// Each non-empty line is an option with its value, like `--ignore target/`, or a command to
+/// run. Lines starting with `#` are comments. Lines after a `[<profile>]` header are only read
+/// when that profile is selected, after the lines outside any profile.
+pub fn load_args(path: &Path, profile: Option<&str>) -> Result<Vec<OsString>, Box<dyn Error>> {
+    parse_args(&std::fs::read_to_string(path)?, profile)
+}
+
+pub fn parse_args(contents: &str, profile: Option<&str>) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let mut args = Vec::new();
+    let mut profile_args = Vec::new();
+    let mut section = None;
+    let mut found = false;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.trim().to_string());
+            found |= section.as_deref() == profile;
+            continue;
+        }
+        let args = match &section {
+            None => &mut args,
+            Some(name) if Some(name.as_str()) == profile => &mut profile_args,
+            Some(_) => continue,
+        };
+
+        match line.find(char::is_whitespace) {
+            Some(split) if line.starts_with('-') => {
+                args.push(line[..split].into());
+                args.push(line[split..].trim().into());
+            }
+            _ => args.push(line.into()),
+        }
+    }
+    if let (Some(profile), false) = (profile, found) {
+        return Err(format!("no [{}] profile in the config file", profile).into());
+    }
+    args.append(&mut profile_args);
+    Ok(args)
+}
+
+// Options that are already applied to the commands, or only say where to read more arguments.
+const NOT_DUMPED: &[&str] = &[
+    "config",
+    "profile",
+    "dump-config",
+    "commands-stdin",
+    "override",
+    "disable",
+    "server",
+    "command",
+];
+
+/// The arguments `options` were parsed from, as a config file with one option per line, in the
+/// order first given. The commands and servers are the ones left after --override and --disable.
+pub fn dump_args(args: &[OsString], options: &Options) -> String {
+    let matches = Options::clap().get_matches_from(args);
+    // Defaults aren't given, so they're left for whichever watchdo reads the file.
+    let mut given = matches
+        .args
+        .iter()
+        .filter(|(name, arg)| arg.occurs > 0 && !NOT_DUMPED.contains(name))
+        .collect::<Vec<_>>();
+    given.sort_by_key(|(_, arg)| arg.indices.first().copied());
+
+    let mut dump = String::new();
+    for (name, arg) in given {
+        if arg.vals.is_empty() {
+            dump += &format!("--{}\n", name);
+        }
+        for value in &arg.vals {
+            dump += &format!("--{} {}\n", name, value.to_string_lossy());
+        }
+    }
+    for server in &options.server {
+        dump += &format!("--server {}\n", server.to_string_lossy());
+    }
+    for command in &options.command {
+        dump += &format!("{}\n", command.to_string_lossy());
+    }
+    dump
+}
+
+/// A different command to run in place of one, as `<command>:<new command>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOverride {
+    pub selector: String,
+    pub command: OsString,
+}
+
+impl FromStr for CommandOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, command) = split_selector(s, "<command>:<new command>", Colons::Right)?;
+        Ok(CommandOverride {
+            selector: selector.to_string(),
+            command: command.trim().into(),
+        })
+    }
+}
+
+/// Notices when a file is modified, by polling its modification time.
+pub struct FileChanges {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileChanges {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        FileChanges {
+            modified: modified(&path),
+            path,
+        }
+    }
+
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Replaces this process with a fresh run of watchdo with the same arguments. Only returns if
+/// that fails.
+#[cfg(unix)]
+pub fn reexec() -> Box<dyn Error> {
+    use std::os::unix::process::CommandExt;
+
+    match std::env::current_exe() {
+        Ok(exe) => std::process::Command::new(exe)
+            .args(std::env::args_os().skip(1))
+            .exec()
+            .into(),
+        Err(e) => e.into(),
+    }
+}
+
+/// Starts a fresh run of watchdo with the same arguments and exits once it does. Only returns if
+/// that fails.
+#[cfg(not(unix))]
+pub fn reexec() -> Box<dyn Error> {
+    let status = std::env::current_exe().and_then(|exe| {
+        std::process::Command::new(exe)
+            .args(std::env::args_os().skip(1))
+            .status()
+    });
+    match status {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn options_and_commands() {
+        let args = parse_args(
+            "# comment\n--ignore target/\n\n--bell\ncargo test --all\n",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            vec!["--ignore", "target/", "--bell", "cargo test --all"]
+        );
+    }
+
+    #[test]
+    fn selected_profile_comes_after_the_rest() {
+        let contents = "--bell\n[ci]\n--env CI=1\ncargo test --locked\n[dev]\n--panes\n\
+                        [ ci ]\n--override 1:cargo test --all\n";
+
+        assert_eq!(parse_args(contents, None).unwrap(), vec!["--bell"]);
+        assert_eq!(
+            parse_args(contents, Some("ci")).unwrap(),
+            vec![
+                "--bell",
+                "--env",
+                "CI=1",
+                "cargo test --locked",
+                "--override",
+                "1:cargo test --all"
+            ]
+        );
+        assert!(parse_args(contents, Some("release")).is_err());
+    }
+
+    #[test]
+    fn dumps_what_was_given_and_resolved() {
+        let args = parse_args(
+            "--bell\ncargo check\ncargo test\n[ci]\n--disable 1\n--env CI=1\n",
+            Some("ci"),
+        )
+        .unwrap();
+        let args = std::iter::once("watchdo".into())
+            .chain(args)
+            .chain(vec![
+                "--env".into(),
+                "RUST_LOG=debug".into(),
+                "--panes".into(),
+            ])
+            .collect::<Vec<OsString>>();
+        let mut options = Options::from_iter(&args);
+        Commands::resolve(&mut options);
+
+        let dump = dump_args(&args, &options);
+        assert_eq!(
+            dump,
+            "--bell\n--env CI=1\n--env RUST_LOG=debug\n--panes\ncargo test\n"
+        );
+        let reloaded = parse_args(&dump, None).unwrap();
+        let reloaded = Options::from_iter(std::iter::once("watchdo".into()).chain(reloaded));
+        assert_eq!(reloaded.command, options.command);
+    }
+
+    #[test]
+    fn override_splits_at_first_colon() {
+        assert_eq!(
+            "server:curl http://localhost:8080".parse(),
+            Ok(CommandOverride {
+                selector: "server".to_string(),
+                command: "curl http://localhost:8080".into(),
+            })
+        );
+        assert!("cargo test".parse::<CommandOverride>().is_err());
+    }
+
+    #[test]
+    fn notices_modification() {
+        let dir = TestDir::new();
+        let path = dir.join("config");
+        std::fs::write(&path, "--bell").unwrap();
+        let mut changes = FileChanges::new(&path);
+
+        assert!(!changes.changed());
+        std::fs::remove_file(&path).unwrap();
+        assert!(changes.changed());
+        assert!(!changes.changed());
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::BufRead;
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver};
+
+/// Something the user asked watchdo to do while it's running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    ToggleServer,
+    ClearHistory,
+    UpdateGolden,
+    FailSummary,
+    RunAll,
+    /// Hold runs back until resumed.
+    Pause,
+    Resume,
+    RestartServers,
+    /// Keep a command's latest failing output on screen, by its position in the status bar.
+    Pin(usize),
+    Unpin,
+    /// Show a command's output in full, or collapse it again, by its position in the status bar.
+    Expand(usize),
+    /// Run a --task by its name.
+    RunTask(String),
+    /// Only keep the commands of a --preset active, or every command with `None`.
+    SwitchPreset(Option<String>),
+    SelectPane(usize),
+    NextPane,
+    PrevPane,
+    ScrollUp,
+    ScrollDown,
+}
+
+impl FromStr for Control {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "s" => Ok(Control::ToggleServer),
+            "c" => Ok(Control::ClearHistory),
+            "u" => Ok(Control::UpdateGolden),
+            "f" => Ok(Control::FailSummary),
+            "R" => Ok(Control::RunAll),
+            "h" => Ok(Control::Pause),
+            "g" => Ok(Control::Resume),
+            "r" => Ok(Control::RestartServers),
+            "P" => Ok(Control::Unpin),
+            ":preset" => Ok(Control::SwitchPreset(None)),
+            p if p.starts_with(":preset ") => {
+                Ok(Control::SwitchPreset(Some(p[8..].trim().to_string())))
+            }
+            t if t.len() > 1 && t.starts_with(':') => Ok(Control::RunTask(t[1..].to_string())),
+            p if p
+                .strip_prefix('P')
+                .is_some_and(|n| n.parse::<usize>().is_ok()) =>
+            {
+                Ok(Control::Pin(p[1..].parse().unwrap()))
+            }
+            e if e
+                .strip_prefix('e')
+                .is_some_and(|n| n.parse::<usize>().is_ok()) =>
+            {
+                Ok(Control::Expand(e[1..].parse().unwrap()))
+            }
+            "n" => Ok(Control::NextPane),
+            "p" => Ok(Control::PrevPane),
+            "k" => Ok(Control::ScrollUp),
+            "j" => Ok(Control::ScrollDown),
+            n if n.parse::<usize>().is_ok() => Ok(Control::SelectPane(n.parse().unwrap())),
+            other => Err(format!("unknown command {:?}", other)),
+        }
+    }
+}
+
+impl Control {
+    /// The control a --rpc method asks for, if it's one of them.
+    pub fn from_method(method: &str) -> Option<Control> {
+        match method {
+            "run" => Some(Control::RunAll),
+            "pause" => Some(Control::Pause),
+            "resume" => Some(Control::Resume),
+            "restart_server" => Some(Control::RestartServers),
+            "toggle_server" => Some(Control::ToggleServer),
+            "clear_history" => Some(Control::ClearHistory),
+            "update_golden" => Some(Control::UpdateGolden),
+            _ => None,
+        }
+    }
+}
+
+/// Where interactive commands are typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlInput {
+    Stdin,
+    Tty,
+    /// A command reads the terminal, so anything typed is for it.
+    Off,
+}
+
+impl ControlInput {
+    /// `stdin_taken` when stdin carries commands or triggers rather than keys, `inherited` when
+    /// a command is given watchdo's stdin.
+    pub fn choose(stdin_taken: bool, inherited: bool) -> ControlInput {
+        match (stdin_taken, inherited) {
+            (_, true) => ControlInput::Off,
+            (true, false) => ControlInput::Tty,
+            (false, false) => ControlInput::Stdin,
+        }
+    }
+
+    pub fn read(self) -> Receiver<Result<Control, String>> {
+        match self {
+            ControlInput::Stdin => read_stdin(),
+            ControlInput::Tty => read_tty(),
+            ControlInput::Off => channel().1,
+        }
+    }
+}
+
+/// Reads controls from stdin, one per line. Stdin is line buffered, so each key needs Enter.
+fn read_stdin() -> Receiver<Result<Control, String>> {
+    read_controls(std::io::BufReader::new(std::io::stdin()))
+}
+
+/// Reads controls from the terminal, for when stdin is used for something else.
+fn read_tty() -> Receiver<Result<Control, String>> {
+    match std::fs::File::open("/dev/tty") {
+        Ok(tty) => read_controls(std::io::BufReader::new(tty)),
+        Err(_) => channel().1,
+    }
+}
+
+fn read_controls(mut input: impl BufRead + Send + 'static) -> Receiver<Result<Control, String>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        while let Ok(n) = input.read_line(&mut line) {
+            if n == 0 {
+                break;
+            }
+            if !line.trim().is_empty() && tx.send(line.parse()).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controls_are_read_from_what_commands_leave_free() {
+        assert_eq!(ControlInput::choose(false, false), ControlInput::Stdin);
+        assert_eq!(ControlInput::choose(true, false), ControlInput::Tty);
+        assert_eq!(ControlInput::choose(false, true), ControlInput::Off);
+        assert_eq!(ControlInput::choose(true, true), ControlInput::Off);
+    }
+
+    #[test]
+    fn nothing_is_read_when_off() {
+        assert!(ControlInput::Off.read().try_recv().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use regex::Regex;
+use std::str::FromStr;
+
+/// Which lines of a formatter's output are the changes it would make.
+#[derive(Debug, Clone)]
+pub enum DiffFormat {
+    /// `rustfmt --check` and `cargo fmt --check`, with a `Diff in <file>:<line>:` header
+    /// before each change.
+    Rustfmt,
+    /// Unified diffs, like from `black --diff`, `gofmt -d` or `git diff`.
+    Unified,
+    /// Only the lines matching the pattern, like the file list of `prettier --check`.
+    Pattern(Regex),
+}
+
+impl DiffFormat {
+    /// The lines that make up the diff, or `None` when there aren't any, so output that isn't
+    /// a diff, like a syntax error, is shown as it is.
+    fn diff(&self, text: &str) -> Option<String> {
+        let kept = match self {
+            DiffFormat::Rustfmt => hunks(text, &Regex::new(r"^Diff in .+:$").unwrap()),
+            DiffFormat::Unified => hunks(text, &Regex::new(r"^(diff |--- |\+\+\+ |@@ )").unwrap()),
+            DiffFormat::Pattern(pattern) => text
+                .split_inclusive('\n')
+                .filter(|l| pattern.is_match(l.trim_end()))
+                .collect(),
+        };
+        if kept.is_empty() {
+            None
+        } else {
+            Some(kept)
+        }
+    }
+}
+
+/// The lines from each header up to the first line that isn't part of a diff.
+fn hunks(text: &str, header: &Regex) -> String {
+    let mut kept = String::new();
+    let mut in_hunk = false;
+    for line in text.split_inclusive('\n') {
+        if header.is_match(line.trim_end()) {
+            in_hunk = true;
+        } else if !line.starts_with(&[' ', '+', '-', '@', '\\'][..]) {
+            in_hunk = false;
+        }
+        if in_hunk {
+            kept.push_str(line);
+        }
+    }
+    kept
+}
+
+impl FromStr for DiffFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rustfmt" => Ok(DiffFormat::Rustfmt),
+            "diff" => Ok(DiffFormat::Unified),
+            pattern => Regex::new(pattern)
+                .map(DiffFormat::Pattern)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Only shows the changes a formatter would make, as `<command>:<format>`, where the format is
+/// `rustfmt`, `diff` or a regex matching the lines to keep.
+#[derive(Debug, Clone)]
+pub struct DiffOnly {
+    pub selector: String,
+    format: DiffFormat,
+}
+
+impl DiffOnly {
+    /// The output to show for a run, if it has a diff in it.
+    pub fn apply(&self, output: &CommandOutput) -> Option<CommandOutput> {
+        let out = self.format.diff(&output.out);
+        let err = self.format.diff(&output.err);
+        if out.is_none() && err.is_none() {
+            return None;
+        }
+        Some(CommandOutput {
+            success: output.success,
+            warning: output.warning,
+            code: output.code,
+            out: out.unwrap_or_default(),
+            err: err.unwrap_or_default(),
+            duration: output.duration,
+            counts: output.counts,
+        })
+    }
+}
+
+impl FromStr for DiffOnly {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, format) = split_selector(s, "<command>:<format>", Colons::Right)?;
+        Ok(DiffOnly {
+            selector: selector.to_string(),
+            format: format.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_rustfmt_diffs() {
+        let out = "Checking formatting...\nDiff in /src/main.rs:3:\n fn main() {\n-    foo( );\n\
+                   +    foo();\n }\nwarning: unused config\nDiff in /src/lib.rs:1:\n-use a;\n+use \
+                   a::b;\n";
+
+        assert_eq!(
+            DiffFormat::Rustfmt.diff(out).unwrap(),
+            "Diff in /src/main.rs:3:\n fn main() {\n-    foo( );\n+    foo();\n }\n\
+             Diff in /src/lib.rs:1:\n-use a;\n+use a::b;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_unified_diffs() {
+        let err = "would reformat a.py\n--- a.py\n+++ a.py\n@@ -1 +1 @@\n-x=1\n+x = 1\n\
+                   Oh no! 1 file would be reformatted.\n";
+
+        assert_eq!(
+            DiffFormat::Unified.diff(err).unwrap(),
+            "--- a.py\n+++ a.py\n@@ -1 +1 @@\n-x=1\n+x = 1\n"
+        );
+    }
+
+    #[test]
+    fn output_without_a_diff_is_left_alone() {
+        let only = "cargo fmt --check:rustfmt".parse::<DiffOnly>().unwrap();
+        let output = CommandOutput {
+            out: "error: expected `;`\n".to_string(),
+            ..CommandOutput::default()
+        };
+
+        assert!(only.apply(&output).is_none());
+    }
+
+    #[test]
+    fn pattern_keeps_matching_lines() {
+        let only = "npx prettier --check .:^\\[warn\\] "
+            .parse::<DiffOnly>()
+            .unwrap();
+        assert_eq!(only.selector, "npx prettier --check .");
+        let output = CommandOutput {
+            out: "Checking formatting...\n[warn] a.js\n[warn] b.css\n[warn] Code style issues \
+                  found.\n"
+                .to_string(),
+            ..CommandOutput::default()
+        };
+
+        assert_eq!(
+            only.apply(&output).unwrap().out,
+            "[warn] a.js\n[warn] b.css\n[warn] Code style issues found.\n"
+        );
+    }
+}
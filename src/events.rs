@@ -0,0 +1,369 @@
+use notify::DebouncedEvent;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// Decides whether a change to an absolute path should trigger a run.
+pub type PathFilter = Box<dyn Fn(&Path) -> bool>;
+
+/// How many burst windows a burst can hold changes back for, so a directory files are always
+/// being created in still triggers runs.
+const MAX_BURST_WINDOWS: u32 = 20;
+
+/// Files being created in a directory, and the changes in it held back until they settle.
+struct Burst {
+    dir: PathBuf,
+    started: Instant,
+    last: Instant,
+    held: Vec<PathBuf>,
+}
+
+/// Turns the watcher's events into the changes that should trigger a run.
+pub struct Events {
+    rx: Receiver<DebouncedEvent>,
+    resolve: Box<dyn Fn(PathBuf) -> PathBuf>,
+    // Each filter with the option it comes from.
+    filters: Vec<(String, PathFilter)>,
+    burst_window: Option<Duration>,
+    bursts: Vec<Burst>,
+    // When the watcher last reported a change, whether or not it triggered a run.
+    last_event: Option<Instant>,
+}
+
+impl Events {
+    pub fn new(rx: Receiver<DebouncedEvent>) -> Self {
+        Events {
+            rx,
+            resolve: Box::new(|p| p),
+            filters: Vec::new(),
+            burst_window: None,
+            bursts: Vec::new(),
+            last_event: None,
+        }
+    }
+
+    /// Treats files being created in a directory, like an archive being extracted, as one
+    /// change once there's been no change in it for `window`.
+    pub fn set_burst_window(&mut self, window: Duration) {
+        self.burst_window = Some(window);
+    }
+
+    /// Maps each changed path before it's filtered, e.g. to make it absolute.
+    pub fn set_resolve(&mut self, resolve: impl Fn(PathBuf) -> PathBuf + 'static) {
+        self.resolve = Box::new(resolve);
+    }
+
+    /// Only changes to paths every filter accepts trigger a run. `name` says where the filter
+    /// comes from, for explaining why a change was left out.
+    pub fn add_filter(&mut self, name: &str, filter: impl Fn(&Path) -> bool + 'static) {
+        self.filters.push((name.to_string(), Box::new(filter)));
+    }
+
+    /// Resolves a hypothetical change to `path`, or gives the name of the filter that would leave
+    /// it out.
+    pub fn explain(&self, path: PathBuf) -> Result<PathBuf, &str> {
+        let path = (self.resolve)(path);
+        match self.filters.iter().find(|(_, filter)| !filter(&path)) {
+            Some((name, _)) => Err(name),
+            None => Ok(path),
+        }
+    }
+
+    /// How long ago the watcher last reported a change, if it has.
+    pub fn since_last_event(&self) -> Option<Duration> {
+        self.last_event.map(|at| at.elapsed())
+    }
+
+    /// Takes the pending events, returning the changed paths that should trigger a run, or
+    /// `None` if nothing should. An empty list means the changes are unknown.
+    pub fn drain(&mut self) -> Result<Option<Vec<PathBuf>>, Box<dyn Error>> {
+        let mut changed = None;
+        loop {
+            match self.rx.try_recv() {
+                Err(TryRecvError::Empty) => break,
+                Err(e @ TryRecvError::Disconnected) => Err(e)?,
+                Ok(event) => {
+                    self.last_event = Some(Instant::now());
+                    let created = matches!(event, DebouncedEvent::Create(_));
+                    let paths = event_paths(event)
+                        .map(|p| (self.resolve)(p))
+                        .collect::<Vec<_>>();
+                    if paths.is_empty() {
+                        changed.get_or_insert_with(Vec::new);
+                    }
+                    for path in paths {
+                        match self.extend_burst(&path, created) {
+                            Some(burst) => burst.held.push(path),
+                            None => changed.get_or_insert_with(Vec::new).push(path),
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(window) = self.burst_window {
+            let (settled, bursting) = std::mem::take(&mut self.bursts)
+                .into_iter()
+                .partition::<Vec<_>, _>(|b| {
+                    b.last.elapsed() >= window || b.started.elapsed() >= window * MAX_BURST_WINDOWS
+                });
+            self.bursts = bursting;
+            for burst in settled {
+                changed.get_or_insert_with(Vec::new).extend(burst.held);
+            }
+        }
+
+        let mut changed = match changed {
+            Some(changed) => changed,
+            None => return Ok(None),
+        };
+        if changed.is_empty() {
+            return Ok(Some(changed));
+        }
+        changed.retain(|p| self.filters.iter().all(|(_, f)| f(p)));
+        Ok(Some(changed).filter(|c| !c.is_empty()))
+    }
+
+    /// A change in a bursting directory extends the burst, and creating a file starts one.
+    /// Gives the burst the change is held back by, if any.
+    fn extend_burst(&mut self, path: &Path, created: bool) -> Option<&mut Burst> {
+        self.burst_window?;
+        let now = Instant::now();
+        match self.bursts.iter().position(|b| path.starts_with(&b.dir)) {
+            Some(i) => {
+                self.bursts[i].last = now;
+                Some(&mut self.bursts[i])
+            }
+            None if created => {
+                // An extracted archive's files are created inside the directory it creates
+                // first.
+                let dir = if path.is_dir() {
+                    path
+                } else {
+                    path.parent().unwrap_or(path)
+                };
+                self.bursts.push(Burst {
+                    dir: dir.to_path_buf(),
+                    started: now,
+                    last: now,
+                    held: Vec::new(),
+                });
+                self.bursts.last_mut()
+            }
+            None => None,
+        }
+    }
+}
+
+/// Describes what triggered a run, naming the first few paths.
+pub fn trigger_summary(paths: &[PathBuf]) -> String {
+    const SHOWN: usize = 3;
+
+    if paths.is_empty() {
+        return "triggered by: unknown changes".to_string();
+    }
+    let mut summary = paths
+        .iter()
+        .take(SHOWN)
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if paths.len() > SHOWN {
+        summary.push_str(&format!(" and {} more", paths.len() - SHOWN));
+    }
+    format!("triggered by: {}", summary)
+}
+
+/// A rule across `width` columns to start a run's output with, saying when it started and what
+/// triggered it.
+pub fn separator(time: &str, paths: &[PathBuf], width: usize) -> String {
+    let label = format!("── {} {} ", time, trigger_summary(paths));
+    let fill = width.saturating_sub(label.chars().count());
+    format!("{}{}", label, "─".repeat(fill))
+}
+
+fn event_paths(event: DebouncedEvent) -> impl Iterator<Item = PathBuf> {
+    let paths = match event {
+        DebouncedEvent::NoticeWrite(p)
+        | DebouncedEvent::NoticeRemove(p)
+        | DebouncedEvent::Create(p)
+        | DebouncedEvent::Write(p)
+        | DebouncedEvent::Chmod(p)
+        | DebouncedEvent::Remove(p)
+        | DebouncedEvent::Error(_, Some(p)) => vec![p],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Rescan | DebouncedEvent::Error(_, None) => vec![],
+    };
+    paths.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn no_events() {
+        let (_tx, rx) = channel();
+
+        assert_eq!(Events::new(rx).drain().unwrap(), None);
+    }
+
+    #[test]
+    fn filters_changed_paths() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.add_filter("test", |p| p.extension().is_some_and(|e| e == "rs"));
+
+        tx.send(DebouncedEvent::Write("/a/main.rs".into())).unwrap();
+        tx.send(DebouncedEvent::Write("/a/README.md".into()))
+            .unwrap();
+
+        assert_eq!(
+            events.drain().unwrap(),
+            Some(vec![PathBuf::from("/a/main.rs")])
+        );
+    }
+
+    #[test]
+    fn nothing_when_every_path_is_filtered() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.add_filter("test", |_| false);
+
+        tx.send(DebouncedEvent::Write("/a/main.rs".into())).unwrap();
+
+        assert_eq!(events.drain().unwrap(), None);
+    }
+
+    #[test]
+    fn burst_of_creates_is_one_change() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.set_burst_window(Duration::from_millis(20));
+
+        tx.send(DebouncedEvent::Create("/a/pkg/one.rs".into()))
+            .unwrap();
+        tx.send(DebouncedEvent::Create("/a/pkg/two.rs".into()))
+            .unwrap();
+        assert_eq!(events.drain().unwrap(), None);
+        tx.send(DebouncedEvent::Write("/a/pkg/two.rs".into()))
+            .unwrap();
+        assert_eq!(events.drain().unwrap(), None);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(
+            events.drain().unwrap(),
+            Some(vec![
+                PathBuf::from("/a/pkg/one.rs"),
+                PathBuf::from("/a/pkg/two.rs"),
+                PathBuf::from("/a/pkg/two.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn writes_outside_bursts_are_not_held() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.set_burst_window(Duration::from_millis(20));
+
+        tx.send(DebouncedEvent::Write("/a/main.rs".into())).unwrap();
+
+        assert_eq!(
+            events.drain().unwrap(),
+            Some(vec![PathBuf::from("/a/main.rs")])
+        );
+    }
+
+    #[test]
+    fn writes_elsewhere_pass_during_a_burst() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.set_burst_window(Duration::from_millis(50));
+
+        tx.send(DebouncedEvent::Create("/a/pkg/one.rs".into()))
+            .unwrap();
+        tx.send(DebouncedEvent::Write("/a/main.rs".into())).unwrap();
+
+        assert_eq!(
+            events.drain().unwrap(),
+            Some(vec![PathBuf::from("/a/main.rs")])
+        );
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(
+            events.drain().unwrap(),
+            Some(vec![PathBuf::from("/a/pkg/one.rs")])
+        );
+    }
+
+    #[test]
+    fn endless_burst_is_released() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.set_burst_window(Duration::from_millis(10));
+
+        let started = Instant::now();
+        let released = loop {
+            tx.send(DebouncedEvent::Create("/a/pkg/one.rs".into()))
+                .unwrap();
+            if let Some(released) = events.drain().unwrap() {
+                break released;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(2));
+        };
+
+        assert!(started.elapsed() >= Duration::from_millis(10) * MAX_BURST_WINDOWS);
+        assert!(!released.is_empty());
+    }
+
+    #[test]
+    fn explains_which_filter_leaves_path_out() {
+        let (_tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.add_filter("--ext", |p| p.extension().is_some_and(|e| e == "rs"));
+
+        assert_eq!(
+            events.explain("/a/main.rs".into()),
+            Ok(PathBuf::from("/a/main.rs"))
+        );
+        assert_eq!(events.explain("/a/README.md".into()), Err("--ext"));
+    }
+
+    #[test]
+    fn summary_names_first_paths() {
+        let paths = ["a.rs", "b.rs", "c.rs", "d.rs", "e.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(trigger_summary(&paths[..2]), "triggered by: a.rs, b.rs");
+        assert_eq!(
+            trigger_summary(&paths),
+            "triggered by: a.rs, b.rs, c.rs and 2 more"
+        );
+        assert_eq!(trigger_summary(&[]), "triggered by: unknown changes");
+    }
+
+    #[test]
+    fn separator_fills_width() {
+        let rule = separator("09:30:00", &[PathBuf::from("a.rs")], 40);
+
+        assert_eq!(rule, "── 09:30:00 triggered by: a.rs ─────────");
+        assert_eq!(rule.chars().count(), 40);
+        assert!(separator("09:30:00", &[], 10).starts_with("── 09:30:00 triggered by"));
+    }
+
+    #[test]
+    fn rescan_is_unknown_change() {
+        let (tx, rx) = channel();
+        let mut events = Events::new(rx);
+        events.add_filter("test", |_| false);
+
+        tx.send(DebouncedEvent::Rescan).unwrap();
+
+        assert_eq!(events.drain().unwrap(), Some(vec![]));
+    }
+}
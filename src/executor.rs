@@ -1,90 +1,893 @@
+use crate::args::{split_selector, Colons};
+use crate::result_format::TestCounts;
+use regex::Regex;
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
-use std::time::Duration;
-use subprocess::{Exec, NullFile, Redirection};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 #[cfg_attr(test, mockall::automock)]
 pub trait Child {
     fn poll(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>>;
     fn terminate(&mut self) -> Result<(), Box<dyn Error>>;
+    /// Kills the command, for when it doesn't exit after being terminated.
+    fn kill(&mut self) -> Result<(), Box<dyn Error>>;
+    fn memory_usage(&self) -> Result<Option<u64>, Box<dyn Error>>;
+    /// Everything written so far, stdout and stderr interleaved as it was read, for commands
+    /// that keep it. It only grows, apart from what's dropped from its start to stay within the
+    /// output limits, and is still available once the run has finished.
+    fn partial_output(&self) -> String;
+    /// How many bytes have been dropped from the start of `partial_output`.
+    fn partial_output_dropped(&self) -> usize;
+    /// When the command last wrote any output, if it has.
+    fn last_output(&self) -> Option<Instant>;
 }
 
 #[cfg_attr(test, mockall::automock(type Child=MockChild;))]
 pub trait Executor {
     type Child: Child;
 
-    fn start(&mut self) -> Result<Self::Child, Box<dyn Error>>;
+    /// `env` is set in the command's environment, on top of watchdo's own.
+    fn start(&mut self, env: &[(String, String)]) -> Result<Self::Child, Box<dyn Error>>;
 }
 
 pub struct SubprocessExecutor {
     command: OsString,
+    limits: OutputLimits,
+    wrapper: Option<OsString>,
+    stdin: Stdin,
+    env: Vec<EnvVar>,
+    // The cgroup.procs file of the cgroup to run in.
+    cgroup: Option<PathBuf>,
+    // The shell to run the command in as a login shell, loading the user's profile.
+    login_shell: Option<OsString>,
+    // Whether runs keep their partial output.
+    partial: bool,
+}
+
+/// Where a command reads its input from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Stdin {
+    /// Nothing, so anything reading stdin sees it end straight away.
+    #[default]
+    Null,
+    /// Watchdo's own stdin.
+    Inherit,
+    File(PathBuf),
+}
+
+/// A file to give a command as its stdin, as `<command>:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdinFile {
+    pub selector: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for StdinFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, path) = split_selector(s, "<command>:<path>", Colons::Left)?;
+        Ok(StdinFile {
+            selector: selector.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// An environment variable to run commands with, as `<key>=<value>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvVar {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for EnvVar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(EnvVar {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected <key>=<value>, got {:?}", s)),
+        }
+    }
+}
+
+/// Maximum number of bytes kept from each output stream. The end of the output is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    pub out: Option<usize>,
+    pub err: Option<usize>,
 }
 
 impl SubprocessExecutor {
     pub fn new(command: impl AsRef<OsStr>) -> Self {
         SubprocessExecutor {
             command: command.as_ref().to_owned(),
+            limits: OutputLimits::default(),
+            wrapper: None,
+            stdin: Stdin::Null,
+            env: Vec::new(),
+            cgroup: None,
+            login_shell: None,
+            partial: false,
         }
     }
+
+    pub fn set_stdin(&mut self, stdin: Stdin) {
+        self.stdin = stdin;
+    }
+
+    /// Variables set for every run, before the ones watchdo sets itself.
+    pub fn set_env(&mut self, env: Vec<EnvVar>) {
+        self.env = env;
+    }
+
+    /// Runs the command through `wrapper`, like `time sh -c '<command>'`.
+    pub fn set_wrapper(&mut self, wrapper: impl AsRef<OsStr>) {
+        self.wrapper = Some(wrapper.as_ref().to_owned());
+    }
+
+    /// Runs the command in the cgroup whose `cgroup.procs` file this is.
+    pub fn set_cgroup(&mut self, procs: PathBuf) {
+        self.cgroup = Some(procs);
+    }
+
+    /// Runs the command with `shell -lc`, for tools set up in the user's profile, like nvm.
+    pub fn set_login_shell(&mut self, shell: impl AsRef<OsStr>) {
+        self.login_shell = Some(shell.as_ref().to_owned());
+    }
+
+    fn shell_command(&self) -> OsString {
+        let command = match &self.login_shell {
+            Some(shell) => {
+                let mut command = OsString::from("exec ");
+                command.push(shell_quote(&shell.to_string_lossy()));
+                command.push(" -lc ");
+                command.push(shell_quote(&self.command.to_string_lossy()));
+                command
+            }
+            None => self.command.clone(),
+        };
+        let command = match &self.wrapper {
+            Some(wrapper) => {
+                let mut wrapped = wrapper.clone();
+                wrapped.push(" sh -c ");
+                wrapped.push(shell_quote(&command.to_string_lossy()));
+                wrapped
+            }
+            None => command,
+        };
+        let procs = match &self.cgroup {
+            Some(procs) => procs,
+            None => return command,
+        };
+
+        // The shell joins before starting anything, so everything it starts is in the cgroup.
+        let mut joined = OsString::from("echo $$ > ");
+        joined.push(shell_quote(&procs.to_string_lossy()));
+        joined.push(" && exec sh -c ");
+        joined.push(shell_quote(&command.to_string_lossy()));
+        joined
+    }
+
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        self.limits = limits;
+    }
+
+    /// Keeps both streams interleaved as they're written, for `Child::partial_output`.
+    pub fn keep_partial_output(&mut self) {
+        self.partial = true;
+    }
 }
 
 impl Executor for SubprocessExecutor {
-    type Child = subprocess::Popen;
+    type Child = SubprocessChild;
+
+    fn start(&mut self, env: &[(String, String)]) -> Result<Self::Child, Box<dyn Error>> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(self.shell_command());
+        for var in &self.env {
+            command.env(&var.key, &var.value);
+        }
+        for (key, value) in env {
+            command.env(key, value);
+        }
+        let scratch = scratch_path()?;
+        command.env("WATCHDO_SCRATCH", &scratch);
+        command.stdin(match &self.stdin {
+            Stdin::Null => Stdio::null(),
+            Stdin::Inherit => Stdio::inherit(),
+            // Opened for each run, so every run reads it from the start.
+            Stdin::File(path) => Stdio::from(File::open(path)?),
+        });
+        // Everything the command starts is in its group, so it can all be signalled together.
+        // A command reading the terminal has to stay in watchdo's group, the terminal's
+        // foreground one, or reading it stops the command.
+        let grouped = cfg!(unix) && self.stdin != Stdin::Inherit;
+        #[cfg(unix)]
+        if grouped {
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        }
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let captured = Arc::new(Mutex::new(Captured::new(self.limits, self.partial)));
+        let mut readers = Vec::new();
+        if let Some(out) = child.stdout.take() {
+            readers.push(read_into(out, captured.clone(), false));
+        }
+        if let Some(err) = child.stderr.take() {
+            readers.push(read_into(err, captured.clone(), true));
+        }
+        Ok(SubprocessChild {
+            child,
+            grouped,
+            captured,
+            readers,
+            scratch,
+        })
+    }
+}
+
+pub struct SubprocessChild {
+    child: std::process::Child,
+    // Whether the command leads a process group of its own.
+    grouped: bool,
+    captured: Arc<Mutex<Captured>>,
+    readers: Vec<JoinHandle<()>>,
+    // The run's WATCHDO_SCRATCH, removed once it's finished.
+    scratch: PathBuf,
+}
 
-    fn start(&mut self) -> Result<Self::Child, Box<dyn Error>> {
-        Ok(Exec::shell(&self.command)
-            .stdin(NullFile)
-            .stdout(Redirection::Pipe)
-            .stderr(Redirection::Pipe)
-            .popen()?)
+struct Captured {
+    out: Tail,
+    err: Tail,
+    // Both streams as they were read, for commands that keep their partial output.
+    interleaved: Option<Tail>,
+    last_output: Option<Instant>,
+}
+
+impl Captured {
+    fn new(limits: OutputLimits, partial: bool) -> Self {
+        let both = limits.out.zip(limits.err).map(|(out, err)| out + err);
+        Captured {
+            out: Tail::new(limits.out),
+            err: Tail::new(limits.err),
+            interleaved: partial.then(|| Tail::new(both)),
+            last_output: None,
+        }
     }
 }
 
-impl Child for subprocess::Popen {
-    fn poll(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>> {
-        match subprocess::Popen::poll(self) {
-            None => Ok(None),
-            Some(exit) => {
-                let output = self
-                    .communicate_start(None)
-                    .limit_time(Duration::from_millis(100))
-                    .read_string();
-
-                let error = match output {
-                    Ok((Some(out), Some(err))) => {
-                        return Ok(Some(CommandOutput {
-                            success: exit.success(),
-                            out,
-                            err,
-                        }));
-                    }
-                    Ok((None, _)) | Ok((_, None)) => unreachable!(),
-                    Err(e) => e,
-                };
-
-                if error.kind() != std::io::ErrorKind::TimedOut {
-                    return Err(Box::new(error));
-                }
+// How much is read from a pipe at once.
+const CHUNK: usize = 8192;
 
-                let output = error.capture;
-                Ok(Some(CommandOutput {
-                    success: exit.success(),
-                    out: std::string::String::from_utf8(output.0.unwrap())?,
-                    err: std::string::String::from_utf8(output.1.unwrap())?,
-                }))
+/// The end of what a command wrote, kept within a limit as it's written. It's allowed a chunk
+/// over the limit, so the start isn't dropped for every read.
+struct Tail {
+    bytes: Vec<u8>,
+    limit: Option<usize>,
+    // How many bytes have been dropped from the start.
+    dropped: usize,
+}
 
+impl Tail {
+    fn new(limit: Option<usize>) -> Self {
+        Tail {
+            bytes: Vec::new(),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        let limit = match self.limit {
+            Some(limit) if self.bytes.len() > limit + CHUNK => limit,
+            _ => return,
+        };
+        let mut start = self.bytes.len() - limit;
+        // Don't start partway through a UTF-8 character.
+        while self.bytes.get(start).is_some_and(|b| b & 0xc0 == 0x80) {
+            start += 1;
+        }
+        self.bytes.drain(..start);
+        self.dropped += start;
+    }
+
+    /// What's kept, trimmed to the limit, noting how much was left out.
+    fn text(&self) -> Result<String, std::string::FromUtf8Error> {
+        let kept = String::from_utf8(self.bytes.clone())?;
+        Ok(keep_tail(kept, self.dropped, self.limit))
+    }
+}
+
+/// Reads a pipe as the command writes to it, so its output can be shown while it runs and it
+/// never blocks on a full pipe.
+fn read_into(
+    mut pipe: impl Read + Send + 'static,
+    captured: Arc<Mutex<Captured>>,
+    is_err: bool,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut buf = [0; CHUNK];
+        loop {
+            let n = match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let mut captured = captured.lock().unwrap();
+            match is_err {
+                true => captured.err.push(&buf[..n]),
+                false => captured.out.push(&buf[..n]),
+            }
+            if let Some(interleaved) = &mut captured.interleaved {
+                interleaved.push(&buf[..n]);
             }
+            captured.last_output = Some(Instant::now());
+        }
+    })
+}
+
+impl Child for SubprocessChild {
+    fn poll(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>> {
+        let output = self.poll_popen()?;
+        if output.is_some() {
+            remove_scratch(&self.scratch);
         }
+        Ok(output)
+    }
+
+    #[cfg(unix)]
+    fn terminate(&mut self) -> Result<(), Box<dyn Error>> {
+        self.signal(libc::SIGTERM)
     }
 
+    #[cfg(not(unix))]
     fn terminate(&mut self) -> Result<(), Box<dyn Error>> {
-        Ok(subprocess::Popen::terminate(self)?)
+        Ok(self.child.kill()?)
+    }
+
+    #[cfg(unix)]
+    fn kill(&mut self) -> Result<(), Box<dyn Error>> {
+        self.signal(libc::SIGKILL)
+    }
+
+    #[cfg(not(unix))]
+    fn kill(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.child.kill()?)
+    }
+
+    fn memory_usage(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        tree_memory_usage(self.child.id())
+    }
+
+    fn partial_output(&self) -> String {
+        match &self.captured.lock().unwrap().interleaved {
+            Some(interleaved) => String::from_utf8_lossy(&interleaved.bytes).into_owned(),
+            None => String::new(),
+        }
+    }
+
+    fn partial_output_dropped(&self) -> usize {
+        let captured = self.captured.lock().unwrap();
+        captured.interleaved.as_ref().map_or(0, |i| i.dropped)
+    }
+
+    fn last_output(&self) -> Option<Instant> {
+        self.captured.lock().unwrap().last_output
+    }
+}
+
+impl Drop for SubprocessChild {
+    fn drop(&mut self) {
+        remove_scratch(&self.scratch);
     }
 }
 
+impl SubprocessChild {
+    /// Sends `signal` to the command's process group, which reaches everything it started,
+    /// like a dev server's own children that would otherwise keep running.
+    #[cfg(unix)]
+    fn signal(&mut self, signal: libc::c_int) -> Result<(), Box<dyn Error>> {
+        // Once it's been reaped its pid may belong to something else.
+        if self.child.try_wait()?.is_some() {
+            return Ok(());
+        }
+        let pid = self.child.id() as libc::pid_t;
+        let target = if self.grouped { -pid } else { pid };
+        // Safe since it only sends a signal.
+        if unsafe { libc::kill(target, signal) } != 0 {
+            let e = std::io::Error::last_os_error();
+            // Everything in the group has already exited.
+            if e.raw_os_error() != Some(libc::ESRCH) {
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_popen(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>> {
+        let exit = match self.child.try_wait()? {
+            None => return Ok(None),
+            Some(exit) => exit,
+        };
+
+        // Something the command started in the background may keep the pipes open, so only wait
+        // a little for the output to end.
+        let deadline = Instant::now() + Duration::from_millis(100);
+        while self.readers.iter().any(|r| !r.is_finished()) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let captured = self.captured.lock().unwrap();
+        Ok(Some(CommandOutput {
+            success: exit.success(),
+            code: exit.code(),
+            out: captured.out.text()?,
+            err: captured.err.text()?,
+            ..CommandOutput::default()
+        }))
+    }
+}
+
+// The directory the runs' scratch paths are in, once it's been made.
+static SCRATCH_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A path unique to a run, for it to keep scratch files at. Nothing is there to begin with.
+fn scratch_path() -> std::io::Result<PathBuf> {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let mut dir = SCRATCH_DIR.lock().unwrap();
+    if dir.is_none() {
+        *dir = Some(private_dir(&std::env::temp_dir())?);
+    }
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    Ok(dir.as_ref().unwrap().join(run.to_string()))
+}
+
+/// Makes a new directory in `parent` that only this user can get into. Its name is random, so
+/// other users can't make it first or guess where the scratch paths are.
+fn private_dir(parent: &Path) -> std::io::Result<PathBuf> {
+    use std::hash::{BuildHasher, Hasher};
+
+    loop {
+        // Each RandomState is seeded differently.
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let dir = parent.join(format!("watchdo-{}-{:016x}", std::process::id(), random));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        match builder.create(&dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|()| dir),
+        }
+    }
+}
+
+/// Removes the runs' scratch paths, for when watchdo exits.
+pub fn remove_scratch_dir() {
+    if let Some(dir) = SCRATCH_DIR.lock().unwrap().take() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+/// Removes whatever a run left at its scratch path, a file or a directory.
+fn remove_scratch(path: &Path) {
+    if std::fs::remove_file(path).is_err() {
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
+
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// The last `limit` bytes of `s`, noting how many were left out, counting the `dropped` ones
+/// before it.
+fn keep_tail(s: String, dropped: usize, limit: Option<usize>) -> String {
+    let mut start = match limit {
+        Some(limit) if s.len() > limit => s.len() - limit,
+        _ => 0,
+    };
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    match dropped + start {
+        0 => s,
+        truncated => format!("... ({} bytes truncated)\n{}", truncated, &s[start..]),
+    }
+}
+
+// Sums the resident memory of the process and all its descendants, since the shell usually
+// spawns the real command as a child.
+#[cfg(target_os = "linux")]
+fn tree_memory_usage(root: u32) -> Result<Option<u64>, Box<dyn Error>> {
+    let mut parents = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        // Processes may exit while we're looking at them.
+        let stat = match std::fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        // The process name may contain spaces, so count fields after its closing paren.
+        let ppid = stat
+            .rsplit(')')
+            .next()
+            .and_then(|rest| rest.split_whitespace().nth(1))
+            .and_then(|ppid| ppid.parse::<u32>().ok());
+        if let Some(ppid) = ppid {
+            parents.push((pid, ppid));
+        }
+    }
+
+    let mut tree = vec![root];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(parents.iter().filter(|p| p.1 == parent).map(|p| p.0));
+        i += 1;
+    }
+
+    let mut total = 0;
+    for pid in tree {
+        let status = match std::fs::read_to_string(format!("/proc/{}/status", pid)) {
+            Ok(status) => status,
+            Err(_) => continue,
+        };
+        let kb = status
+            .lines()
+            .find(|l| l.starts_with("VmRSS:"))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|kb| kb.parse::<u64>().ok())
+            .unwrap_or(0);
+        total += kb * 1024;
+    }
+    Ok(Some(total))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tree_memory_usage(_root: u32) -> Result<Option<u64>, Box<dyn Error>> {
+    Ok(None)
+}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct CommandOutput {
     pub success: bool,
+    /// Passed, but with something worth looking at.
+    pub warning: bool,
+    /// None if the command was killed by a signal.
+    pub code: Option<i32>,
     pub out: String,
     pub err: String,
+    /// How long the run took.
+    pub duration: Duration,
+    /// The test results in the output, for commands with a --format-parser.
+    pub counts: Option<TestCounts>,
+}
+
+impl CommandOutput {
+    /// Whether the run failed because the shell couldn't find the program, going by the exit
+    /// code the shell uses for that or its message, like `sh: 1: carg: not found`.
+    pub fn is_not_found(&self) -> bool {
+        static MESSAGE: OnceLock<Regex> = OnceLock::new();
+        let message = MESSAGE.get_or_init(|| {
+            Regex::new(r"(?m)^(sh|bash|dash|zsh|ksh|ash)\b.*\bnot found\b").unwrap()
+        });
+        !self.success && (self.code == Some(127) || message.is_match(&self.err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn not_found_by_exit_code_or_shell_message() {
+        let run = |code, err: &str| CommandOutput {
+            code: Some(code),
+            err: err.to_string(),
+            ..CommandOutput::default()
+        };
+
+        assert!(run(127, "").is_not_found());
+        assert!(run(1, "bash: line 1: carg: command not found\n").is_not_found());
+        assert!(run(2, "zsh:1: command not found: carg\n").is_not_found());
+        assert!(!run(1, "error: config.toml not found\n").is_not_found());
+        assert!(!CommandOutput {
+            success: true,
+            ..run(127, "")
+        }
+        .is_not_found());
+    }
+
+    #[test]
+    fn wrapper_runs_command_through_shell() {
+        let mut executor = SubprocessExecutor::new("echo 'hi'");
+        executor.set_wrapper("time");
+
+        assert_eq!(executor.shell_command(), "time sh -c 'echo '\\''hi'\\'''");
+    }
+
+    #[test]
+    fn login_shell_runs_command() {
+        let mut executor = SubprocessExecutor::new("npm test");
+        executor.set_login_shell("/bin/bash");
+        executor.set_wrapper("time");
+
+        assert_eq!(
+            executor.shell_command(),
+            "time sh -c 'exec '\\''/bin/bash'\\'' -lc '\\''npm test'\\'''"
+        );
+    }
+
+    #[test]
+    fn joins_cgroup_before_running_command() {
+        let mut executor = SubprocessExecutor::new("cargo test");
+        executor.set_cgroup(PathBuf::from("/sys/fs/cgroup/watchdo-1/cgroup.procs"));
+
+        assert_eq!(
+            executor.shell_command(),
+            "echo $$ > '/sys/fs/cgroup/watchdo-1/cgroup.procs' && exec sh -c 'cargo test'"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn terminate_reaches_what_the_command_started() {
+        let mut executor = SubprocessExecutor::new("sleep 30 & echo $!; wait");
+        executor.keep_partial_output();
+        let mut child = executor.start(&[]).unwrap();
+        let started = Instant::now();
+        while !child.partial_output().ends_with('\n') && started.elapsed() < Duration::from_secs(5)
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stat = PathBuf::from("/proc")
+            .join(child.partial_output().trim())
+            .join("stat");
+        // Exited processes linger as zombies until they're reaped, which isn't up to watchdo.
+        let running = || {
+            std::fs::read_to_string(&stat)
+                .is_ok_and(|s| !s.rsplit(')').next().unwrap().starts_with(" Z"))
+        };
+        assert!(running());
+
+        child.terminate().unwrap();
+        while child.poll().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stopped = Instant::now();
+        while running() && stopped.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!running());
+    }
+
+    #[test]
+    fn stdin_file_splits_at_last_colon() {
+        assert_eq!(
+            "rustfmt --check:src/main.rs".parse(),
+            Ok(StdinFile {
+                selector: "rustfmt --check".to_string(),
+                path: PathBuf::from("src/main.rs"),
+            })
+        );
+        assert!("rustfmt".parse::<StdinFile>().is_err());
+    }
+
+    // The process group of the process whose /proc/<pid>/stat this is.
+    #[cfg(target_os = "linux")]
+    fn group(stat: &str) -> String {
+        let fields = stat.rsplit(')').next().unwrap();
+        fields.split(' ').nth(3).unwrap().to_string()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn run_to_end(executor: &mut SubprocessExecutor) -> CommandOutput {
+        let mut child = executor.start(&[]).unwrap();
+        loop {
+            if let Some(output) = child.poll().unwrap() {
+                return output;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn commands_lead_a_group_of_their_own() {
+        let mut executor = SubprocessExecutor::new("echo $$; cat /proc/$$/stat");
+
+        let output = run_to_end(&mut executor);
+
+        let (pid, stat) = output.out.split_once('\n').unwrap();
+        assert_eq!(group(stat), pid);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn inherited_stdin_stays_in_watchdo_group() {
+        let ours = group(&std::fs::read_to_string("/proc/self/stat").unwrap());
+        let mut executor = SubprocessExecutor::new("cat /proc/$$/stat");
+        executor.set_stdin(Stdin::Inherit);
+
+        let output = run_to_end(&mut executor);
+
+        assert_eq!(group(&output.out), ours);
+    }
+
+    #[test]
+    fn file_is_given_as_stdin() {
+        let dir = TestDir::new();
+        let path = dir.join("stdin");
+        std::fs::write(&path, "from file").unwrap();
+        let mut executor = SubprocessExecutor::new("cat");
+        executor.set_stdin(Stdin::File(path.clone()));
+
+        let mut child = executor.start(&[]).unwrap();
+        let output = loop {
+            if let Some(output) = child.poll().unwrap() {
+                break output;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(output.out, "from file");
+    }
+
+    #[test]
+    fn env_vars_are_set_for_runs() {
+        let mut executor = SubprocessExecutor::new("echo $PROFILE_VAR");
+        executor.set_env(vec!["PROFILE_VAR=a=b".parse().unwrap()]);
+
+        let mut child = executor.start(&[]).unwrap();
+        let output = loop {
+            if let Some(output) = child.poll().unwrap() {
+                break output;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(output.out, "a=b\n");
+        assert!("=1".parse::<EnvVar>().is_err());
+    }
+
+    #[test]
+    fn each_run_gets_its_own_scratch_path() {
+        let mut executor = SubprocessExecutor::new("echo $WATCHDO_SCRATCH; mkdir $WATCHDO_SCRATCH");
+        let mut run = || {
+            let mut child = executor.start(&[]).unwrap();
+            let output = loop {
+                if let Some(output) = child.poll().unwrap() {
+                    break output;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            };
+            assert!(output.success);
+            PathBuf::from(output.out.trim())
+        };
+
+        let (first, second) = (run(), run());
+        assert_ne!(first, second);
+        assert!(!first.exists() && !second.exists());
+        assert_eq!(first.parent(), second.parent());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn private_dir_is_only_for_this_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let parent = TestDir::new();
+        let (first, second) = (
+            private_dir(parent.path()).unwrap(),
+            private_dir(parent.path()).unwrap(),
+        );
+        let mode = std::fs::metadata(&first).unwrap().permissions().mode();
+
+        assert_ne!(first, second);
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn keep_tail_without_limit() {
+        assert_eq!(keep_tail("abcdef".to_string(), 0, None), "abcdef");
+    }
+
+    #[test]
+    fn keep_tail_under_limit() {
+        assert_eq!(keep_tail("abc".to_string(), 0, Some(3)), "abc");
+    }
+
+    #[test]
+    fn keep_tail_over_limit_keeps_end() {
+        assert_eq!(
+            keep_tail("abcdef".to_string(), 0, Some(2)),
+            "... (4 bytes truncated)\nef"
+        );
+    }
+
+    #[test]
+    fn keep_tail_respects_char_boundaries() {
+        assert_eq!(
+            keep_tail("aé".to_string(), 0, Some(1)),
+            "... (3 bytes truncated)\n"
+        );
+    }
+
+    #[test]
+    fn keep_tail_counts_dropped_bytes() {
+        assert_eq!(
+            keep_tail("abc".to_string(), 5, Some(3)),
+            "... (5 bytes truncated)\nabc"
+        );
+    }
+
+    #[test]
+    fn output_is_trimmed_while_the_command_runs() {
+        let mut executor = SubprocessExecutor::new("head -c 100000 /dev/zero | tr '\\0' a");
+        executor.set_output_limits(OutputLimits {
+            out: Some(100),
+            err: Some(100),
+        });
+        executor.keep_partial_output();
+
+        let mut child = executor.start(&[]).unwrap();
+        let mut most = 0;
+        let output = loop {
+            {
+                let captured = child.captured.lock().unwrap();
+                let interleaved = captured.interleaved.as_ref().unwrap();
+                most = most.max(captured.out.bytes.len().max(interleaved.bytes.len()));
+            }
+            if let Some(output) = child.poll().unwrap() {
+                break output;
+            }
+        };
+
+        assert!(most <= 200 + CHUNK, "{}", most);
+        assert_eq!(
+            output.out,
+            format!("... (99900 bytes truncated)\n{}", "a".repeat(100))
+        );
+        assert!(child.partial_output_dropped() > 0);
+    }
+
+    #[test]
+    fn partial_output_is_only_kept_when_asked_for() {
+        let mut executor = SubprocessExecutor::new("echo hi");
+        let mut child = executor.start(&[]).unwrap();
+        while child.poll().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(child.partial_output(), "");
+        assert!(child.captured.lock().unwrap().interleaved.is_none());
+    }
 }
@@ -0,0 +1,284 @@
+use crate::executor::*;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Runs several variants of a command at once, reporting them as a single command that only
+/// succeeds when every variant does.
+pub struct FanOut<E: Executor> {
+    variants: Vec<(String, E)>,
+}
+
+impl<E: Executor> FanOut<E> {
+    pub fn new(variants: Vec<(String, E)>) -> Self {
+        FanOut { variants }
+    }
+
+    pub fn single(executor: E) -> Self {
+        FanOut::new(vec![(String::new(), executor)])
+    }
+
+    pub fn executors_mut(&mut self) -> impl Iterator<Item = &mut E> {
+        self.variants.iter_mut().map(|v| &mut v.1)
+    }
+}
+
+impl<E: Executor> Executor for FanOut<E> {
+    type Child = FanOutChild<E::Child>;
+
+    fn start(&mut self, env: &[(String, String)]) -> Result<Self::Child, Box<dyn Error>> {
+        let mut variants = Vec::new();
+        for (label, executor) in self.variants.iter_mut() {
+            match executor.start(env) {
+                Ok(child) => variants.push(Variant {
+                    label: label.clone(),
+                    child,
+                    output: None,
+                }),
+                Err(e) => {
+                    for variant in variants.iter_mut() {
+                        variant.child.terminate()?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(FanOutChild {
+            variants,
+            finish_order: Vec::new(),
+        })
+    }
+}
+
+pub struct FanOutChild<C: Child> {
+    variants: Vec<Variant<C>>,
+    // The variants in the order they finished.
+    finish_order: Vec<usize>,
+}
+
+struct Variant<C> {
+    label: String,
+    // Kept once finished for its partial output.
+    child: C,
+    output: Option<CommandOutput>,
+}
+
+impl<C: Child> Child for FanOutChild<C> {
+    fn poll(&mut self) -> Result<Option<CommandOutput>, Box<dyn Error>> {
+        for (i, variant) in self.variants.iter_mut().enumerate() {
+            if variant.output.is_none() {
+                variant.output = variant.child.poll()?;
+                if variant.output.is_some() {
+                    self.finish_order.push(i);
+                }
+            }
+        }
+
+        let mut outputs = Vec::new();
+        for variant in self.variants.iter_mut() {
+            match &mut variant.output {
+                None => return Ok(None),
+                Some(output) => outputs.push((variant.label.as_str(), output)),
+            }
+        }
+
+        if outputs.len() == 1 {
+            return Ok(Some(std::mem::take(outputs[0].1)));
+        }
+
+        let mut combined = CommandOutput {
+            success: outputs.iter().all(|(_, o)| o.success),
+            warning: outputs.iter().any(|(_, o)| o.warning),
+            // Report the code of the first variant that failed.
+            code: outputs
+                .iter()
+                .find(|(_, o)| !o.success)
+                .unwrap_or(&outputs[0])
+                .1
+                .code,
+            ..CommandOutput::default()
+        };
+        for (label, output) in outputs {
+            let status = if output.success { "passed" } else { "failed" };
+            combined
+                .out
+                .push_str(&format!("== {} ({}) ==\n{}", label, status, output.out));
+            if !output.err.is_empty() {
+                combined
+                    .err
+                    .push_str(&format!("== {} ==\n{}", label, output.err));
+            }
+        }
+        Ok(Some(combined))
+    }
+
+    fn terminate(&mut self) -> Result<(), Box<dyn Error>> {
+        for variant in self.variants.iter_mut() {
+            if variant.output.is_none() {
+                variant.child.terminate()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn kill(&mut self) -> Result<(), Box<dyn Error>> {
+        for variant in self.variants.iter_mut() {
+            if variant.output.is_none() {
+                variant.child.kill()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn memory_usage(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        let mut total = None;
+        for variant in self.variants.iter().filter(|v| v.output.is_none()) {
+            if let Some(usage) = variant.child.memory_usage()? {
+                total = Some(total.unwrap_or(0) + usage);
+            }
+        }
+        Ok(total)
+    }
+
+    /// The latest output of any variant that's still running.
+    fn last_output(&self) -> Option<Instant> {
+        self.variants
+            .iter()
+            .filter(|v| v.output.is_none())
+            .filter_map(|v| v.child.last_output())
+            .max()
+    }
+
+    /// A single variant's output as it's written. With several, each variant's output is added
+    /// once it finishes, so their lines don't mix.
+    fn partial_output(&self) -> String {
+        if let [variant] = &self.variants[..] {
+            return variant.child.partial_output();
+        }
+
+        self.finish_order
+            .iter()
+            .map(|i| &self.variants[*i])
+            .map(|v| format!("== {} ==\n{}", v.label, v.child.partial_output()))
+            .collect()
+    }
+
+    /// Only a single variant's output is dropped from while it grows. With several, each
+    /// variant's output is added whole, as far as it was kept.
+    fn partial_output_dropped(&self) -> usize {
+        match &self.variants[..] {
+            [variant] => variant.child.partial_output_dropped(),
+            _ => 0,
+        }
+    }
+}
+
+/// Values to substitute for `{}` in a command, as `<command>=<value>,<value>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variants {
+    pub selector: String,
+    pub values: Vec<String>,
+}
+
+impl FromStr for Variants {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .rfind('=')
+            .ok_or_else(|| format!("expected <command>=<value>,<value>, got {:?}", s))?;
+        Ok(Variants {
+            selector: s[..split].to_string(),
+            values: s[split + 1..].split(',').map(str::to_string).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod tests {
+    use super::*;
+
+    fn finishing(success: bool, out: &'static str) -> MockExecutor {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(move |_| {
+            let mut child = MockChild::new();
+            child.expect_poll().return_once(move || {
+                Ok(Some(CommandOutput {
+                    success,
+                    out: out.to_string(),
+                    ..CommandOutput::default()
+                }))
+            });
+            Ok(child)
+        });
+        executor
+    }
+
+    fn running() -> MockExecutor {
+        let mut executor = MockExecutor::new();
+        executor.expect_start().return_once(|_| {
+            let mut child = MockChild::new();
+            child.expect_poll().returning(|| Ok(None));
+            child.expect_terminate().times(1).returning(|| Ok(()));
+            Ok(child)
+        });
+        executor
+    }
+
+    #[test]
+    fn single_variant_output_is_unchanged() {
+        let mut fan_out = FanOut::single(finishing(true, "out"));
+
+        let output = fan_out.start(&[]).unwrap().poll().unwrap().unwrap();
+
+        assert_eq!(
+            output,
+            CommandOutput {
+                success: true,
+                out: "out".to_string(),
+                ..CommandOutput::default()
+            }
+        );
+    }
+
+    #[test]
+    fn waits_for_every_variant() {
+        let mut fan_out = FanOut::new(vec![
+            ("stable".to_string(), finishing(true, "")),
+            ("nightly".to_string(), running()),
+        ]);
+
+        let mut child = fan_out.start(&[]).unwrap();
+
+        assert_eq!(child.poll().unwrap(), None);
+        child.terminate();
+    }
+
+    #[test]
+    fn succeeds_only_if_all_variants_succeed() {
+        let mut fan_out = FanOut::new(vec![
+            ("stable".to_string(), finishing(true, "a\n")),
+            ("nightly".to_string(), finishing(false, "b\n")),
+        ]);
+
+        let output = fan_out.start(&[]).unwrap().poll().unwrap().unwrap();
+
+        assert!(!output.success);
+        assert_eq!(
+            output.out,
+            "== stable (passed) ==\na\n== nightly (failed) ==\nb\n"
+        );
+    }
+
+    #[test]
+    fn parse_variants() {
+        assert_eq!(
+            "cargo +{} test=stable,nightly".parse::<Variants>().unwrap(),
+            Variants {
+                selector: "cargo +{} test".to_string(),
+                values: vec!["stable".to_string(), "nightly".to_string()],
+            }
+        );
+    }
+}
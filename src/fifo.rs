@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+/// A named pipe that other programs write command names to, one per line, to request runs.
+pub struct TriggerFifo {
+    path: PathBuf,
+    // Whether watchdo made the fifo, rather than reusing one, so it's removed again.
+    created: bool,
+    lines: Receiver<String>,
+}
+
+impl TriggerFifo {
+    /// Creates the fifo, or reuses one left behind, and starts reading it.
+    #[cfg(unix)]
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::FileTypeExt;
+
+        let created = match std::fs::metadata(path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => false,
+            Ok(_) => return Err(format!("{} exists and isn't a fifo", path.display()).into()),
+            Err(_) => {
+                let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+                // Safe since the path is a valid C string that outlives the call.
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                    let e = std::io::Error::last_os_error();
+                    return Err(format!("can't create fifo {}: {}", path.display(), e).into());
+                }
+                true
+            }
+        };
+
+        let (tx, lines) = channel();
+        let reading = path.to_path_buf();
+        std::thread::spawn(move || {
+            // Opening blocks until something opens it to write, and reading ends when the
+            // last writer closes it, so it's opened again for the next.
+            while let Ok(fifo) = std::fs::File::open(&reading) {
+                for line in BufReader::new(fifo).lines() {
+                    let line = match line {
+                        Ok(line) => line.trim().to_string(),
+                        Err(_) => break,
+                    };
+                    if !line.is_empty() && tx.send(line).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(TriggerFifo {
+            path: path.to_path_buf(),
+            created,
+            lines,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn create(_path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err("--trigger-fifo needs named pipes".into())
+    }
+
+    /// The next line that's been written, if any.
+    pub fn try_recv(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+}
+
+impl Drop for TriggerFifo {
+    fn drop(&mut self) {
+        if self.created {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[cfg(unix)]
+    fn reads_lines_from_each_writer() {
+        let dir = TestDir::new();
+        let path = dir.join("trigger");
+        let fifo = TriggerFifo::create(&path).unwrap();
+
+        let mut received = Vec::new();
+        for (written, lines) in [("cargo test\n\n", 1), ("all\n", 2)] {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .write_all(written.as_bytes())
+                .unwrap();
+            let started = Instant::now();
+            while received.len() < lines && started.elapsed() < Duration::from_secs(5) {
+                match fifo.try_recv() {
+                    Some(line) => received.push(line),
+                    None => std::thread::sleep(Duration::from_millis(10)),
+                }
+            }
+        }
+
+        assert_eq!(received, vec!["cargo test", "all"]);
+        drop(fifo);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reused_fifo_is_left_behind() {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        let dir = TestDir::new();
+        let path = dir.join("trigger");
+        let made = TriggerFifo::create(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        // Left behind, like by a watchdo that was killed.
+        std::mem::forget(made);
+
+        drop(TriggerFifo::create(&path).unwrap());
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(std::fs::metadata(&path).unwrap().file_type().is_fifo());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn existing_file_is_not_a_fifo() {
+        let dir = TestDir::new();
+        let path = dir.join("trigger");
+        std::fs::write(&path, "").unwrap();
+
+        assert!(TriggerFifo::create(&path).is_err());
+    }
+}
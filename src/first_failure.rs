@@ -0,0 +1,196 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use regex::Regex;
+use std::str::FromStr;
+
+/// How to find the failures in a command's output.
+#[derive(Debug, Clone)]
+pub enum Detector {
+    /// `cargo test`, whose failures start with `---- <test> stdout ----`.
+    Cargo,
+    /// Test Anything Protocol, whose failures start with `not ok`.
+    Tap,
+    /// pytest, whose failures start with a `____ <test> ____` line.
+    Pytest,
+    /// Failures start with a line matching the pattern, and go on until the next one.
+    Pattern(Regex),
+}
+
+impl Detector {
+    fn start(&self) -> Regex {
+        match self {
+            Detector::Cargo => Regex::new(r"^---- .+ ----$").unwrap(),
+            Detector::Tap => Regex::new(r"^not ok\b").unwrap(),
+            Detector::Pytest => Regex::new(r"^_{3,} .+ _{3,}$").unwrap(),
+            Detector::Pattern(pattern) => pattern.clone(),
+        }
+    }
+
+    /// A line after the failures, like a summary, which ends the one before it.
+    fn end(&self) -> Option<Regex> {
+        let end = match self {
+            Detector::Cargo => r"^failures:$",
+            Detector::Tap => r"^(ok\b|\d+\.\.\d+)",
+            Detector::Pytest => r"^={3,} .+ ={3,}$",
+            Detector::Pattern(_) => return None,
+        };
+        Some(Regex::new(end).unwrap())
+    }
+
+    /// Leaves out every failure after the first, putting a count of them in their place.
+    /// `None` when there's only one failure, or none.
+    fn first_failure(&self, text: &str) -> Option<String> {
+        let (start, end) = (self.start(), self.end());
+        let mut shown = String::new();
+        let mut seen = false;
+        let mut hiding = false;
+        let mut hidden = 0;
+        let mut cut = 0;
+        for line in text.split_inclusive('\n') {
+            let trimmed = line.trim_end();
+            if start.is_match(trimmed) {
+                hiding = seen;
+                if hiding {
+                    hidden += 1;
+                    if hidden == 1 {
+                        cut = shown.len();
+                    }
+                }
+                seen = true;
+            } else if end.as_ref().is_some_and(|e| e.is_match(trimmed)) {
+                hiding = false;
+            }
+            if !hiding {
+                shown.push_str(line);
+            }
+        }
+        if hidden == 0 {
+            return None;
+        }
+        let s = if hidden == 1 { "" } else { "s" };
+        shown.insert_str(cut, &format!("… [{} more failure{}]\n", hidden, s));
+        Some(shown)
+    }
+}
+
+impl FromStr for Detector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cargo" => Ok(Detector::Cargo),
+            "tap" => Ok(Detector::Tap),
+            "pytest" => Ok(Detector::Pytest),
+            pattern => Regex::new(pattern)
+                .map(Detector::Pattern)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Only shows a command's first failure, as `<command>:<detector>`, where the detector is
+/// `cargo`, `tap`, `pytest` or a regex matching the first line of each failure.
+#[derive(Debug, Clone)]
+pub struct FirstFailure {
+    pub selector: String,
+    detector: Detector,
+}
+
+impl FirstFailure {
+    /// The output to show for a failed run, if there's more than one failure in it.
+    pub fn apply(&self, output: &CommandOutput) -> Option<CommandOutput> {
+        if output.success {
+            return None;
+        }
+        let out = self.detector.first_failure(&output.out);
+        let err = self.detector.first_failure(&output.err);
+        if out.is_none() && err.is_none() {
+            return None;
+        }
+        Some(CommandOutput {
+            success: output.success,
+            warning: output.warning,
+            code: output.code,
+            out: out.unwrap_or_else(|| output.out.clone()),
+            err: err.unwrap_or_else(|| output.err.clone()),
+            duration: output.duration,
+            counts: output.counts,
+        })
+    }
+}
+
+impl FromStr for FirstFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, detector) = split_selector(s, "<command>:<detector>", Colons::Right)?;
+        Ok(FirstFailure {
+            selector: selector.to_string(),
+            detector: detector.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_first_cargo_failure_and_summary() {
+        let out = "running 3 tests\ntest a ... FAILED\ntest b ... FAILED\ntest c ... FAILED\n\n\
+                   failures:\n\n---- a stdout ----\npanicked at a\n\n---- b stdout ----\n\
+                   panicked at b\n\n---- c stdout ----\npanicked at c\n\n\
+                   failures:\n    a\n    b\n    c\n\ntest result: FAILED. 0 passed; 3 failed\n";
+
+        assert_eq!(
+            Detector::Cargo.first_failure(out).unwrap(),
+            "running 3 tests\ntest a ... FAILED\ntest b ... FAILED\ntest c ... FAILED\n\n\
+             failures:\n\n---- a stdout ----\npanicked at a\n\n… [2 more failures]\n\
+             failures:\n    a\n    b\n    c\n\ntest result: FAILED. 0 passed; 3 failed\n"
+        );
+    }
+
+    #[test]
+    fn keeps_passing_tap_results() {
+        let out =
+            "1..4\nnot ok 1 - a\n  # expected 1\nok 2 - b\nnot ok 3 - c\n  # expected 3\nok 4\n";
+
+        assert_eq!(
+            Detector::Tap.first_failure(out).unwrap(),
+            "1..4\nnot ok 1 - a\n  # expected 1\nok 2 - b\n… [1 more failure]\nok 4\n"
+        );
+    }
+
+    #[test]
+    fn single_failure_is_left_alone() {
+        assert_eq!(
+            Detector::Cargo.first_failure("---- a stdout ----\nboom\n"),
+            None
+        );
+        assert_eq!(Detector::Tap.first_failure("ok 1\n"), None);
+    }
+
+    #[test]
+    fn pattern_failures_go_until_the_next() {
+        let detector = "^FAIL ".parse::<Detector>().unwrap();
+
+        assert_eq!(
+            detector
+                .first_failure("FAIL a\n  at a.js\nFAIL b\n  at b.js\n")
+                .unwrap(),
+            "FAIL a\n  at a.js\n… [1 more failure]\n"
+        );
+    }
+
+    #[test]
+    fn parses_selector_and_detector() {
+        let first = "npm test:^FAIL: ".parse::<FirstFailure>().unwrap();
+        assert_eq!(first.selector, "npm test");
+        assert!(matches!(first.detector, Detector::Pattern(p) if p.as_str() == "^FAIL: "));
+        assert!(matches!(
+            "1:pytest".parse::<FirstFailure>().unwrap().detector,
+            Detector::Pytest
+        ));
+        assert!("cargo".parse::<FirstFailure>().is_err());
+    }
+}
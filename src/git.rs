@@ -0,0 +1,112 @@
+use crate::config::FileChanges;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The branch and dirty state of the repository watchdo runs in, for the status bar.
+pub struct GitStatus {
+    worktree: PathBuf,
+    git_dir: PathBuf,
+    head: FileChanges,
+    index: FileChanges,
+    branch: String,
+    dirty: bool,
+}
+
+impl GitStatus {
+    /// Finds the repository `dir` is in, if any.
+    pub fn find(dir: &Path) -> Option<Self> {
+        let dir = dir.canonicalize().ok()?;
+        let worktree = dir.ancestors().find(|d| d.join(".git").exists())?;
+        let git_dir = git_dir(worktree)?;
+        let mut status = GitStatus {
+            worktree: worktree.to_path_buf(),
+            head: FileChanges::new(git_dir.join("HEAD")),
+            index: FileChanges::new(git_dir.join("index")),
+            git_dir,
+            branch: String::new(),
+            dirty: false,
+        };
+        status.read();
+        Some(status)
+    }
+
+    /// Reads the status again if `.git` changed, as it does when switching branches or
+    /// committing, or if `files_changed`.
+    pub fn refresh(&mut self, files_changed: bool) {
+        // Both are checked, so neither reports an old change next time.
+        let head_changed = self.head.changed();
+        let index_changed = self.index.changed();
+        if head_changed || index_changed || files_changed {
+            self.read();
+        }
+    }
+
+    /// The branch, with a `*` when there are uncommitted changes.
+    pub fn label(&self) -> String {
+        format!("{}{}", self.branch, if self.dirty { "*" } else { "" })
+    }
+
+    fn read(&mut self) {
+        self.branch = std::fs::read_to_string(self.git_dir.join("HEAD"))
+            .map_or_else(|_| "?".to_string(), |head| branch(&head));
+        self.dirty = Command::new("git")
+            .arg("status")
+            .arg("--porcelain")
+            .current_dir(&self.worktree)
+            .output()
+            .is_ok_and(|o| o.status.success() && !o.stdout.is_empty());
+    }
+}
+
+/// The repository's git directory, following the `gitdir:` file worktrees and submodules have.
+fn git_dir(worktree: &Path) -> Option<PathBuf> {
+    let dot_git = worktree.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = std::fs::read_to_string(&dot_git).ok()?;
+    let path = contents.trim().strip_prefix("gitdir:")?.trim();
+    Some(worktree.join(path))
+}
+
+/// The branch name from `.git/HEAD`, or the start of the commit when it's detached.
+fn branch(head: &str) -> String {
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string()
+        }
+        None => head.chars().take(7).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn branch_from_head() {
+        assert_eq!(branch("ref: refs/heads/feature/login\n"), "feature/login");
+        assert_eq!(
+            branch("64666d0a1b2c3d4e5f60718293a4b5c6d7e8f901\n"),
+            "64666d0"
+        );
+    }
+
+    #[test]
+    fn follows_gitdir_file() {
+        let dir = TestDir::new();
+        let worktree = dir.path();
+        std::fs::write(worktree.join(".git"), "gitdir: ../repo/.git/worktrees/a\n").unwrap();
+
+        assert_eq!(
+            git_dir(worktree),
+            Some(worktree.join("../repo/.git/worktrees/a"))
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A file a command's stdout must match, as `<command>:<file>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Golden {
+    pub selector: String,
+    pub path: PathBuf,
+}
+
+impl Golden {
+    /// Fails `output` with a diff if it doesn't match the golden file.
+    pub fn check(&self, output: &mut CommandOutput) {
+        let expected = match std::fs::read_to_string(&self.path) {
+            Ok(expected) => expected,
+            Err(e) => {
+                output.success = false;
+                output.err.push_str(&format!(
+                    "watchdo: can't read golden file {}: {}\n",
+                    self.path.display(),
+                    e
+                ));
+                return;
+            }
+        };
+
+        if expected == output.out {
+            return;
+        }
+
+        output.success = false;
+        output.err.push_str(&format!(
+            "watchdo: output doesn't match {}\n",
+            self.path.display()
+        ));
+        output.err.push_str(&diff(&expected, &output.out));
+    }
+
+    pub fn update(&self, output: &CommandOutput) -> std::io::Result<()> {
+        std::fs::write(&self.path, &output.out)
+    }
+}
+
+impl FromStr for Golden {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, path) = split_selector(s, "<command>:<file>", Colons::Left)?;
+        Ok(Golden {
+            selector: selector.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Line diff of `expected` and `actual`, with `-` for expected lines and `+` for actual ones.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let a = expected.lines().collect::<Vec<_>>();
+    let b = actual.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            result.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            result.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        } else {
+            result.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn diff_of_equal_is_context() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), " a\n b\n");
+    }
+
+    #[test]
+    fn diff_shows_changes() {
+        assert_eq!(diff("a\nb\nc\n", "a\nx\nc\n"), " a\n+x\n-b\n c\n");
+    }
+
+    #[test]
+    fn diff_shows_additions_and_removals() {
+        assert_eq!(diff("a\n", "a\nb\n"), " a\n+b\n");
+        assert_eq!(diff("a\nb\n", "b\n"), "-a\n b\n");
+    }
+
+    #[test]
+    fn matching_output_is_unchanged() {
+        let dir = TestDir::new();
+        let path = dir.join("golden");
+        std::fs::write(&path, "out\n").unwrap();
+        let golden = Golden {
+            selector: "1".to_string(),
+            path,
+        };
+        let mut output = CommandOutput {
+            success: true,
+            out: "out\n".to_string(),
+            ..CommandOutput::default()
+        };
+
+        golden.check(&mut output);
+
+        assert!(output.success);
+        assert_eq!(output.err, "");
+    }
+
+    #[test]
+    fn mismatched_output_fails() {
+        let dir = TestDir::new();
+        let path = dir.join("golden");
+        std::fs::write(&path, "expected\n").unwrap();
+        let golden = Golden {
+            selector: "1".to_string(),
+            path,
+        };
+        let mut output = CommandOutput {
+            success: true,
+            out: "actual\n".to_string(),
+            ..CommandOutput::default()
+        };
+
+        golden.check(&mut output);
+
+        assert!(!output.success);
+        assert!(output.err.ends_with("+actual\n-expected\n"));
+    }
+}
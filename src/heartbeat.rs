@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+/// A line every so often saying watchdo is still watching, for when there's no status bar to
+/// show it's alive.
+pub struct Heartbeat {
+    period: Duration,
+    last: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(period: Duration) -> Self {
+        Heartbeat {
+            period,
+            last: Instant::now(),
+        }
+    }
+
+    /// The line to print once each period, with how many files are watched, if known, and how
+    /// long ago the latest change was.
+    pub fn beat(&mut self, files: Option<usize>, last_change: Option<Duration>) -> Option<String> {
+        if self.last.elapsed() < self.period {
+            return None;
+        }
+        self.last = Instant::now();
+        let time = chrono::Local::now().format("%H:%M:%S").to_string();
+        Some(line(&time, files, last_change))
+    }
+}
+
+fn line(time: &str, files: Option<usize>, last_change: Option<Duration>) -> String {
+    let files = match files {
+        Some(1) => ", 1 file".to_string(),
+        Some(n) => format!(", {} files", n),
+        None => String::new(),
+    };
+    let last_change = match last_change {
+        Some(ago) => format!("last change {} ago", short(ago)),
+        None => "no changes yet".to_string(),
+    };
+    format!("watchdo: {} still watching{}, {}", time, files, last_change)
+}
+
+/// A duration in its largest whole unit, like 3m.
+fn short(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s => format!("{}h", s / (60 * 60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn says_how_long_since_the_last_change() {
+        assert_eq!(
+            line("14:03:22", Some(120), Some(Duration::from_secs(200))),
+            "watchdo: 14:03:22 still watching, 120 files, last change 3m ago"
+        );
+        assert_eq!(
+            line("14:03:22", None, None),
+            "watchdo: 14:03:22 still watching, no changes yet"
+        );
+        assert_eq!(short(Duration::from_secs(7300)), "2h");
+    }
+
+    #[test]
+    fn beats_once_each_period() {
+        let mut heartbeat = Heartbeat::new(Duration::from_millis(50));
+        assert_eq!(heartbeat.beat(Some(1), None), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(heartbeat.beat(Some(1), None).is_some());
+        assert_eq!(heartbeat.beat(Some(1), None), None);
+    }
+}
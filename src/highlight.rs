@@ -0,0 +1,188 @@
+use crate::args::{split_selector, Colons};
+use colored::Color;
+use regex::Regex;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// Colors the lines of unified diffs in `output`: additions green, removals red and hunk
+/// headers cyan. Lines that are already colored are left alone. Nothing is colored without
+/// `colors`.
+pub fn color_diffs(output: &str, colors: bool) -> String {
+    output
+        .split_inclusive('\n')
+        .map(|line| {
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line, ""),
+            };
+            let style = if text.contains('\x1b') {
+                None
+            } else if text.starts_with("+++") || text.starts_with("---") {
+                Some(BOLD)
+            } else if text.starts_with("@@") {
+                Some(Color::Cyan.to_fg_str())
+            } else if text.starts_with('+') {
+                Some(Color::Green.to_fg_str())
+            } else if text.starts_with('-') {
+                Some(Color::Red.to_fg_str())
+            } else {
+                None
+            };
+            match style.filter(|_| colors) {
+                Some(style) => format!("{}{}", paint(text, style), newline),
+                None => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+const BOLD: &str = "1";
+
+/// `text` in an SGR `style`, like `31` for red.
+fn paint(text: &str, style: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style, text)
+}
+
+/// Cuts lines longer than `max` characters short, saying how much was left out. Shorter lines
+/// are left alone.
+pub fn clamp_lines(output: &str, max: usize) -> Cow<'_, str> {
+    if output.split('\n').all(|line| line.len() <= max) {
+        return Cow::Borrowed(output);
+    }
+
+    output
+        .split_inclusive('\n')
+        .map(|line| {
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line, ""),
+            };
+            match text.char_indices().nth(max) {
+                Some((cut, _)) => format!(
+                    "{}… [{} more bytes]{}",
+                    &text[..cut],
+                    text.len() - cut,
+                    newline
+                ),
+                None => line.to_string(),
+            }
+        })
+        .collect::<String>()
+        .into()
+}
+
+/// Output to color wherever it appears, as `<regex>:<color>`.
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pattern: Regex,
+    color: Color,
+}
+
+impl FromStr for Highlight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, color) = split_selector(s, "<pattern>:<color>", Colons::Left)?;
+        Ok(Highlight {
+            pattern: Regex::new(pattern).map_err(|e| e.to_string())?,
+            color: color
+                .parse()
+                .map_err(|_| format!("unknown color {:?}", color))?,
+        })
+    }
+}
+
+/// Colors the matches of `highlights` in `output`. Where matches overlap, the earliest wins, then
+/// the first highlight given. Lines that are already colored are left alone. Nothing is colored
+/// without `colors`.
+pub fn highlight(output: &str, highlights: &[Highlight], colors: bool) -> String {
+    if highlights.is_empty() || !colors {
+        return output.to_string();
+    }
+
+    output
+        .split_inclusive('\n')
+        .map(|line| {
+            if line.contains('\x1b') {
+                return line.to_string();
+            }
+            let mut matches = highlights
+                .iter()
+                .flat_map(|h| h.pattern.find_iter(line).map(move |m| (m, h.color)))
+                .filter(|(m, _)| !m.as_str().is_empty())
+                .collect::<Vec<_>>();
+            matches.sort_by_key(|(m, _)| m.start());
+
+            let mut highlighted = String::new();
+            let mut end = 0;
+            for (m, color) in matches {
+                if m.start() < end {
+                    continue;
+                }
+                highlighted.push_str(&line[end..m.start()]);
+                highlighted.push_str(&paint(m.as_str(), color.to_fg_str()));
+                end = m.end();
+            }
+            highlighted.push_str(&line[end..]);
+            highlighted
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_added_and_removed_lines() {
+        assert_eq!(
+            color_diffs("same\n-old\n+new\n", true),
+            "same\n\x1b[31m-old\x1b[0m\n\x1b[32m+new\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn leaves_colored_lines() {
+        assert_eq!(
+            color_diffs("\x1b[31m-old\x1b[0m", true),
+            "\x1b[31m-old\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn colors_nothing_without_colors() {
+        let highlights = vec!["ERROR:red".parse::<Highlight>().unwrap()];
+
+        assert_eq!(color_diffs("-old\n", false), "-old\n");
+        assert_eq!(highlight("ERROR\n", &highlights, false), "ERROR\n");
+    }
+
+    #[test]
+    fn clamps_only_long_lines() {
+        let long = format!("{}\n", "é".repeat(20));
+
+        assert!(matches!(
+            clamp_lines("short\nlines\n", 10),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            clamp_lines(&format!("short\n{}", long), 10),
+            format!("short\n{}… [20 more bytes]\n", "é".repeat(10))
+        );
+    }
+
+    #[test]
+    fn highlights_matches_in_their_color() {
+        let highlights = vec![
+            "ERROR:red".parse::<Highlight>().unwrap(),
+            "WARN(ING)?:yellow".parse().unwrap(),
+            "ERROR: .*:blue".parse().unwrap(),
+        ];
+
+        assert_eq!(
+            highlight("ERROR: disk full\nWARNING twice WARN\n", &highlights, true),
+            "\x1b[31mERROR\x1b[0m: disk full\n\x1b[33mWARNING\x1b[0m twice \x1b[33mWARN\x1b[0m\n"
+        );
+        assert!("ERROR:blurple".parse::<Highlight>().is_err());
+    }
+}
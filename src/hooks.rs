@@ -0,0 +1,158 @@
+use crate::args::{split_selector, Colons};
+use crate::command_history::CommandState;
+use crate::stream::Stream;
+use std::str::FromStr;
+
+/// A script to run whenever a command enters a state, as `<state>:<script>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    pub on: HookState,
+    pub script: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookState {
+    Running,
+    /// Finished, whether it passed or not.
+    Completed,
+    Passed,
+    Failed,
+    Terminated,
+    Retried,
+}
+
+impl Hook {
+    /// Whether the hook runs when a command enters `state`. `passed` is whether a completed
+    /// run had the expected outcome.
+    pub fn matches(&self, state: &CommandState, passed: bool) -> bool {
+        match (self.on, state) {
+            (HookState::Running, CommandState::Running) => true,
+            (HookState::Completed, CommandState::Completed(_)) => true,
+            (HookState::Passed, CommandState::Completed(_)) => passed,
+            (HookState::Failed, CommandState::Completed(_)) => !passed,
+            (HookState::Terminated, CommandState::Terminated(..)) => true,
+            (HookState::Retried, CommandState::Retried(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Starts the script with details of the command in its environment, without waiting for
+    /// it. Failing to start it is reported on `stream`.
+    pub fn run(&self, name: &str, state: &CommandState, passed: bool, stream: Stream) {
+        let mut command = std::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.script)
+            .env("WATCHDO_COMMAND", name)
+            .env("WATCHDO_STATE", state_name(state));
+        if let CommandState::Completed(output)
+        | CommandState::Terminated(output, _)
+        | CommandState::Retried(output) = state
+        {
+            command.env("WATCHDO_PASSED", (passed as u8).to_string());
+            if let Some(code) = output.code {
+                command.env("WATCHDO_EXIT_CODE", code.to_string());
+            }
+        }
+
+        spawn(command, &self.script, stream);
+    }
+}
+
+/// Starts an --on-green script, with the names of the passing commands in its environment,
+/// one per line.
+pub fn run_on_green(script: &str, passing: &[&str], stream: Stream) {
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(script)
+        .env("WATCHDO_PASSING", passing.join("\n"));
+    spawn(command, script, stream);
+}
+
+fn spawn(mut command: std::process::Command, script: &str, stream: Stream) {
+    match command.spawn() {
+        // Reap the script once it's done, so it doesn't linger as a zombie.
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => stream.println(format!("watchdo: failed to run hook {:?}: {}", script, e)),
+    }
+}
+
+fn state_name(state: &CommandState) -> &'static str {
+    match state {
+        CommandState::Requested => "requested",
+        CommandState::Running => "running",
+        CommandState::Completed(_) => "completed",
+        CommandState::Terminated(..) => "terminated",
+        CommandState::Retried(_) => "retried",
+    }
+}
+
+impl FromStr for HookState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(HookState::Running),
+            "completed" => Ok(HookState::Completed),
+            "passed" => Ok(HookState::Passed),
+            "failed" => Ok(HookState::Failed),
+            "terminated" => Ok(HookState::Terminated),
+            "retried" => Ok(HookState::Retried),
+            other => Err(format!(
+                "expected running, completed, passed, failed, terminated or retried, got {:?}",
+                other
+            )),
+        }
+    }
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (on, script) = split_selector(s, "<state>:<script>", Colons::Right)?;
+        Ok(Hook {
+            on: on.parse()?,
+            script: script.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::CommandOutput;
+
+    #[test]
+    fn parse_hook() {
+        assert_eq!(
+            "failed:notify-send 'tests: failed'"
+                .parse::<Hook>()
+                .unwrap(),
+            Hook {
+                on: HookState::Failed,
+                script: "notify-send 'tests: failed'".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_state_is_error() {
+        assert!("exploded:echo".parse::<Hook>().is_err());
+    }
+
+    #[test]
+    fn passed_and_failed_split_completed() {
+        let completed = CommandState::Completed(CommandOutput::default());
+        let hook = |s: &str| s.parse::<Hook>().unwrap();
+
+        assert!(hook("passed:x").matches(&completed, true));
+        assert!(!hook("passed:x").matches(&completed, false));
+        assert!(hook("failed:x").matches(&completed, false));
+        assert!(hook("completed:x").matches(&completed, false));
+        assert!(!hook("running:x").matches(&completed, true));
+    }
+}
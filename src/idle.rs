@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// How often `xprintidle` is run.
+const POLL: Duration = Duration::from_millis(250);
+
+/// Whether the user has stopped typing, from the X11 idle time reported by `xprintidle`.
+pub struct InputIdle {
+    period: Duration,
+    // The latest idle time and when it was read, kept up to date by a thread so checking
+    // doesn't wait on a process.
+    latest: Arc<Mutex<Option<(Instant, Duration)>>>,
+}
+
+impl InputIdle {
+    /// Input counts as idle once there's been none for `period`.
+    pub fn new(period: Duration) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let polled = Arc::downgrade(&latest);
+        std::thread::spawn(move || poll(polled));
+        InputIdle { period, latest }
+    }
+
+    pub fn is_idle(&mut self) -> bool {
+        // Without a way to tell, never hold runs back.
+        match *self.latest.lock().unwrap() {
+            Some((read, idle)) => idle + read.elapsed() >= self.period,
+            None => true,
+        }
+    }
+}
+
+/// Reads the idle time until the InputIdle is dropped, or it can't be read.
+fn poll(latest: Weak<Mutex<Option<(Instant, Duration)>>>) {
+    loop {
+        let idle = idle_time();
+        let latest = match latest.upgrade() {
+            Some(latest) => latest,
+            None => return,
+        };
+        *latest.lock().unwrap() = idle.map(|idle| (Instant::now(), idle));
+        if idle.is_none() {
+            return;
+        }
+        drop(latest);
+        std::thread::sleep(POLL);
+    }
+}
+
+fn idle_time() -> Option<Duration> {
+    let output = std::process::Command::new("xprintidle").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_idle_ms(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_idle_ms(s: &str) -> Option<Duration> {
+    s.trim().parse().ok().map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_time_counts_since_it_was_read() {
+        let mut idle = InputIdle {
+            period: Duration::from_secs(1),
+            latest: Arc::new(Mutex::new(None)),
+        };
+        assert!(idle.is_idle());
+
+        *idle.latest.lock().unwrap() = Some((Instant::now(), Duration::from_millis(100)));
+        assert!(!idle.is_idle());
+
+        let read = Instant::now() - Duration::from_secs(1);
+        *idle.latest.lock().unwrap() = Some((read, Duration::from_millis(100)));
+        assert!(idle.is_idle());
+    }
+
+    #[test]
+    fn parses_milliseconds() {
+        assert_eq!(parse_idle_ms("1500\n"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_idle_ms("no display"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use globset::{Glob, GlobMatcher};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Maps changed paths to the commands they affect.
+///
+/// Each non-empty line of the map file is a glob followed by whitespace and a command selector,
+/// e.g. `src/**/*.rs cargo test`. Lines starting with `#` are comments.
+pub struct ImpactMap {
+    rules: Vec<Rule>,
+    fallback: Fallback,
+}
+
+struct Rule {
+    glob: String,
+    matcher: GlobMatcher,
+    selector: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    All,
+    None,
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Fallback::All),
+            "none" => Ok(Fallback::None),
+            other => Err(format!("expected all or none, got {:?}", other)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Impact<'m> {
+    All,
+    Selected(Vec<&'m str>),
+}
+
+impl ImpactMap {
+    pub fn load(path: &Path, fallback: Fallback) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?, fallback)
+    }
+
+    pub fn parse(contents: &str, fallback: Fallback) -> Result<Self, Box<dyn Error>> {
+        let mut rules = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let split = line
+                .find(char::is_whitespace)
+                .ok_or_else(|| format!("expected '<glob> <command>', got {:?}", line))?;
+            let (glob, selector) = line.split_at(split);
+            rules.push(Rule {
+                glob: glob.to_string(),
+                matcher: Glob::new(glob)?.compile_matcher(),
+                selector: selector.trim().to_string(),
+            });
+        }
+        Ok(ImpactMap { rules, fallback })
+    }
+
+    /// The globs matching `path`, each with the command it selects.
+    pub fn matching_rules(&self, path: &Path) -> Vec<(&str, &str)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matcher.is_match(path))
+            .map(|rule| (rule.glob.as_str(), rule.selector.as_str()))
+            .collect()
+    }
+
+    pub fn fallback(&self) -> Fallback {
+        self.fallback
+    }
+
+    /// `paths` should be relative to the watched directory. An empty list means the changes are
+    /// unknown, which impacts everything.
+    pub fn impacted(&self, paths: &[PathBuf]) -> Impact<'_> {
+        if paths.is_empty() {
+            return Impact::All;
+        }
+
+        let mut selected = Vec::new();
+        for path in paths {
+            let mut matched = false;
+            for rule in &self.rules {
+                if rule.matcher.is_match(path) {
+                    matched = true;
+                    if !selected.contains(&rule.selector.as_str()) {
+                        selected.push(rule.selector.as_str());
+                    }
+                }
+            }
+
+            if !matched && self.fallback == Fallback::All {
+                return Impact::All;
+            }
+        }
+        Impact::Selected(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(ps: &[&str]) -> Vec<PathBuf> {
+        ps.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn matching_rules_select_commands() {
+        let map = ImpactMap::parse(
+            "# comment\nsrc/**/*.rs cargo test\ndocs/** mdbook build\n",
+            Fallback::All,
+        )
+        .unwrap();
+
+        assert_eq!(
+            map.impacted(&paths(&["src/main.rs"])),
+            Impact::Selected(vec!["cargo test"])
+        );
+        assert_eq!(
+            map.impacted(&paths(&["src/main.rs", "docs/index.md"])),
+            Impact::Selected(vec!["cargo test", "mdbook build"])
+        );
+    }
+
+    #[test]
+    fn unmatched_path_uses_fallback() {
+        let contents = "src/**/*.rs cargo test";
+
+        let all = ImpactMap::parse(contents, Fallback::All).unwrap();
+        assert_eq!(all.impacted(&paths(&["README.md"])), Impact::All);
+
+        let none = ImpactMap::parse(contents, Fallback::None).unwrap();
+        assert_eq!(
+            none.impacted(&paths(&["README.md"])),
+            Impact::Selected(vec![])
+        );
+    }
+
+    #[test]
+    fn unknown_changes_impact_everything() {
+        let map = ImpactMap::parse("src/**/*.rs cargo test", Fallback::None).unwrap();
+
+        assert_eq!(map.impacted(&[]), Impact::All);
+    }
+
+    #[test]
+    fn line_without_command_is_error() {
+        assert!(ImpactMap::parse("src/**/*.rs", Fallback::All).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+/// A JSON value, for the little JSON watchdo reads and writes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            at: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.at < parser.chars.len() {
+            return Err(format!(
+                "unexpected {:?} after the value",
+                parser.chars[parser.at]
+            ));
+        }
+        Ok(value)
+    }
+
+    /// The value of `key`, if this is an object with it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// How deeply arrays and objects can nest, so a request can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+    // How many arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl Parser {
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        if matches!(self.peek(), Some('[' | '{')) {
+            if self.depth == MAX_DEPTH {
+                return Err(format!("nested deeper than {}", MAX_DEPTH));
+            }
+            self.depth += 1;
+            let value = self.collection();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.at;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.at += 1;
+                }
+                let number = self.chars[start..self.at].iter().collect::<String>();
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("invalid number {:?}", number))
+            }
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn collection(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some('{') => {
+                self.at += 1;
+                let mut fields = Vec::new();
+                if !self.eat('}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        fields.push((key, self.value()?));
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Object(fields))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = self.next().ok_or("unterminated string")?;
+            match c {
+                '"' => return Ok(s),
+                '\\' => match self.next().ok_or("unterminated string")? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex()?;
+                        // Characters outside the BMP are escaped as a pair of surrogates.
+                        let low = self.chars.get(self.at..self.at + 2) == Some(&['\\', 'u']);
+                        if (0xd800..0xdc00).contains(&code) && low {
+                            self.at += 2;
+                            let low = self.hex()?;
+                            if (0xdc00..0xe000).contains(&low) {
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                        }
+                        s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// The four hex digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u32, String> {
+        let hex = (0..4)
+            .map(|_| self.next().ok_or("unterminated string"))
+            .collect::<Result<String, _>>()?;
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape \\u{}", hex))
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(format!("expected {}", word));
+            }
+        }
+        Ok(value)
+    }
+
+    fn whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.whitespace();
+        if self.peek() == Some(c) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}", c))
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.at += 1;
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests() {
+        let request = Json::parse(
+            r#" {"jsonrpc": "2.0", "id": 3, "method": "run", "params": [true, null]} "#,
+        )
+        .unwrap();
+
+        assert_eq!(request.get("id"), Some(&Json::Number(3.)));
+        assert_eq!(request.get("method").and_then(Json::as_str), Some("run"));
+        assert_eq!(
+            request.get("params"),
+            Some(&Json::Array(vec![Json::Bool(true), Json::Null]))
+        );
+        assert!(Json::parse("{\"id\": }").is_err());
+        assert!(Json::parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn round_trips_escapes() {
+        let value = Json::object(vec![("name", "echo \"a\\b\"\n\u{1}é".into())]);
+        let written = value.to_string();
+
+        assert_eq!(written, r#"{"name":"echo \"a\\b\"\n\u0001é"}"#);
+        assert_eq!(Json::parse(&written), Ok(value));
+    }
+
+    #[test]
+    fn decodes_surrogate_pairs() {
+        assert_eq!(
+            Json::parse(r#""\uD83D\uDE00 \uD83D""#),
+            Ok(Json::String("\u{1f600} \u{fffd}".to_string()))
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        assert!(Json::parse(&"[".repeat(100_000)).is_err());
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&nested).is_ok());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::args::{split_selector, Colons};
+use globset::{Glob, GlobMatcher};
+use std::path::Path;
+use std::str::FromStr;
+
+/// What to do with changes seen shortly after a command finishes, which are likely caused by
+/// the command itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopGuard {
+    /// Ignore the changes entirely.
+    Suppress,
+    /// Run the other commands, but not the ones that just finished.
+    Attribute,
+}
+
+impl FromStr for LoopGuard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "suppress" => Ok(LoopGuard::Suppress),
+            "attribute" => Ok(LoopGuard::Attribute),
+            other => Err(format!("expected suppress or attribute, got {:?}", other)),
+        }
+    }
+}
+
+/// Some of a command's files, as `<command>:<glob>`, like those it writes or those a server needs
+/// restarting for.
+#[derive(Debug, Clone)]
+pub struct CommandGlob {
+    pub selector: String,
+    glob: GlobMatcher,
+}
+
+impl CommandGlob {
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.glob.is_match(path)
+    }
+}
+
+impl FromStr for CommandGlob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, glob) = split_selector(s, "<command>:<glob>", Colons::Left)?;
+        Ok(CommandGlob {
+            selector: selector.to_string(),
+            glob: Glob::new(glob)
+                .map_err(|e| e.to_string())?
+                .compile_matcher(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_loop_guard() {
+        assert_eq!("suppress".parse(), Ok(LoopGuard::Suppress));
+        assert_eq!("attribute".parse(), Ok(LoopGuard::Attribute));
+        assert!("ignore".parse::<LoopGuard>().is_err());
+    }
+
+    #[test]
+    fn parses_selector_and_glob() {
+        let output: CommandGlob = "cargo build:target/**".parse().unwrap();
+
+        assert_eq!(output.selector, "cargo build");
+        assert!(output.is_match(Path::new("target/debug/watchdo")));
+        assert!(!output.is_match(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn output_glob_without_selector_is_error() {
+        assert!("target/**".parse::<CommandGlob>().is_err());
+    }
+
+    #[test]
+    fn invalid_output_glob_is_error() {
+        assert!("1:target/[".parse::<CommandGlob>().is_err());
+    }
+}
@@ -1,210 +1,1152 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 
-use colored::{ColoredString, Colorize};
-use notify::{watcher, RecursiveMode, Watcher};
+use chrono::NaiveTime;
+use globset::Glob;
+use notify::watcher;
+use regex::Regex;
 use std::error::Error;
 use std::ffi::OsString;
-use std::path::PathBuf;
-use std::sync::mpsc::{channel, TryRecvError};
-use std::time::{Duration, Instant};
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 
+mod action;
+use self::action::*;
+
+mod args;
+use self::args::*;
+
+mod badge;
+use self::badge::*;
+
+mod cgroup;
+use self::cgroup::*;
+
 mod command_history;
 use self::command_history::*;
 
+mod command_log;
+use self::command_log::*;
+
 mod command_runner;
 use self::command_runner::*;
 
+mod commands;
+use self::commands::*;
+
+mod config;
+use self::config::*;
+
+mod control;
+use self::control::*;
+
+mod diff_only;
+use self::diff_only::*;
+
+mod events;
+use self::events::*;
+
 mod executor;
 use self::executor::*;
 
+mod fan_out;
+use self::fan_out::*;
+
+mod fifo;
+use self::fifo::*;
+
+mod first_failure;
+use self::first_failure::*;
+
+mod git;
+use self::git::*;
+
+mod golden;
+use self::golden::*;
+
+mod highlight;
+use self::highlight::*;
+
+mod shuffle;
+use self::shuffle::*;
+
+mod since;
+use self::since::*;
+
+mod sparkline;
+use self::sparkline::*;
+
+mod theme;
+use self::theme::*;
+
+mod heartbeat;
+use self::heartbeat::*;
+
+mod hooks;
+use self::hooks::*;
+
+#[cfg_attr(not(feature = "input-idle"), allow(dead_code))]
+mod idle;
+use self::idle::*;
+
+mod impact;
+use self::impact::*;
+
+mod json;
+use self::json::*;
+
+mod loop_guard;
+use self::loop_guard::*;
+
+mod recent_events;
+use self::recent_events::*;
+
+mod require_output;
+use self::require_output::*;
+
+mod restart_filter;
+use self::restart_filter::*;
+
+mod remote;
+use self::remote::*;
+
+mod metrics;
+use self::metrics::*;
+
+mod notifier;
+use self::notifier::*;
+
+mod panes;
+use self::panes::*;
+
+mod phase;
+use self::phase::*;
+
+mod preset;
+use self::preset::*;
+
+mod result_format;
+use self::result_format::*;
+
+mod rpc;
+use self::rpc::*;
+
+mod run_if;
+use self::run_if::*;
+
+mod schedule;
+use self::schedule::*;
+
+mod status;
+use self::status::*;
+
+mod stream;
+use self::stream::*;
+
+mod task;
+use self::task::*;
+
+mod terminal;
+use self::terminal::*;
+
+#[cfg(test)]
+mod test_dir;
+
+mod trigger;
+use self::trigger::*;
+
+mod watch;
+use self::watch::*;
+
 #[derive(StructOpt, Debug)]
 struct Options {
-    #[structopt(long, parse(from_os_str), default_value = "./")]
-    watch_dir: PathBuf,
+    /// Directory to watch, may be repeated. Defaults to the current directory.
+    #[structopt(long, parse(from_os_str), number_of_values = 1)]
+    watch_dir: Vec<PathBuf>,
+
+    /// Also watch the real paths of symlinks, so changes made without going through the link
+    /// are seen.
+    #[structopt(long)]
+    watch_symlink_targets: bool,
+
+    /// How many more times to try watching the files when it fails, like when the directory is
+    /// on a volume that's still being mounted.
+    #[structopt(long, default_value = "5")]
+    watch_retries: usize,
+
+    /// How long to wait before trying to watch the files again, doubling with each try.
+    #[structopt(long, parse(try_from_str = parse_duration), default_value = "200ms")]
+    watch_retry_delay: Duration,
 
+    /// Only let changes to the files changed since a git ref, a time like "2024-05-01 09:30" or a
+    /// duration ago like 2h trigger runs, until a full run with R. The first run is for those
+    /// files.
+    #[structopt(long)]
+    since: Option<Since>,
+
+    /// Gitignore-style pattern of paths to ignore, anchored at each watched directory.
+    #[structopt(long, number_of_values = 1)]
+    ignore: Vec<String>,
+
+    /// Quick command, like cargo check, run before every other test. When it fails, the rest of
+    /// the run is skipped.
     #[structopt(long, parse(from_os_str))]
-    server: Option<OsString>,
+    canary: Option<OsString>,
+
+    /// Long-running command, like the app or a database, restarted with the latest passing
+    /// version. It waits for every test to pass, or with --phase only for the tests in earlier
+    /// phases. Can be given more than once.
+    #[structopt(long, parse(from_os_str), number_of_values = 1)]
+    server: Vec<OsString>,
+
+    /// Glyph for passing runs, instead of the theme's.
+    #[structopt(long)]
+    ok_str: Option<String>,
+
+    /// Status bar theme: default, colorblind, monochrome or one from --theme-file.
+    #[structopt(long, default_value = "default")]
+    theme: String,
+
+    /// File of themes, each a [name] followed by '<state> = <glyph> <color> on <color>' lines.
+    #[structopt(long, parse(from_os_str))]
+    theme_file: Option<PathBuf>,
+
+    /// Terminate commands whose resident memory exceeds this size (e.g. 512M, 2G).
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_memory: Option<u64>,
+
+    /// Limit the memory of everything watchdo runs, taken together (e.g. 4G). Needs cgroup v2
+    /// on Linux, and is ignored elsewhere.
+    #[structopt(long, parse(try_from_str = parse_size))]
+    cgroup_memory: Option<u64>,
+
+    /// Limit everything watchdo runs to this many CPUs' worth of time, taken together (e.g.
+    /// 1.5). Needs cgroup v2 on Linux, and is ignored elsewhere.
+    #[structopt(long)]
+    cgroup_cpus: Option<f64>,
+
+    /// Only run commands during these hours (e.g. 09:00-18:00). Changes outside are queued.
+    #[structopt(long, parse(try_from_str = parse_hours))]
+    active_hours: Option<(NaiveTime, NaiveTime)>,
+
+    /// Only run commands on these days (e.g. mon-fri). Changes outside are queued.
+    #[structopt(long, parse(try_from_str = parse_days))]
+    active_days: Option<Weekdays>,
+
+    /// Don't reprint output that only differs from the last printed output by whitespace.
+    #[structopt(long)]
+    collapse_whitespace: bool,
+
+    /// Only print a line saying how much output a failing run had, instead of the output
+    /// itself. `e<n>` expands the output of the command at that position.
+    #[structopt(long)]
+    collapse_output: bool,
+
+    /// File mapping path globs to the commands they affect, one '<glob> <command>' per line.
+    /// Commands can be referred to by their text, their position (starting at 1) or 'server'
+    /// for every server.
+    #[structopt(long, parse(from_os_str))]
+    impact_map: Option<PathBuf>,
+
+    /// What to run when a change matches no rule in the impact map: all or none.
+    #[structopt(long, default_value = "all")]
+    impact_fallback: Fallback,
+
+    /// Keep at most this many bytes of each command's stdout (e.g. 64K). The end is kept.
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_stdout_bytes: Option<u64>,
+
+    /// Keep at most this many bytes of each command's stderr (e.g. 64K). The end is kept.
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_stderr_bytes: Option<u64>,
+
+    /// Never start a command sooner than this after its previous run started (e.g. 10s).
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    min_interval: Option<Duration>,
+
+    /// Terminate a test that's still running after this long (e.g. 5m), e.g. one stuck waiting
+    /// for input. Servers are never timed out.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    timeout: Option<Duration>,
+
+    /// Stop a test that hasn't written any output for this long, like 2m, however long it has
+    /// been running. For long commands that show their progress.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    inactivity_timeout: Option<Duration>,
+
+    /// Command to give watchdo's stdin, by text or position, for commands that prompt for
+    /// input. Other commands read from /dev/null. Interactive commands aren't read at all then,
+    /// so everything typed goes to the command; use --rpc or --trigger-fifo to control watchdo.
+    #[structopt(long, number_of_values = 1)]
+    inherit_stdin: Vec<String>,
+
+    /// File to give a command as its stdin, as <command>:<path>. It's read from the start for
+    /// every run.
+    #[structopt(long, number_of_values = 1)]
+    stdin_file: Vec<StdinFile>,
+
+    /// Run a command once per value, substituting it for {} in the command, as
+    /// <command>=<value>,<value>. The command only passes if every variant passes.
+    #[structopt(long, number_of_values = 1)]
+    variants: Vec<Variants>,
+
+    /// Only restart a server for changes to files matching a glob, as <server>:<glob>. Other
+    /// changes still run the tests.
+    #[structopt(long, number_of_values = 1)]
+    restart_on: Vec<CommandGlob>,
+
+    /// Restart a server without downtime, as <server>:<regex>. The new one is started while the
+    /// old one keeps running, which is stopped once the new one's output matches the regex, like
+    /// "Listening on". The server needs to allow sharing its port, like with SO_REUSEPORT.
+    #[structopt(long, number_of_values = 1)]
+    graceful_restart: Vec<GracefulRestart>,
+
+    /// Servers to start once and keep running, like a database or a log tailer. Changes,
+    /// restarting the servers and turning them off leave them running until watchdo exits.
+    #[structopt(long, number_of_values = 1)]
+    persistent: Vec<String>,
+
+    /// Server configuration files, like .env. When only these change, the servers aren't
+    /// restarted and --server-config-reload runs instead.
+    #[structopt(long, parse(try_from_str = Glob::new), number_of_values = 1)]
+    server_config: Vec<Glob>,
+
+    /// Command that has the running servers reload their configuration, like sending them
+    /// SIGHUP, run when only --server-config files change.
+    #[structopt(long, parse(from_os_str), requires = "server-config")]
+    server_config_reload: Option<OsString>,
+
+    /// Files a command writes, as <command>:<glob>. Changes only to these files right after the
+    /// command finishes don't trigger another run.
+    #[structopt(long, number_of_values = 1)]
+    command_output: Vec<CommandGlob>,
+
+    /// Guard against commands triggering themselves: suppress ignores changes to the
+    /// --command-output files of the commands that just finished, attribute only skips
+    /// re-running the commands that just finished.
+    #[structopt(long)]
+    loop_guard: Option<LoopGuard>,
+
+    /// How long after a command finishes its changes are attributed to it.
+    #[structopt(long, parse(try_from_str = parse_duration), default_value = "1s")]
+    loop_guard_window: Duration,
+
+    /// Where to write the status bar and watchdo's own messages: stdout or stderr.
+    #[structopt(long, default_value = "stdout")]
+    status_stream: Stream,
+
+    /// Where to write command output: split (stdout and stderr as the command wrote them),
+    /// stdout or stderr.
+    #[structopt(long, default_value = "split")]
+    output_stream: OutputRouting,
+
+    /// Get changes from watchman, which scales to huge repositories, instead of watching every
+    /// file. Falls back to watching files if watchman or watchman-wait isn't installed. Files
+    /// aren't walked with watchman, so --watch-symlink-targets has no effect and --heartbeat
+    /// doesn't count the watched files, though --ignore still filters the changes.
+    #[structopt(long)]
+    watchman: bool,
+
+    /// Also take changes from a command that prints one changed path per line, for changes that
+    /// can't be watched locally, like 'ssh host watchman-wait -m 0 /src'. Relative paths are
+    /// relative to the current directory. Can be given more than once.
+    #[structopt(long, number_of_values = 1)]
+    events_from: Vec<String>,
+
+    /// Run the commands for each line written to stdin, like `producer | watchdo
+    /// --stdin-trigger 'cargo test'`, as well as for changes. Interactive commands are then read
+    /// from the terminal instead.
+    #[structopt(long, conflicts_with_all = &["commands-stdin", "inherit-stdin"])]
+    stdin_trigger: bool,
+
+    /// Only run for the lines written to stdin that match this regex.
+    #[structopt(long, requires = "stdin-trigger")]
+    stdin_trigger_filter: Option<Regex>,
+
+    /// Treat files being created in a directory, like an archive being extracted, as one change
+    /// once nothing in it has changed for this long (e.g. 500ms). Changes elsewhere aren't held
+    /// back, and a burst ends after 20 windows even if it hasn't settled.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    burst_window: Option<Duration>,
+
+    /// Only count changes to a file when its content matches a pattern, as <path>:<regex>.
+    #[structopt(long, number_of_values = 1)]
+    trigger_on_content: Vec<ContentTrigger>,
+
+    /// Also run a command periodically regardless of changes, as <command>:<duration>.
+    #[structopt(long, number_of_values = 1)]
+    interval: Vec<Interval>,
+
+    /// Don't run a command for changes for a while after it passes, as <command>:<duration>.
+    /// Other commands still run, and it runs once the cooldown ends if anything changed.
+    #[structopt(long, number_of_values = 1)]
+    success_cooldown: Vec<SuccessCooldown>,
+
+    /// Ring the terminal bell when a command fails.
+    #[structopt(long)]
+    bell: bool,
+
+    /// Show a desktop notification when a command fails.
+    #[structopt(long)]
+    desktop_notify: bool,
+
+    /// Only notify after a command has failed this many times in a row.
+    #[structopt(long, default_value = "1")]
+    notify_after: usize,
+
+    /// After notifying for a command, hold back its notifications for this long, like 30s. If
+    /// it's still failing then, they're merged into one.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    notify_cooldown: Option<Duration>,
+
+    /// Command whose first run only warms up caches, by text or position. Its result is
+    /// discarded and later runs are the ones that count.
+    #[structopt(long, number_of_values = 1)]
+    warmup: Vec<String>,
+
+    /// File a command's stdout must match for it to pass, as <command>:<file>.
+    #[structopt(long, number_of_values = 1)]
+    golden: Vec<Golden>,
+
+    /// Pattern a command's output must contain for it to pass, even when it exits with 0, as
+    /// <command>:<regex>.
+    #[structopt(long, number_of_values = 1)]
+    require_output: Vec<RequireOutput>,
+
+    /// File a command's output is appended to after each run, as <command>:<file>.
+    #[structopt(long, number_of_values = 1)]
+    command_log: Vec<CommandLog>,
+
+    /// Keep what's already in the --command-log files instead of emptying them at start.
+    #[structopt(long)]
+    command_log_append: bool,
+
+    /// Format of a command's test results, to show how many passed, failed and were skipped, as
+    /// <command>:<junit|tap>.
+    #[structopt(long, number_of_values = 1)]
+    format_parser: Vec<FormatParser>,
+
+    /// Only show the first failure in a command's output, with a count of the rest, as
+    /// <command>:<detector>. The detector is cargo, tap, pytest or a regex matching the first
+    /// line of each failure.
+    #[structopt(long, number_of_values = 1)]
+    first_failure: Vec<FirstFailure>,
+
+    /// Only show the changes a formatter would make, like in check mode, as <command>:<format>.
+    /// The format is rustfmt, diff for unified diffs, or a regex matching the lines to keep,
+    /// like "^\[warn\] " for prettier's file list. Output without changes is shown in full.
+    #[structopt(long, number_of_values = 1)]
+    diff_only: Vec<DiffOnly>,
+
+    /// Treat an exit code as passing, a warning or failing, as <code>:<pass|warn|fail>.
+    #[structopt(long, number_of_values = 1)]
+    code_status: Vec<CodeStatus>,
+
+    /// Run every command through this program, like `time` or `strace -f`.
+    #[structopt(long, parse(from_os_str))]
+    wrapper: Option<OsString>,
+
+    /// Run commands in a login shell, your $SHELL with -lc, so tools set up in your profile,
+    /// like nvm or rbenv, are found as in your terminal.
+    #[structopt(long)]
+    login_shell: bool,
+
+    /// Also run servers through the wrapper.
+    #[structopt(long)]
+    wrap_server: bool,
+
+    /// Command expected to fail, by text or position. Its failures are shown as expected and
+    /// don't stop later commands, while passing is shown as a warning.
+    #[structopt(long, number_of_values = 1)]
+    expect_fail: Vec<String>,
+
+    /// Color the lines of diffs in command output when writing to a terminal.
+    #[structopt(long)]
+    color_diffs: bool,
+
+    /// Color output matching a regex when writing to a terminal, as <regex>:<color>, like
+    /// ERROR:red.
+    #[structopt(long, number_of_values = 1)]
+    highlight: Vec<Highlight>,
+
+    /// Characters of each output line to print. Longer lines, like a serialized blob, are cut
+    /// short.
+    #[structopt(long, default_value = "10000")]
+    max_line_length: usize,
+
+    /// Regex of output that marks a failure as flaky, so the command is run again.
+    #[structopt(long)]
+    flaky_pattern: Option<Regex>,
+
+    /// How many times in a row a flaky failure is retried.
+    #[structopt(long, default_value = "2")]
+    flaky_retries: usize,
+
+    /// Write an SVG badge showing whether the commands pass to this file whenever it changes.
+    #[structopt(long, parse(from_os_str))]
+    badge: Option<PathBuf>,
+
+    /// Put a command in a phase, as `<command>:<phase>`. Commands in a phase run together, after
+    /// every command in earlier phases passed. By default each command is its own phase, by its
+    /// position, which runs before the commands given that number.
+    #[structopt(long, number_of_values = 1)]
+    phase: Vec<Phase>,
+
+    /// Command, by text or position, whose state alone is the overall status for the badge and
+    /// notifications. The others still run and show in the status bar.
+    #[structopt(long)]
+    primary: Option<String>,
 
-    #[structopt(long, default_value = "✓")]
-    ok_str: String,
+    /// Don't start runs while this many children are running, as a safety net against runaway
+    /// restarts.
+    #[structopt(long, default_value = "64")]
+    max_children: usize,
+
+    /// Count runs watchdo terminates on purpose, like server restarts, as failures. Runs
+    /// terminated for going over --timeout or --max-memory always count.
+    #[structopt(long)]
+    restarts_count_as_failures: bool,
+
+    /// Start a phase once the earlier ones finished, even if they failed.
+    #[structopt(long)]
+    run_after_failure: bool,
+
+    /// Only run a command once another has exited with a certain code, as
+    /// <command>:<prerequisite>=<code>. Otherwise its runs are skipped. It runs outside the
+    /// phases, as soon as the prerequisite finishes.
+    #[structopt(long, number_of_values = 1)]
+    run_if: Vec<RunIf>,
+
+    /// Run one test at a time in order, even ones sharing a --phase, and run every one of them
+    /// even after a failure.
+    #[structopt(long)]
+    sequential: bool,
+
+    /// Run the tests in a different order each time, to catch ones that depend on another
+    /// running first. Tests with a --phase stay in their phase.
+    #[structopt(long)]
+    shuffle: bool,
+
+    /// Seed for --shuffle, to repeat the orders of an earlier run.
+    #[structopt(long, requires = "shuffle")]
+    shuffle_seed: Option<u64>,
+
+    /// Serve Prometheus metrics about the commands at `/metrics` on this address.
+    #[structopt(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Print which changed paths triggered each run above the status bar.
+    #[structopt(long)]
+    watch_change_summary: bool,
+
+    /// Print a rule between runs, with when each started and what triggered it, to find where
+    /// the latest run's output starts.
+    #[structopt(long)]
+    separators: bool,
+
+    /// Hold runs back until there's been no keyboard or mouse input for this long.
+    #[cfg(feature = "input-idle")]
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    defer_while_typing: Option<Duration>,
+
+    /// Instead of printing each failure as it happens, print the output of every command that's
+    /// currently failing together.
+    #[structopt(long)]
+    fail_summary: bool,
+
+    /// Hold back commands' output until no test is running, then print it all together, each
+    /// labeled with its command.
+    #[structopt(long)]
+    defer_output: bool,
+
+    /// Command whose output is shown as it's written, by text or position, so a long run's log
+    /// grows in place. Best with --pinned-status.
+    #[structopt(
+        long,
+        number_of_values = 1,
+        conflicts_with_all = &["defer-output", "panes", "oneline"]
+    )]
+    live_output: Vec<String>,
+
+    /// Run a script whenever a command enters a state, as `<state>:<script>`. States are
+    /// running, completed, passed, failed, terminated and retried. The script gets
+    /// WATCHDO_COMMAND, WATCHDO_STATE, WATCHDO_PASSED and WATCHDO_EXIT_CODE in its environment.
+    #[structopt(long, number_of_values = 1)]
+    on: Vec<Hook>,
+
+    /// Run a script each time every test starts passing, like tagging the commit or copying
+    /// build artifacts. It runs once until something fails or runs again, and gets the passing
+    /// commands in WATCHDO_PASSING, one per line.
+    #[structopt(long, number_of_values = 1)]
+    on_green: Vec<String>,
+
+    /// Do something other than running the commands when matching files change, as
+    /// <glob>:<action>. Actions are `touch <path>`, `signal <signal> <pid|pid file>`,
+    /// `print <text>` with {} for the path, and `run` to also run the commands.
+    #[structopt(long, number_of_values = 1)]
+    on_change: Vec<Reaction>,
+
+    /// Show how long each running command has been going next to its cell, like `? 12s`.
+    #[structopt(long)]
+    show_elapsed: bool,
+
+    /// Fill the empty part of the status bar in the color of the overall health, like green when
+    /// everything passes, so it can be seen out of the corner of an eye.
+    #[structopt(long)]
+    health_tint: bool,
+
+    /// Don't run anything until the first change, for when everything is known to pass. Until
+    /// then, the status bar shows each command as idle.
+    #[structopt(long, conflicts_with = "since")]
+    idle_start: bool,
+
+    /// Show a sparkline of each command's recent run durations next to its cells.
+    #[structopt(long)]
+    sparkline: bool,
+
+    /// Show the latest notable events under the status bar, like a test starting to fail or a
+    /// server restarting, with the time they happened. Up to this many are shown at once.
+    #[structopt(long)]
+    recent_events: Option<usize>,
+
+    /// How long each of --recent-events is shown for.
+    #[structopt(long, parse(try_from_str = parse_duration), default_value = "1m")]
+    recent_events_for: Duration,
+
+    /// Show the git branch at the start of the status bar, with a * when the working tree has
+    /// uncommitted changes.
+    #[structopt(long)]
+    show_git: bool,
+
+    /// Print a line this often saying watchdo is still watching, with how long ago the latest
+    /// change was, for when it runs unattended.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    heartbeat: Option<Duration>,
+
+    /// Keep the status bar pinned to the bottom of the terminal, with output scrolling above it.
+    #[structopt(long)]
+    pinned_status: bool,
+
+    /// Show each command's latest output in its own pane, switched with the number keys.
+    #[structopt(long)]
+    panes: bool,
+
+    /// Only show a one-line count of passing, failing and running commands, updated in place,
+    /// for status bars like tmux's. Command output isn't shown.
+    #[structopt(long, conflicts_with_all = &["panes", "pinned-status"])]
+    oneline: bool,
+
+    /// With --oneline, print each new status on its own line instead of updating it in place.
+    #[structopt(long, requires = "oneline")]
+    oneline_newlines: bool,
+
+    /// File of extra arguments, one option or command per line, read before the command line.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Section of the --config file to also read, like [ci], for options and commands that only
+    /// apply in that environment.
+    #[structopt(long, requires = "config")]
+    profile: Option<String>,
+
+    /// Environment variable to run every command with, as <key>=<value>.
+    #[structopt(long, number_of_values = 1)]
+    env: Vec<EnvVar>,
+
+    /// Command to run in place of another, as <command>:<new command>. The command is selected
+    /// by its text, position or as a server.
+    #[structopt(name = "override", long = "override", number_of_values = 1)]
+    override_command: Vec<CommandOverride>,
+
+    /// Command not to run, by its text, position or as a server. Positions in other options
+    /// count only the commands that are left.
+    #[structopt(long, number_of_values = 1)]
+    disable: Vec<String>,
+
+    /// Write the options and commands in use, after applying the --config file, --profile,
+    /// --override and --disable, to a file that --config can read, then exit.
+    #[structopt(long, parse(from_os_str))]
+    dump_config: Option<PathBuf>,
+
+    /// Start over with a fresh watchdo when the --config file changes.
+    #[structopt(long)]
+    restart_on_watchdo_config_change: bool,
+
+    /// Print which commands a change to this path would run and why, then exit without running
+    /// anything.
+    #[structopt(long, parse(from_os_str))]
+    explain: Option<PathBuf>,
+
+    /// Unix socket to take JSON-RPC requests on, one per line, from editors and other tools. The
+    /// methods are run, pause, resume, restart_server, toggle_server, clear_history,
+    /// update_golden and status.
+    #[structopt(long, parse(from_os_str))]
+    rpc: Option<PathBuf>,
+
+    /// Named pipe to create and read command names from, one per line, requesting runs of them
+    /// like `echo 'cargo test' > <path>`. Commands can also be given by position, servers as
+    /// "server", and "all" runs everything like R.
+    #[structopt(long, parse(from_os_str))]
+    trigger_fifo: Option<PathBuf>,
+
+    /// Read additional commands from stdin, one per line, before starting. Interactive
+    /// commands are then read from the terminal instead.
+    #[structopt(long)]
+    commands_stdin: bool,
+
+    /// Command that only runs when asked for, by typing :<name>, as <name>:<command>. Changes
+    /// never run it.
+    #[structopt(long, number_of_values = 1)]
+    task: Vec<Task>,
+
+    /// Command in a named set to switch to by typing :preset <name>, which stops the commands
+    /// outside it, as <name>:<command>. Typing :preset alone switches back to every command.
+    #[structopt(long, number_of_values = 1)]
+    preset: Vec<Preset>,
 
     #[structopt(parse(from_os_str))]
     command: Vec<OsString>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let options = Options::from_args();
+    restore_on_panic();
+
+    let mut args = std::env::args_os().collect::<Vec<_>>();
+    let mut options = Options::from_iter(&args);
+    if let Some(config) = &options.config {
+        let config_args = load_args(config, options.profile.as_deref())?;
+        args.splice(1..1, config_args);
+        options = Options::from_iter(&args);
+    }
+    if options.watch_dir.is_empty() {
+        options.watch_dir.push(PathBuf::from("./"));
+    }
+    if options.commands_stdin {
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                options.command.push(line.into());
+            }
+        }
+    }
+    Commands::resolve(&mut options);
+    if let Some(path) = &options.dump_config {
+        std::fs::write(path, dump_args(&args, &options))?;
+        return Ok(());
+    }
 
     let (tx, rx) = channel();
+    let forwarded_tx = tx.clone();
 
     let mut watcher = watcher(tx, Duration::from_millis(100))?;
 
-    for result in ignore::WalkBuilder::new(options.watch_dir.clone())
-        .follow_links(true)
-        .build()
-    {
-        watcher.watch(result?.path(), RecursiveMode::NonRecursive)?;
+    let use_watchman = options.watchman && watchman_available();
+    if options.watchman && !use_watchman {
+        options
+            .status_stream
+            .println("watchdo: watchman isn't available, watching files directly");
+    }
+    if use_watchman && options.watch_symlink_targets {
+        options
+            .status_stream
+            .println("watchdo: --watch-symlink-targets has no effect with --watchman");
+    }
+    let status = options.status_stream;
+    let roots = with_retries(
+        options.watch_retries,
+        options.watch_retry_delay,
+        || {
+            let mut roots = WatchRoots::new(&options.watch_dir, &options.ignore)?;
+            if !use_watchman {
+                roots.watch(&mut watcher, options.watch_symlink_targets, status)?;
+            }
+            Ok(roots)
+        },
+        |e, delay| status.println(format!("watchdo: {}, trying again in {:?}", e, delay)),
+    )?;
+    let roots = Rc::new(roots);
+
+    let mut events = Events::new(rx);
+    let resolve_roots = roots.clone();
+    events.set_resolve(move |p| resolve_roots.through_links(WatchRoots::absolute(&p)));
+    let ignore_roots = roots.clone();
+    events.add_filter("--ignore or version control", move |p| {
+        !ignore_roots.is_ignored(p)
+    });
+    for trigger in options.trigger_on_content.drain(..) {
+        let trigger = trigger.canonicalize();
+        events.add_filter("--trigger-on-content", move |p| trigger.allows(p));
+    }
+    if let Some(window) = options.burst_window {
+        events.set_burst_window(window);
     }
+    let since = match &options.since {
+        Some(since) => Some(since.changed(&roots)?),
+        None => None,
+    };
+    let scope = since.clone().map(SinceScope::new).unwrap_or_default();
+    let since_scope = scope.clone();
+    events.add_filter("--since", move |p| since_scope.allows(p));
 
-    let mut commands = Commands::new(&options.command, options.server);
-    commands.request_run();
+    let mut commands = Commands::new(&options)?;
+    if let Some(path) = &options.explain {
+        explain(path, &events, &roots, &commands);
+        return Ok(());
+    }
+    if let Some(seed) = commands.shuffle_seed() {
+        options.status_stream.println(format!(
+            "watchdo: shuffling the run order, repeat it with --shuffle-seed {}",
+            seed
+        ));
+    }
+    for command in &options.events_from {
+        forward_events(
+            command,
+            Path::new(""),
+            forwarded_tx.clone(),
+            options.status_stream,
+        )?;
+    }
+    if use_watchman {
+        for dir in &options.watch_dir {
+            let command = watchman_command(dir);
+            forward_events(&command, dir, forwarded_tx.clone(), options.status_stream)?;
+        }
+    }
+    match &since {
+        _ if options.idle_start => options
+            .status_stream
+            .println("watchdo: idle, waiting for the first change before running anything"),
+        // An empty list would mean the changes are unknown, which runs everything.
+        Some(files) if files.is_empty() => options
+            .status_stream
+            .println("watchdo: no files changed since --since, waiting for a full run (R)"),
+        Some(files) => {
+            options.status_stream.println(format!(
+                "watchdo: only running for files changed since --since ({} of them), until a \
+                 full run (R)",
+                files.len()
+            ));
+            let files = files.iter().map(|f| roots.relative(f)).collect::<Vec<_>>();
+            commands.request_run(&files);
+        }
+        None => commands.request_run(&[]),
+    }
 
+    let stdin_triggers = options
+        .stdin_trigger
+        .then(|| stdin_triggers(options.stdin_trigger_filter.clone(), options.status_stream));
+    let controls = ControlInput::choose(
+        options.commands_stdin || options.stdin_trigger,
+        !options.inherit_stdin.is_empty(),
+    )
+    .read();
+    let mut pinned = if options.pinned_status {
+        Some(PinnedStatus::new(options.status_stream))
+    } else {
+        None
+    };
+    let mut panes = if options.panes {
+        Some(Panes::new(options.status_stream))
+    } else {
+        None
+    };
+    let mut config_changes = options
+        .config
+        .as_deref()
+        .filter(|_| options.restart_on_watchdo_config_change)
+        .map(FileChanges::new);
+    let mut badge = options.badge.clone().map(Badge::new);
+    let mut git = if options.show_git {
+        GitStatus::find(Path::new("."))
+    } else {
+        None
+    };
+    let mut heartbeat = options.heartbeat.map(Heartbeat::new);
+    // Files aren't watched one by one through watchman.
+    let watched_files = Some(roots.watched_files()).filter(|_| !use_watchman);
+    let mut metrics_server = options.metrics_addr.map(MetricsServer::bind).transpose()?;
+    let rpc = options.rpc.as_deref().map(RpcServer::bind).transpose()?;
+    let trigger_fifo = options
+        .trigger_fifo
+        .as_deref()
+        .map(TriggerFifo::create)
+        .transpose()?;
+    let mut shown_trigger = commands.last_trigger().0;
+    // Output held back by --defer-output, as each command's name, stderr and stdout.
+    let mut deferred = Vec::new();
     let mut last_printed = None;
+    let mut last_oneline = None;
+    let mut last_region = Vec::new();
+    // The command whose live output was printed last.
+    let mut last_live = None;
+    // Until now, Ctrl-C stops watchdo right away, like while it reads --commands-stdin.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let on_interrupt = interrupted.clone();
+    ctrlc::set_handler(move || on_interrupt.store(true, Ordering::SeqCst))?;
     loop {
-        loop {
-            match rx.try_recv() {
-                Err(TryRecvError::Empty) => break,
-                Err(e @ TryRecvError::Disconnected) => Err(e)?,
-                Ok(_) => commands.request_run(),
-            }
+        if interrupted.load(Ordering::SeqCst) {
+            commands.shutdown()?;
+            remove_scratch_dir();
+            drop(panes);
+            drop(pinned);
+            drop(rpc);
+            drop(trigger_fifo);
+            std::process::exit(130);
+        }
+        if config_changes.as_mut().is_some_and(FileChanges::changed) {
+            commands.shutdown()?;
+            remove_scratch_dir();
+            // The new process won't know to restore the terminal.
+            drop(panes);
+            drop(pinned);
+            return Err(reexec());
         }
 
-        commands.tick(|output| {
-            eprintln!("{}", output.err);
-            println!("{}", output.out);
-        })?;
-
-        let width = term_size::dimensions().map(|d| d.0).unwrap_or(80);
-        let to_print = commands.print(width, &options.ok_str);
-        if last_printed.as_ref() != Some(&to_print) {
-            for p in to_print.iter() {
-                print!("{}", p);
+        while let Ok(control) = controls.try_recv() {
+            match control {
+                Ok(control) => {
+                    if control == Control::RunAll {
+                        scope.lift();
+                    }
+                    let for_panes = panes.as_mut().map(|p| p.control(&control));
+                    if for_panes != Some(true) {
+                        commands.control(control)?;
+                    }
+                }
+                Err(e) => options.status_stream.println(e),
             }
-            println!("");
-
-            last_printed = Some(to_print);
         }
 
-        std::thread::sleep(Duration::from_millis(10));
-    }
-}
-
-struct Commands {
-    last_request: Option<Instant>,
-    debounce: Duration,
-    tests: Vec<CommandHistory<SubprocessExecutor>>,
-    server: Option<CommandHistory<SubprocessExecutor>>,
-}
-
-impl Commands {
-    fn new(tests: &[OsString], server: Option<OsString>) -> Self {
-        let tests = tests
-            .iter()
-            .map(|c| CommandHistory::new(CommandRunner::new(SubprocessExecutor::new(c))))
-            .collect();
-        let server =
-            server.map(|s| CommandHistory::new(CommandRunner::new(SubprocessExecutor::new(s))));
-        Commands {
-            last_request: None,
-            debounce: Duration::from_millis(100),
-            tests,
-            server,
-        }
-    }
-
-    fn request_run(&mut self) {
-        match self.last_request {
-            None => {
-                self.last_request = Some(Instant::now());
-            }
-            Some(t) => {
-                self.last_request = Some(Instant::now());
-                if t.elapsed() <= self.debounce {
-                    return;
+        let drained = events.drain()?;
+        if let Some(line) = heartbeat
+            .as_mut()
+            .and_then(|h| h.beat(watched_files, events.since_last_event()))
+        {
+            options.status_stream.println(line);
+        }
+        if let Some(git) = git.as_mut() {
+            git.refresh(drained.is_some());
+        }
+        while let Some(call) = rpc.as_ref().and_then(RpcServer::try_recv) {
+            if call.method == "status" {
+                call.respond(commands.status());
+                continue;
+            }
+            let control = match Control::from_method(&call.method) {
+                Some(control) => control,
+                None => {
+                    let message = format!("unknown method {}", call.method);
+                    call.fail(METHOD_NOT_FOUND, message);
+                    continue;
                 }
+            };
+            if control == Control::RunAll {
+                scope.lift();
+            }
+            match commands.control(control) {
+                Ok(()) => call.respond(Json::Null),
+                Err(e) => call.fail(INTERNAL_ERROR, e.to_string()),
             }
         }
-
-        for command in self.commands_mut() {
-            command.request_run();
+        while let Some(name) = trigger_fifo.as_ref().and_then(TriggerFifo::try_recv) {
+            if name == "all" {
+                scope.lift();
+                commands.control(Control::RunAll)?;
+            } else if !commands.request_selected(&name) {
+                options
+                    .status_stream
+                    .println(format!("watchdo: no command {} to run", name));
+            }
         }
-    }
-
-    fn commands(&self) -> impl Iterator<Item = &CommandHistory<SubprocessExecutor>> {
-        self.tests.iter().chain(self.server.iter())
-    }
-
-    fn commands_mut(&mut self) -> impl Iterator<Item = &mut CommandHistory<SubprocessExecutor>> {
-        self.tests.iter_mut().chain(self.server.iter_mut())
-    }
 
-    fn tick(
-        &mut self,
-        mut print_output: impl FnMut(&CommandOutput) -> (),
-    ) -> Result<(), Box<dyn Error>> {
-        for test in self.tests.iter_mut() {
-            if let Some(output) = test.try_finish()? {
-                if !output.success {
-                    print_output(output);
-                }
+        if stdin_triggers
+            .as_ref()
+            .is_some_and(|triggers| triggers.try_iter().count() > 0)
+        {
+            commands.request_run(&[]);
+        }
+        if let Some(changed) = drained {
+            let changed = changed
+                .iter()
+                .map(|p| roots.relative(p))
+                .collect::<Vec<_>>();
+            if let Some(changed) = react(&options.on_change, changed, options.status_stream) {
+                commands.request_run(&changed);
             }
         }
+        let (trigger, changed) = commands.last_trigger();
+        let hide_output = panes.is_some() || options.oneline;
+        if trigger != shown_trigger && options.separators && !hide_output {
+            let width = term_size::dimensions().map(|d| d.0).unwrap_or(80);
+            let time = chrono::Local::now().format("%H:%M:%S").to_string();
+            options
+                .output_stream
+                .out()
+                .println(separator(&time, changed, width));
+        }
+        if trigger != shown_trigger && options.watch_change_summary && !hide_output {
+            options.status_stream.println(trigger_summary(changed));
+        }
+        shown_trigger = trigger;
 
-        for test in self.tests.iter_mut() {
-            test.run_if_needed()?;
-            if !Self::last_success(test) {
-                break;
+        let routing = options.output_stream;
+        let mut failed = false;
+        commands.tick(|name, output| {
+            failed |= !output.success;
+            // Panes show the latest output themselves.
+            if hide_output || (options.fail_summary && !output.success) {
+                return;
             }
+            if options.defer_output {
+                deferred.push((name.to_string(), output.err.clone(), output.out.clone()));
+                return;
+            }
+            print_output(routing, &options, &output.err, &output.out);
+        })?;
+        for (name, output) in commands.take_live_output() {
+            if last_live.as_ref() != Some(&name) {
+                routing.out().println(format!("-- {} --", name));
+                last_live = Some(name);
+            }
+            routing
+                .out()
+                .print(clamp_lines(&output, options.max_line_length));
+            routing.out().flush();
+        }
+        if !deferred.is_empty() && commands.is_settled() {
+            for (name, err, out) in deferred.drain(..) {
+                routing.out().println(format!("-- {} --", name));
+                print_output(routing, &options, &err, &out);
+            }
+        }
+        if failed && options.fail_summary && !hide_output {
+            routing.out().print(commands.fail_summary(true));
         }
 
-        if let Some(server_history) = self.server.as_mut() {
-            let all_tests_succeeded = self.tests.iter().all(Self::last_success);
+        if let Some(badge) = badge.as_mut() {
+            badge.update(commands.aggregate())?;
+        }
+        if let Some(metrics_server) = metrics_server.as_mut() {
+            metrics_server.update(commands.metrics());
+        }
 
-            if server_history.has_outstanding_request() && all_tests_succeeded {
-                server_history.restart()?;
-            } else if let Some(output) = server_history.try_finish()? {
-                print_output(output);
+        if options.oneline {
+            let line = commands.oneline();
+            if last_oneline.as_ref() != Some(&line) {
+                let status = options.status_stream;
+                if options.oneline_newlines {
+                    status.println(&line);
+                } else {
+                    status.print(format!("\r\x1b[2K{}", line));
+                }
+                status.flush();
+                last_oneline = Some(line);
+            }
+        } else {
+            let width = term_size::dimensions().map(|d| d.0).unwrap_or(80);
+            let git_label = git.as_ref().map(|g| format!("{} ", g.label()));
+            let label_width = git_label.as_ref().map_or(0, |l| l.chars().count());
+            let mut to_print = commands.print(width.saturating_sub(label_width));
+            if let Some(label) = git_label {
+                to_print.insert(0, label.as_str().into());
+            }
+            let region = commands
+                .pinned_output()
+                .map_or_else(Vec::new, |(name, failing)| pin_region(name, failing, width));
+            // Pinned output needs the status bar pinned too.
+            if !region.is_empty() && pinned.is_none() && panes.is_none() {
+                pinned = Some(PinnedStatus::new(options.status_stream));
+            }
+            if let Some(panes) = panes.as_mut() {
+                let status = to_print.iter().map(|p| p.to_string()).collect::<String>();
+                let mut shown = commands.panes();
+                for pane in &mut shown {
+                    pane.output = clamp_lines(&pane.output, options.max_line_length).into_owned();
+                }
+                panes.draw(&shown, &status);
+            } else if last_printed.as_ref() != Some(&to_print) || last_region != region {
+                let status = options.status_stream;
+                match pinned.as_mut() {
+                    Some(pinned) => pinned.draw(
+                        &to_print.iter().map(|p| p.to_string()).collect::<String>(),
+                        &region,
+                    ),
+                    None => {
+                        for p in to_print.iter() {
+                            status.print(p);
+                        }
+                        status.println("");
+                        status.flush();
+                    }
+                }
+
+                last_printed = Some(to_print);
+                last_region = region;
             }
         }
 
-        Ok(())
+        std::thread::sleep(Duration::from_millis(10));
     }
+}
 
-    fn last_success(h: &CommandHistory<SubprocessExecutor>) -> bool {
-        match h.last() {
-            Some(CommandState::Completed(CommandOutput { success: true, .. })) => true,
-            _ => false,
-        }
-    }
+/// The lines showing a pinned command's latest failing output, as much of its end as fits in a
+/// third of the terminal.
+fn pin_region(name: &str, failing: Option<&str>, width: usize) -> Vec<String> {
+    let height = term_size::dimensions().map(|d| d.1).unwrap_or(24);
+    let header = match failing {
+        Some(_) => format!("-- pinned: {} --", name),
+        None => format!("-- pinned: {}, not failed yet --", name),
+    };
+    let lines = failing.unwrap_or("").lines().collect::<Vec<_>>();
+    let shown = (height / 3).saturating_sub(1);
+    std::iter::once(header)
+        .chain(
+            lines[lines.len().saturating_sub(shown)..]
+                .iter()
+                .map(|l| l.chars().take(width).collect()),
+        )
+        .collect()
+}
 
-    fn print(&self, width: usize, ok_str: &str) -> Vec<ColoredString> {
-        self.commands()
-            .flat_map(|c| print(c, width, &ok_str))
-            .collect()
+/// Prints which commands a change to `path` would run, and why.
+fn explain<E: Executor>(path: &Path, events: &Events, roots: &WatchRoots, commands: &Commands<E>) {
+    let path = match events.explain(path.to_path_buf()) {
+        Ok(path) => roots.relative(&path),
+        Err(filter) => {
+            println!("{}: left out by {}", path.display(), filter);
+            return;
+        }
+    };
+    println!("{}:", path.display());
+    for (name, reason) in commands.explain(&path) {
+        match reason {
+            Ok(reason) => println!("  {}: runs, {}", name, reason),
+            Err(reason) => println!("  {}: skipped, {}", name, reason),
+        }
     }
 }
 
-fn print<'c, E: Executor>(
-    command_history: &'c CommandHistory<E>,
-    width: usize,
-    ok_str: &'c str,
-) -> impl Iterator<Item = ColoredString> + 'c {
-    let chars = command_history.iter().map(move |state| match state {
-        CommandState::Requested => ".".normal(),
-        CommandState::Running => "?".black().on_yellow(),
-        CommandState::Completed(output) => {
-            if output.success {
-                ok_str.white().on_green()
-            } else {
-                "x".white().on_red()
-            }
+fn print_output(routing: OutputRouting, options: &Options, err: &str, out: &str) {
+    for (stream, text) in [(routing.err(), err), (routing.out(), out)] {
+        let text = clamp_lines(text, options.max_line_length);
+        if !stream.is_terminal() {
+            stream.println(text);
+            continue;
         }
-        CommandState::Terminated(output) => {
-            if output.success {
-                ok_str.black().on_white()
-            } else {
-                "x".black().on_white()
-            }
+        let colors = colored::control::SHOULD_COLORIZE.should_colorize();
+        let text = highlight(&text, &options.highlight, colors);
+        if options.color_diffs {
+            stream.println(color_diffs(&text, colors));
+        } else {
+            stream.println(text);
         }
-    });
-    let spaces = std::iter::repeat(" ".normal().on_white()).take(width);
-    let whole_print = spaces.chain(chars);
-    match whole_print.size_hint() {
-        (min, Some(max)) => {
-            assert_eq!(min, max);
-            Box::new(whole_print.skip(min - width).take(width))
-        }
-        _ => unreachable!(),
     }
 }
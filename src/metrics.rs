@@ -0,0 +1,263 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Counts of what each command did, for exporting to Prometheus.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    commands: Vec<CommandMetrics>,
+    // Bumped on every change, so unchanged metrics needn't be rendered again.
+    version: u64,
+}
+
+#[derive(Debug)]
+struct CommandMetrics {
+    name: String,
+    runs: u64,
+    failures: u64,
+    retries: u64,
+    up: bool,
+    last_duration: Option<Duration>,
+}
+
+impl Metrics {
+    /// Records a finished run of the command called `name`.
+    pub fn finished(&mut self, name: &str, passed: bool, duration: Option<Duration>) {
+        let command = self.run(name, duration);
+        if !passed {
+            command.failures += 1;
+        }
+        command.up = passed;
+    }
+
+    /// Records a failed run of the command called `name` that's being tried again. It isn't a
+    /// failure, nor does it change whether the command is up.
+    pub fn retried(&mut self, name: &str, duration: Option<Duration>) {
+        self.run(name, duration).retries += 1;
+    }
+
+    fn run(&mut self, name: &str, duration: Option<Duration>) -> &mut CommandMetrics {
+        self.version += 1;
+        let command = match self.commands.iter().position(|c| c.name == name) {
+            Some(i) => &mut self.commands[i],
+            None => {
+                self.commands.push(CommandMetrics {
+                    name: name.to_string(),
+                    runs: 0,
+                    failures: 0,
+                    retries: 0,
+                    up: false,
+                    last_duration: None,
+                });
+                self.commands.last_mut().unwrap()
+            }
+        };
+        command.runs += 1;
+        if duration.is_some() {
+            command.last_duration = duration;
+        }
+        command
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut family =
+            |name: &str,
+             kind: &str,
+             help: &str,
+             value: &dyn Fn(&CommandMetrics) -> Option<String>| {
+                out.push_str(&format!(
+                    "# HELP {} {}\n# TYPE {} {}\n",
+                    name, help, name, kind
+                ));
+                for command in &self.commands {
+                    if let Some(value) = value(command) {
+                        out.push_str(&format!(
+                            "{}{{command=\"{}\"}} {}\n",
+                            name,
+                            escape(&command.name),
+                            value
+                        ));
+                    }
+                }
+            };
+        family(
+            "watchdo_runs_total",
+            "counter",
+            "Finished runs of each command.",
+            &|c| Some(c.runs.to_string()),
+        );
+        family(
+            "watchdo_failures_total",
+            "counter",
+            "Finished runs of each command that didn't pass.",
+            &|c| Some(c.failures.to_string()),
+        );
+        family(
+            "watchdo_retries_total",
+            "counter",
+            "Finished runs of each command that failed and were tried again.",
+            &|c| Some(c.retries.to_string()),
+        );
+        family(
+            "watchdo_up",
+            "gauge",
+            "Whether the latest run of each command passed.",
+            &|c| Some((c.up as u8).to_string()),
+        );
+        family(
+            "watchdo_last_run_duration_seconds",
+            "gauge",
+            "How long the latest run of each command took.",
+            &|c| c.last_duration.map(|d| d.as_secs_f64().to_string()),
+        );
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// How long a client has to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the latest rendered metrics at `/metrics` over HTTP.
+pub struct MetricsServer {
+    rendered: Arc<Mutex<String>>,
+    version: Option<u64>,
+}
+
+impl MetricsServer {
+    pub fn bind(addr: SocketAddr) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(addr)?;
+        let rendered = Arc::new(Mutex::new(String::new()));
+        let serving = rendered.clone();
+        std::thread::spawn(move || serve(listener, serving));
+        Ok(MetricsServer {
+            rendered,
+            version: None,
+        })
+    }
+
+    pub fn update(&mut self, metrics: &Metrics) {
+        if self.version != Some(metrics.version) {
+            *self.rendered.lock().unwrap() = metrics.render();
+            self.version = Some(metrics.version);
+        }
+    }
+}
+
+fn serve(listener: TcpListener, rendered: Arc<Mutex<String>>) {
+    for stream in listener.incoming().flatten() {
+        let rendered = rendered.clone();
+        // A slow or stuck client only holds up its own thread, and not for long.
+        std::thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            // A client going away mid-request only affects that client.
+            let _ = respond(stream, &rendered);
+        });
+    }
+}
+
+fn respond(mut stream: TcpStream, rendered: &Mutex<String>) -> std::io::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", rendered.lock().unwrap().clone())
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn counts_runs_and_failures() {
+        let mut metrics = Metrics::default();
+        metrics.finished("cargo test", false, Some(Duration::from_millis(1500)));
+        metrics.finished("cargo test", true, None);
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("watchdo_runs_total{command=\"cargo test\"} 2\n"));
+        assert!(rendered.contains("watchdo_failures_total{command=\"cargo test\"} 1\n"));
+        assert!(rendered.contains("watchdo_up{command=\"cargo test\"} 1\n"));
+        assert!(
+            rendered.contains("watchdo_last_run_duration_seconds{command=\"cargo test\"} 1.5\n")
+        );
+    }
+
+    #[test]
+    fn counts_retried_runs() {
+        let mut metrics = Metrics::default();
+        metrics.retried("cargo test", Some(Duration::from_secs(1)));
+        metrics.finished("cargo test", true, None);
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("watchdo_runs_total{command=\"cargo test\"} 2\n"));
+        assert!(rendered.contains("watchdo_failures_total{command=\"cargo test\"} 0\n"));
+        assert!(rendered.contains("watchdo_retries_total{command=\"cargo test\"} 1\n"));
+        assert!(rendered.contains("watchdo_up{command=\"cargo test\"} 1\n"));
+    }
+
+    #[test]
+    fn stuck_client_does_not_block_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let rendered = Arc::new(Mutex::new("watchdo_up 1\n".to_string()));
+        std::thread::spawn(move || serve(listener, rendered));
+
+        let _stuck = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.ends_with("\r\n\r\nwatchdo_up 1\n"));
+    }
+
+    #[test]
+    fn escapes_command_names() {
+        assert_eq!(escape("echo \"a\\b\""), "echo \\\"a\\\\b\\\"");
+    }
+
+    #[test]
+    fn serves_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let rendered = Mutex::new("watchdo_up 1\n".to_string());
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        respond(listener.accept().unwrap().0, &rendered).unwrap();
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nwatchdo_up 1\n"));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::stream::Stream;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Tells the user when commands start failing.
+pub struct Notifier {
+    pub bell: bool,
+    pub desktop: bool,
+    /// How many failures in a row before notifying.
+    pub after: usize,
+    /// How long after notifying for a command to hold back more of its notifications.
+    pub cooldown: Option<Duration>,
+    pub stream: Stream,
+    cooldowns: HashMap<String, Cooldown>,
+}
+
+/// A command's latest notification, and what's happened since.
+struct Cooldown {
+    notified: Instant,
+    // Notifications held back since then.
+    suppressed: usize,
+    failing: bool,
+}
+
+impl Notifier {
+    pub fn new(
+        bell: bool,
+        desktop: bool,
+        after: usize,
+        cooldown: Option<Duration>,
+        stream: Stream,
+    ) -> Self {
+        Notifier {
+            bell,
+            desktop,
+            after,
+            cooldown,
+            stream,
+            cooldowns: HashMap::new(),
+        }
+    }
+
+    /// Called whenever a command finishes.
+    pub fn finished(&mut self, name: &str, consecutive_failures: usize) {
+        if let Some(message) = self.message(name, consecutive_failures) {
+            self.notify(&message);
+        }
+    }
+
+    /// Sends one notification for each command that's still failing after the notifications it
+    /// had held back.
+    pub fn tick(&mut self) {
+        for message in self.due() {
+            self.notify(&message);
+        }
+    }
+
+    fn message(&mut self, name: &str, consecutive_failures: usize) -> Option<String> {
+        if let Some(cooldown) = self.cooldowns.get_mut(name) {
+            cooldown.failing = consecutive_failures > 0;
+        }
+        // Only notify once per streak of failures.
+        if consecutive_failures != self.after.max(1) {
+            return None;
+        }
+
+        if let (Some(window), Some(cooldown)) = (self.cooldown, self.cooldowns.get_mut(name)) {
+            if cooldown.notified.elapsed() < window {
+                cooldown.suppressed += 1;
+                return None;
+            }
+        }
+        if self.cooldown.is_some() {
+            self.cooldowns.insert(
+                name.to_string(),
+                Cooldown {
+                    notified: Instant::now(),
+                    suppressed: 0,
+                    failing: true,
+                },
+            );
+        }
+
+        Some(if consecutive_failures == 1 {
+            format!("{} failed", name)
+        } else {
+            format!("{} failed {} times in a row", name, consecutive_failures)
+        })
+    }
+
+    fn due(&mut self) -> Vec<String> {
+        let window = match self.cooldown {
+            Some(window) => window,
+            None => return Vec::new(),
+        };
+
+        let mut due = Vec::new();
+        for (name, cooldown) in &mut self.cooldowns {
+            if cooldown.suppressed == 0 || cooldown.notified.elapsed() < window {
+                continue;
+            }
+            if cooldown.failing {
+                due.push(format!(
+                    "{} still failing, {} more failures since the last notification",
+                    name, cooldown.suppressed
+                ));
+                cooldown.notified = Instant::now();
+            }
+            cooldown.suppressed = 0;
+        }
+        due
+    }
+
+    fn notify(&self, message: &str) {
+        if self.bell {
+            self.stream.print("\x07");
+            self.stream.flush();
+        }
+        if self.desktop {
+            desktop_notification(message);
+        }
+    }
+}
+
+fn desktop_notification(message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {:?} with title \"watchdo\"",
+            message
+        ));
+        command
+    } else {
+        let mut command = std::process::Command::new("notify-send");
+        command.arg("watchdo").arg(message);
+        command
+    };
+    // Notifications are best effort, the status bar still shows the failure.
+    let _ = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier(cooldown: Duration) -> Notifier {
+        Notifier::new(false, false, 1, Some(cooldown), Stream::Stderr)
+    }
+
+    #[test]
+    fn flapping_within_cooldown_notifies_once() {
+        let mut notifier = notifier(Duration::from_secs(60));
+
+        assert_eq!(notifier.message("test", 1), Some("test failed".to_string()));
+        assert_eq!(notifier.message("test", 0), None);
+        assert_eq!(notifier.message("test", 1), None);
+        assert_eq!(
+            notifier.message("other", 1),
+            Some("other failed".to_string())
+        );
+        assert!(notifier.due().is_empty());
+    }
+
+    #[test]
+    fn held_back_failures_are_merged_after_cooldown() {
+        let mut notifier = notifier(Duration::from_millis(20));
+        notifier.message("test", 1);
+        notifier.message("test", 0);
+        notifier.message("test", 1);
+        notifier.message("test", 0);
+        notifier.message("test", 1);
+
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(
+            notifier.due(),
+            vec!["test still failing, 2 more failures since the last notification"]
+        );
+        assert!(notifier.due().is_empty());
+    }
+
+    #[test]
+    fn nothing_is_merged_once_passing() {
+        let mut notifier = notifier(Duration::from_millis(20));
+        notifier.message("test", 1);
+        notifier.message("test", 0);
+        notifier.message("test", 1);
+        notifier.message("test", 0);
+
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert!(notifier.due().is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::control::Control;
+use crate::stream::Stream;
+use crate::terminal::TerminalGuard;
+
+/// Shows each command's latest output in its own pane on the terminal's alternate screen, with a
+/// row of tabs to switch between them. The terminal is restored when this is dropped.
+pub struct Panes {
+    stream: Stream,
+    active: usize,
+    // Lines scrolled up from the end of the active pane's output.
+    scroll: usize,
+    count: usize,
+    last_frame: Option<Vec<String>>,
+    _guard: TerminalGuard,
+}
+
+/// What to show for one command.
+pub struct Pane {
+    pub tab: String,
+    pub output: String,
+}
+
+impl Panes {
+    pub fn new(stream: Stream) -> Self {
+        stream.print("\x1b[?1049h\x1b[?25l");
+        Panes {
+            stream,
+            active: 0,
+            scroll: 0,
+            count: 0,
+            last_frame: None,
+            // Show the cursor again and leave the alternate screen.
+            _guard: TerminalGuard::new(stream, "\x1b[0m\x1b[?25h\x1b[?1049l"),
+        }
+    }
+
+    /// Handles controls for switching and scrolling panes, returning whether it was one.
+    pub fn control(&mut self, control: &Control) -> bool {
+        let last = self.count.saturating_sub(1);
+        let active = match *control {
+            Control::SelectPane(n) => n.saturating_sub(1).min(last),
+            Control::NextPane => (self.active + 1) % self.count.max(1),
+            Control::PrevPane => self.active.checked_sub(1).unwrap_or(last),
+            Control::ScrollUp => {
+                self.scroll += 1;
+                return true;
+            }
+            Control::ScrollDown => {
+                self.scroll = self.scroll.saturating_sub(1);
+                return true;
+            }
+            _ => return false,
+        };
+        if active != self.active {
+            self.active = active;
+            self.scroll = 0;
+        }
+        true
+    }
+
+    pub fn draw(&mut self, panes: &[Pane], status: &str) {
+        self.count = panes.len();
+        self.active = self.active.min(panes.len().saturating_sub(1));
+
+        let height = term_size::dimensions().map(|d| d.1).unwrap_or(24).max(3);
+        let mut frame = vec![tabs(panes, self.active)];
+        let output = panes.get(self.active).map(|p| p.output.as_str());
+        let (lines, scroll) = visible_lines(output.unwrap_or(""), height - 2, self.scroll);
+        self.scroll = scroll;
+        frame.extend(lines.into_iter().map(str::to_string));
+        frame.resize(height - 1, String::new());
+        frame.push(status.to_string());
+
+        if self.last_frame.as_ref() == Some(&frame) {
+            return;
+        }
+        self.stream
+            .print(format!("\x1b[H\x1b[2J{}", frame.join("\r\n")));
+        self.stream.flush();
+        self.last_frame = Some(frame);
+    }
+}
+
+fn tabs(panes: &[Pane], active: usize) -> String {
+    panes
+        .iter()
+        .enumerate()
+        .map(|(i, pane)| {
+            if i == active {
+                format!("[{} {}]", i + 1, pane.tab)
+            } else {
+                format!(" {} {} ", i + 1, pane.tab)
+            }
+        })
+        .collect()
+}
+
+/// The lines of `output` that fit in `height`, scrolled `scroll` lines up from the end. Returns
+/// the scroll clamped to the output's length.
+fn visible_lines(output: &str, height: usize, scroll: usize) -> (Vec<&str>, usize) {
+    let lines = output.lines().collect::<Vec<_>>();
+    let scroll = scroll.min(lines.len().saturating_sub(height));
+    let end = lines.len() - scroll;
+    (lines[end.saturating_sub(height)..end].to_vec(), scroll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_end_of_output() {
+        assert_eq!(visible_lines("a\nb\nc\nd", 2, 0), (vec!["c", "d"], 0));
+    }
+
+    #[test]
+    fn scrolls_up_to_start() {
+        assert_eq!(visible_lines("a\nb\nc\nd", 2, 1), (vec!["b", "c"], 1));
+        assert_eq!(visible_lines("a\nb\nc\nd", 2, 5), (vec!["a", "b"], 2));
+    }
+
+    #[test]
+    fn short_output_does_not_scroll() {
+        assert_eq!(visible_lines("a", 3, 2), (vec!["a"], 0));
+    }
+}
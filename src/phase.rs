@@ -0,0 +1,45 @@
+use crate::args::{split_selector, Colons};
+use std::str::FromStr;
+
+/// Puts a command in a phase, as `<command>:<phase>`. Commands in the same phase run together,
+/// and a phase only starts once the one before it finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub selector: String,
+    pub phase: usize,
+}
+
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, phase) = split_selector(s, "<command>:<phase>", Colons::Left)?;
+        Ok(Phase {
+            selector: selector.to_string(),
+            phase: phase
+                .parse()
+                .map_err(|_| format!("expected a phase number, got {:?}", phase))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_phase() {
+        assert_eq!(
+            "cargo test:2".parse::<Phase>().unwrap(),
+            Phase {
+                selector: "cargo test".to_string(),
+                phase: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn phase_must_be_number() {
+        assert!("cargo test:soon".parse::<Phase>().is_err());
+    }
+}
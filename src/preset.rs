@@ -0,0 +1,40 @@
+use crate::args::split_named;
+use std::str::FromStr;
+
+/// A command in a named set of commands to switch to, by typing `:preset <name>`, as
+/// `<name>:<command>`. The command is selected by its text, position or as a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub selector: String,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, selector) = split_named(s, "preset")?;
+        Ok(Preset {
+            name: name.to_string(),
+            selector: selector.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_and_command() {
+        assert_eq!(
+            "frontend:npm run test:unit".parse(),
+            Ok(Preset {
+                name: "frontend".to_string(),
+                selector: "npm run test:unit".to_string(),
+            })
+        );
+        assert!("frontend".parse::<Preset>().is_err());
+        assert!("front end:2".parse::<Preset>().is_err());
+    }
+}
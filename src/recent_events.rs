@@ -0,0 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The latest notable things that happened, like a test starting to fail or a server
+/// restarting, shown under the status bar for a while.
+pub struct RecentEvents {
+    shown: usize,
+    shown_for: Duration,
+    // Newest first.
+    events: VecDeque<Event>,
+    // Whether each command's latest run passed, to tell when that changes.
+    passing: HashMap<String, bool>,
+}
+
+struct Event {
+    at: Instant,
+    time: String,
+    message: String,
+}
+
+impl RecentEvents {
+    pub fn new(shown: usize, shown_for: Duration) -> Self {
+        RecentEvents {
+            shown,
+            shown_for,
+            events: VecDeque::new(),
+            passing: HashMap::new(),
+        }
+    }
+
+    /// Notes a finished run when the command starts failing or passes again. A first run that
+    /// passes isn't worth noting.
+    pub fn finished(&mut self, name: &str, passed: bool) {
+        match (self.passing.insert(name.to_string(), passed), passed) {
+            (Some(false), true) => self.push(format!("{} passing again", name)),
+            (None | Some(true), false) => self.push(format!("{} failed", name)),
+            _ => {}
+        }
+    }
+
+    pub fn push(&mut self, message: String) {
+        let time = chrono::Local::now().format("%H:%M").to_string();
+        self.push_at(Instant::now(), time, message);
+    }
+
+    fn push_at(&mut self, at: Instant, time: String, message: String) {
+        self.events.push_front(Event { at, time, message });
+        self.events.truncate(self.shown);
+    }
+
+    /// The events that are still shown, newest first, as a line exactly `width` characters
+    /// wide. `None` once they've all faded.
+    pub fn line(&self, width: usize) -> Option<String> {
+        let shown = self
+            .events
+            .iter()
+            .filter(|e| e.at.elapsed() < self.shown_for)
+            .map(|e| format!("{} {}", e.time, e.message))
+            .collect::<Vec<_>>();
+        if shown.is_empty() {
+            return None;
+        }
+        let line = shown.join(" · ");
+        Some(format!("{:<1$.1$}", line, width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_when_passing_changes() {
+        let mut events = RecentEvents::new(5, Duration::from_secs(60));
+        events.finished("build", true);
+        assert_eq!(events.line(80), None);
+
+        events.finished("build", false);
+        events.finished("build", false);
+        events.finished("build", true);
+        events.finished("test", false);
+        let messages = events
+            .events
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["test failed", "build passing again", "build failed"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_newest_few_and_fits_the_width() {
+        let mut events = RecentEvents::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        for (time, message) in [
+            ("14:01", "a failed"),
+            ("14:02", "b failed"),
+            ("14:03", "c failed"),
+        ] {
+            events.push_at(now, time.to_string(), message.to_string());
+        }
+
+        assert_eq!(
+            events.line(40).unwrap(),
+            "14:03 c failed · 14:02 b failed         "
+        );
+        assert_eq!(events.line(10).unwrap(), "14:03 c fa");
+    }
+
+    #[test]
+    fn events_fade() {
+        let mut events = RecentEvents::new(2, Duration::ZERO);
+        events.push("a failed".to_string());
+
+        assert_eq!(events.line(80), None);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::executor::shell_quote;
+use crate::stream::Stream;
+use notify::DebouncedEvent;
+use regex::Regex;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Runs `command` and treats each line it prints as a changed path, for changes watchdo can't
+/// see itself, like `ssh host watchman-wait -m 0 /src` for a remote directory. Relative paths
+/// are relative to `base`.
+pub fn forward_events(
+    command: &str,
+    base: &Path,
+    tx: Sender<DebouncedEvent>,
+    stream: Stream,
+) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let command = command.to_string();
+    let base = base.to_path_buf();
+    std::thread::spawn(move || {
+        if forward_lines(BufReader::new(stdout), &base, &tx) {
+            stream.println(format!("watchdo: {:?} stopped reporting changes", command));
+        }
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Sends each non-empty line as a change, returning whether the input ended while changes were
+/// still wanted.
+fn forward_lines(input: impl BufRead, base: &Path, tx: &Sender<DebouncedEvent>) -> bool {
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let path = line.trim();
+        if path.is_empty() {
+            continue;
+        }
+        if tx.send(DebouncedEvent::Write(base.join(path))).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Reads stdin as it's written, for `producer | watchdo --stdin-trigger`, sending a run request
+/// for each line, or only for the lines matching `filter`.
+pub fn stdin_triggers(filter: Option<Regex>, stream: Stream) -> Receiver<()> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let stdin = BufReader::new(std::io::stdin());
+        if trigger_lines(stdin, filter.as_ref(), &tx) {
+            stream.println("watchdo: stdin ended, only changes trigger runs now");
+        }
+    });
+    rx
+}
+
+/// Sends a request for each line that matches, returning whether the input ended while
+/// requests were still wanted.
+fn trigger_lines(input: impl BufRead, filter: Option<&Regex>, tx: &Sender<()>) -> bool {
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if filter.is_none_or(|f| f.is_match(&line)) && tx.send(()).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether watchman and its `watchman-wait` client are installed and the server is reachable.
+pub fn watchman_available() -> bool {
+    let succeeds = |program: &str, arg: &str| {
+        Command::new(program)
+            .arg(arg)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+    };
+    succeeds("watchman", "version") && succeeds("watchman-wait", "--help")
+}
+
+/// Reports every change under `dir`, relative to it, until stopped.
+pub fn watchman_command(dir: &Path) -> String {
+    format!(
+        "watchman-wait --max-events 0 {}",
+        shell_quote(&dir.to_string_lossy())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn each_line_is_a_change() {
+        let (tx, rx) = channel();
+
+        assert!(forward_lines(
+            &b"src/main.rs\n\n /abs/lib.rs \n"[..],
+            Path::new("root"),
+            &tx
+        ));
+
+        let paths = rx
+            .try_iter()
+            .map(|event| match event {
+                DebouncedEvent::Write(path) => path,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("root/src/main.rs"),
+                PathBuf::from("/abs/lib.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_lines_are_requests() {
+        let (tx, rx) = channel();
+        let filter = Regex::new("^ERROR").unwrap();
+
+        assert!(trigger_lines(
+            &b"INFO started\nERROR boom\nERROR again\n"[..],
+            Some(&filter),
+            &tx
+        ));
+        assert_eq!(rx.try_iter().count(), 2);
+        assert!(trigger_lines(&b"a\n\nb\n"[..], None, &tx));
+        assert_eq!(rx.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn watchman_waits_on_quoted_dir() {
+        assert_eq!(
+            watchman_command(Path::new("my dir")),
+            "watchman-wait --max-events 0 'my dir'"
+        );
+    }
+}
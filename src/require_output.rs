@@ -0,0 +1,80 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use regex::Regex;
+use std::str::FromStr;
+
+/// A pattern a command's output must contain for it to pass, as `<command>:<regex>`, for
+/// commands that can exit with 0 without doing their work.
+#[derive(Debug, Clone)]
+pub struct RequireOutput {
+    pub selector: String,
+    pattern: Regex,
+}
+
+impl RequireOutput {
+    /// Fails a passing `output` that doesn't contain the pattern.
+    pub fn check(&self, output: &mut CommandOutput) {
+        if !output.success
+            || self.pattern.is_match(&output.out)
+            || self.pattern.is_match(&output.err)
+        {
+            return;
+        }
+
+        output.success = false;
+        output.err.push_str(&format!(
+            "watchdo: exited with 0, but the output doesn't contain /{}/\n",
+            self.pattern
+        ));
+    }
+}
+
+impl FromStr for RequireOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, pattern) = split_selector(s, "<command>:<regex>", Colons::Right)?;
+        Ok(RequireOutput {
+            selector: selector.to_string(),
+            pattern: Regex::new(pattern).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(success: bool, out: &str) -> CommandOutput {
+        CommandOutput {
+            success,
+            out: out.to_string(),
+            ..CommandOutput::default()
+        }
+    }
+
+    #[test]
+    fn fails_passing_runs_without_the_pattern() {
+        let require = "2:Deployed (successfully|to .+)"
+            .parse::<RequireOutput>()
+            .unwrap();
+        assert_eq!(require.selector, "2");
+
+        let mut deployed = output(true, "Deployed to staging:8080\n");
+        require.check(&mut deployed);
+        assert!(deployed.success);
+
+        let mut skipped = output(true, "Nothing to deploy\n");
+        require.check(&mut skipped);
+        assert!(!skipped.success);
+        assert_eq!(
+            skipped.err,
+            "watchdo: exited with 0, but the output doesn't contain /Deployed (successfully|to \
+             .+)/\n"
+        );
+
+        let mut failed = output(false, "");
+        require.check(&mut failed);
+        assert_eq!(failed.err, "");
+    }
+}
@@ -0,0 +1,23 @@
+use crate::args::{split_selector, Colons};
+use regex::Regex;
+use std::str::FromStr;
+
+/// Restart a server by starting the new one first, and only stopping the old one once the new
+/// one writes output matching a pattern, as `<server>:<regex>`.
+#[derive(Debug, Clone)]
+pub struct GracefulRestart {
+    pub selector: String,
+    pub ready: Regex,
+}
+
+impl FromStr for GracefulRestart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, ready) = split_selector(s, "<server>:<regex>", Colons::Right)?;
+        Ok(GracefulRestart {
+            selector: selector.to_string(),
+            ready: Regex::new(ready).map_err(|e| e.to_string())?,
+        })
+    }
+}
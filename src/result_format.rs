@@ -0,0 +1,187 @@
+use crate::args::{split_selector, Colons};
+use crate::executor::CommandOutput;
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+/// A test output format to count results from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// JUnit XML, counted from its `<testsuite>` elements.
+    Junit,
+    /// Test Anything Protocol.
+    Tap,
+}
+
+/// How many tests passed, failed and were skipped in a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TestCounts {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+impl ResultFormat {
+    /// Counts the results in the output's stdout, or its stderr if there are none there.
+    pub fn counts(self, output: &CommandOutput) -> Option<TestCounts> {
+        self.parse(&output.out).or_else(|| self.parse(&output.err))
+    }
+
+    fn parse(self, text: &str) -> Option<TestCounts> {
+        match self {
+            ResultFormat::Junit => parse_junit(text),
+            ResultFormat::Tap => parse_tap(text),
+        }
+    }
+}
+
+fn parse_junit(text: &str) -> Option<TestCounts> {
+    let suite = Regex::new(r"<testsuite\s[^>]*>").unwrap();
+    let attribute = Regex::new(r#"(\w+)\s*=\s*"(\d+)""#).unwrap();
+
+    let mut counts = None;
+    for suite in suite.find_iter(text) {
+        let (mut tests, mut failed, mut skipped) = (0, 0, 0);
+        for attr in attribute.captures_iter(suite.as_str()) {
+            let n = attr[2].parse::<usize>().unwrap_or(0);
+            match &attr[1] {
+                "tests" => tests = n,
+                "failures" | "errors" => failed += n,
+                "skipped" | "disabled" => skipped += n,
+                _ => {}
+            }
+        }
+        let counts = counts.get_or_insert_with(TestCounts::default);
+        counts.failed += failed;
+        counts.skipped += skipped;
+        counts.passed += tests.saturating_sub(failed + skipped);
+    }
+    counts
+}
+
+fn parse_tap(text: &str) -> Option<TestCounts> {
+    // Indented results belong to subtests, which their parent's result already covers.
+    let result = Regex::new(r"^(not )?ok\b(.*)$").unwrap();
+    let directive = Regex::new(r"(?i)#\s*(skip|todo)").unwrap();
+
+    let mut counts = None;
+    for line in text.lines() {
+        let captures = match result.captures(line) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let counts = counts.get_or_insert_with(TestCounts::default);
+        if directive.is_match(&captures[2]) {
+            counts.skipped += 1;
+        } else if captures.get(1).is_some() {
+            counts.failed += 1;
+        } else {
+            counts.passed += 1;
+        }
+    }
+    counts
+}
+
+impl fmt::Display for TestCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} passed, {} failed", self.passed, self.failed)?;
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ResultFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "junit" => Ok(ResultFormat::Junit),
+            "tap" => Ok(ResultFormat::Tap),
+            other => Err(format!("expected junit or tap, got {:?}", other)),
+        }
+    }
+}
+
+/// The format a command's test results are in, as `<command>:<format>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatParser {
+    pub selector: String,
+    pub format: ResultFormat,
+}
+
+impl FromStr for FormatParser {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, format) = split_selector(s, "<command>:<format>", Colons::Left)?;
+        Ok(FormatParser {
+            selector: selector.to_string(),
+            format: format.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(passed: usize, failed: usize, skipped: usize) -> Option<TestCounts> {
+        Some(TestCounts {
+            passed,
+            failed,
+            skipped,
+        })
+    }
+
+    #[test]
+    fn counts_tap_results() {
+        let tap = "TAP version 13\n1..5\nok 1 - adds\nnot ok 2 - subtracts\n\
+                   ok 3 - divides # SKIP no floats\nnot ok 4 - rounds # TODO\n    ok 1 - nested\n\
+                   ok 5\n";
+
+        assert_eq!(ResultFormat::Tap.parse(tap), counts(2, 1, 2));
+    }
+
+    #[test]
+    fn counts_junit_suites() {
+        let junit = r#"<?xml version="1.0"?>
+<testsuites tests="9">
+  <testsuite name="a" tests="4" failures="1" errors="0" skipped="1">
+  </testsuite>
+  <testsuite name="b" tests="5" failures="0" errors="2">
+  </testsuite>
+</testsuites>"#;
+
+        assert_eq!(ResultFormat::Junit.parse(junit), counts(5, 3, 1));
+    }
+
+    #[test]
+    fn no_results_is_none() {
+        assert_eq!(ResultFormat::Tap.parse("running...\n"), None);
+        assert_eq!(ResultFormat::Junit.parse("<testsuites/>"), None);
+    }
+
+    #[test]
+    fn falls_back_to_stderr() {
+        let output = CommandOutput {
+            err: "ok 1\n".to_string(),
+            ..CommandOutput::default()
+        };
+
+        assert_eq!(ResultFormat::Tap.counts(&output), counts(1, 0, 0));
+    }
+
+    #[test]
+    fn parses_parser() {
+        assert_eq!(
+            "npm test:tap".parse(),
+            Ok(FormatParser {
+                selector: "npm test".to_string(),
+                format: ResultFormat::Tap,
+            })
+        );
+        assert!("npm test:xml".parse::<FormatParser>().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::json::Json;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INTERNAL_ERROR: i64 = -32603;
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+
+/// A JSON-RPC request from a client, waiting for its response.
+pub struct RpcCall {
+    pub method: String,
+    // None for notifications, which don't get a response.
+    id: Option<Json>,
+    reply: Sender<String>,
+}
+
+impl RpcCall {
+    pub fn respond(self, result: Json) {
+        self.send(("result", result));
+    }
+
+    pub fn fail(self, code: i64, message: impl Into<String>) {
+        self.send(("error", error(code, message)));
+    }
+
+    fn send(self, outcome: (&str, Json)) {
+        if let Some(id) = self.id {
+            let response = Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), outcome]);
+            // The client may have gone away, which only affects it.
+            let _ = self.reply.send(response.to_string());
+        }
+    }
+}
+
+fn error(code: i64, message: impl Into<String>) -> Json {
+    Json::object(vec![
+        ("code", Json::Number(code as f64)),
+        ("message", Json::String(message.into())),
+    ])
+}
+
+/// Parses one line of a connection into a call, or the response for a request that isn't valid.
+fn parse_call(line: &str, reply: &Sender<String>) -> Result<RpcCall, String> {
+    let failure = |id: Json, code, message: String| {
+        Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("error", error(code, message)),
+        ])
+        .to_string()
+    };
+    let request = Json::parse(line).map_err(|e| failure(Json::Null, PARSE_ERROR, e))?;
+    let id = request.get("id").cloned();
+    match request.get("method").and_then(Json::as_str) {
+        Some(method) => Ok(RpcCall {
+            method: method.to_string(),
+            id,
+            reply: reply.clone(),
+        }),
+        None => Err(failure(
+            id.unwrap_or(Json::Null),
+            INVALID_REQUEST,
+            "expected a method".to_string(),
+        )),
+    }
+}
+
+/// Takes JSON-RPC requests, one per line, on a Unix socket.
+pub struct RpcServer {
+    path: PathBuf,
+    calls: Receiver<RpcCall>,
+}
+
+impl RpcServer {
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<Self, Box<dyn Error>> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::UnixListener;
+
+        match std::fs::metadata(path) {
+            // A socket left behind by a watchdo that didn't exit cleanly would stop the bind.
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                if std::os::unix::net::UnixStream::connect(path).is_err() {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+            Ok(_) => return Err(format!("{} exists and isn't a socket", path.display()).into()),
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        let (tx, calls) = channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let reader = match stream.try_clone() {
+                        Ok(reader) => BufReader::new(reader),
+                        Err(_) => return,
+                    };
+                    serve(reader, stream, tx);
+                });
+            }
+        });
+        Ok(RpcServer {
+            path: path.to_path_buf(),
+            calls,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err("--rpc needs Unix sockets".into())
+    }
+
+    /// The next call that's waiting, if any.
+    pub fn try_recv(&self) -> Option<RpcCall> {
+        self.calls.try_recv().ok()
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Answers a connection's requests in order, until it closes or watchdo stops answering.
+fn serve(reader: impl BufRead, mut writer: impl Write, calls: Sender<RpcCall>) {
+    let (reply, replies) = channel();
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse_call(&line, &reply) {
+            Ok(call) => {
+                let notification = call.id.is_none();
+                if calls.send(call).is_err() {
+                    return;
+                }
+                if notification {
+                    continue;
+                }
+                match replies.recv() {
+                    Ok(response) => response,
+                    Err(_) => return,
+                }
+            }
+            Err(response) => response,
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn answers_each_request_in_order() {
+        let (tx, calls) = channel();
+        let input = "{\"id\": 1, \"method\": \"status\"}\n\
+                     {\"method\": \"run\"}\n\
+                     not json\n\
+                     {\"id\": \"b\", \"method\": \"nope\"}\n";
+        let server = std::thread::spawn(move || {
+            let mut output = Vec::new();
+            serve(input.as_bytes(), &mut output, tx);
+            String::from_utf8(output).unwrap()
+        });
+
+        let call = calls.recv().unwrap();
+        assert_eq!(call.method, "status");
+        call.respond(Json::Bool(true));
+        calls.recv().unwrap().respond(Json::Null);
+        calls
+            .recv()
+            .unwrap()
+            .fail(METHOD_NOT_FOUND, "unknown method nope");
+
+        let output = server.join().unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], r#"{"jsonrpc":"2.0","id":1,"result":true}"#);
+        assert!(lines[1].contains(r#""id":null,"error":{"code":-32700"#));
+        assert_eq!(
+            lines[2],
+            r#"{"jsonrpc":"2.0","id":"b","error":{"code":-32601,"message":"unknown method nope"}}"#
+        );
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn leaves_other_files_alone() {
+        let dir = TestDir::new();
+        let path = dir.join("notes");
+        std::fs::write(&path, "notes").unwrap();
+
+        assert!(RpcServer::bind(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    }
+}
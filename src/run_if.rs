@@ -0,0 +1,50 @@
+use crate::args::{split_selector, Colons};
+use std::str::FromStr;
+
+/// Only runs a command once another has exited with a certain code, as
+/// `<command>:<prerequisite>=<code>`, like running migrations when a schema check exits with 2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunIf {
+    pub selector: String,
+    pub prerequisite: String,
+    pub code: i32,
+}
+
+impl FromStr for RunIf {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = "<command>:<prerequisite>=<code>";
+        let (selected, code) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected {}, got {:?}", format, s))?;
+        let (selector, prerequisite) = split_selector(selected, format, Colons::Left)
+            .map_err(|_| format!("expected {}, got {:?}", format, s))?;
+        Ok(RunIf {
+            selector: selector.to_string(),
+            prerequisite: prerequisite.to_string(),
+            code: code
+                .parse()
+                .map_err(|_| format!("expected an exit code, got {:?}", code))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_run_if() {
+        assert_eq!(
+            "npm run db:migrate:1=2".parse::<RunIf>().unwrap(),
+            RunIf {
+                selector: "npm run db:migrate".to_string(),
+                prerequisite: "1".to_string(),
+                code: 2,
+            }
+        );
+        assert!("migrate:1".parse::<RunIf>().is_err());
+        assert!("migrate:1=pending".parse::<RunIf>().is_err());
+    }
+}
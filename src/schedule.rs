@@ -0,0 +1,171 @@
+use crate::args::{parse_duration, split_selector, Colons};
+use chrono::{Datelike, NaiveTime, Weekday};
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Window of time in which commands are allowed to run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    hours: Option<(NaiveTime, NaiveTime)>,
+    days: Option<Weekdays>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Weekdays(Vec<Weekday>);
+
+impl Schedule {
+    pub fn new(hours: Option<(NaiveTime, NaiveTime)>, days: Option<Weekdays>) -> Self {
+        Schedule { hours, days }
+    }
+
+    pub fn is_active_now(&self) -> bool {
+        let now = chrono::Local::now();
+        self.is_active(now.weekday(), now.time())
+    }
+
+    pub fn is_active(&self, day: Weekday, time: NaiveTime) -> bool {
+        if let Some(days) = &self.days {
+            if !days.0.contains(&day) {
+                return false;
+            }
+        }
+
+        match self.hours {
+            None => true,
+            Some((start, end)) if start <= end => start <= time && time < end,
+            // Windows like 22:00-06:00 wrap around midnight.
+            Some((start, end)) => start <= time || time < end,
+        }
+    }
+}
+
+/// Runs a command periodically, as `<command>:<duration>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval {
+    pub selector: String,
+    pub period: Duration,
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, period) = split_selector(s, "<command>:<duration>", Colons::Left)?;
+        Ok(Interval {
+            selector: selector.to_string(),
+            period: parse_duration(period).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/// How long after passing a command isn't run again for changes, as `<command>:<duration>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuccessCooldown {
+    pub selector: String,
+    pub period: Duration,
+}
+
+impl FromStr for SuccessCooldown {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Interval { selector, period } = s.parse()?;
+        Ok(SuccessCooldown { selector, period })
+    }
+}
+
+pub fn parse_hours(s: &str) -> Result<(NaiveTime, NaiveTime), Box<dyn Error>> {
+    let mut parts = s.splitn(2, '-');
+    let start = parts.next().unwrap_or("");
+    let end = parts
+        .next()
+        .ok_or_else(|| format!("expected a range like 09:00-18:00, got {:?}", s))?;
+    let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M");
+    Ok((parse(start)?, parse(end)?))
+}
+
+pub fn parse_days(s: &str) -> Result<Weekdays, Box<dyn Error>> {
+    let parse = |d: &str| {
+        d.trim()
+            .parse::<Weekday>()
+            .map_err(|_| format!("unknown day {:?}", d))
+    };
+
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        let mut range = part.splitn(2, '-');
+        let start = parse(range.next().unwrap_or(""))?;
+        let end = match range.next() {
+            Some(end) => parse(end)?,
+            None => start,
+        };
+
+        let mut day = start;
+        days.push(day);
+        while day != end {
+            day = day.succ();
+            days.push(day);
+        }
+    }
+    Ok(Weekdays(days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn default_is_always_active() {
+        assert!(Schedule::default().is_active(Weekday::Sun, time(3, 0)));
+    }
+
+    #[test]
+    fn hours_window() {
+        let schedule = Schedule::new(Some(parse_hours("09:00-18:00").unwrap()), None);
+
+        assert!(!schedule.is_active(Weekday::Mon, time(8, 59)));
+        assert!(schedule.is_active(Weekday::Mon, time(9, 0)));
+        assert!(!schedule.is_active(Weekday::Mon, time(18, 0)));
+    }
+
+    #[test]
+    fn hours_window_wrapping_midnight() {
+        let schedule = Schedule::new(Some(parse_hours("22:00-06:00").unwrap()), None);
+
+        assert!(schedule.is_active(Weekday::Mon, time(23, 0)));
+        assert!(schedule.is_active(Weekday::Mon, time(5, 0)));
+        assert!(!schedule.is_active(Weekday::Mon, time(12, 0)));
+    }
+
+    #[test]
+    fn day_ranges() {
+        assert_eq!(
+            parse_days("mon-wed,sat").unwrap(),
+            Weekdays(vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Sat])
+        );
+    }
+
+    #[test]
+    fn parse_interval() {
+        assert_eq!(
+            "cargo audit:5m".parse::<Interval>().unwrap(),
+            Interval {
+                selector: "cargo audit".to_string(),
+                period: Duration::from_secs(300),
+            }
+        );
+    }
+
+    #[test]
+    fn days_restrict_window() {
+        let schedule = Schedule::new(None, Some(parse_days("mon-fri").unwrap()));
+
+        assert!(schedule.is_active(Weekday::Fri, time(12, 0)));
+        assert!(!schedule.is_active(Weekday::Sat, time(12, 0)));
+    }
+}
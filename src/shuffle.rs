@@ -0,0 +1,69 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shuffles the order commands run in, repeatably for the same seed.
+#[derive(Debug, Clone)]
+pub struct Shuffle {
+    seed: u64,
+    state: u64,
+}
+
+impl Shuffle {
+    pub fn new(seed: u64) -> Self {
+        Shuffle { seed, state: seed }
+    }
+
+    /// Seeded from the current time.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Shuffle::new(nanos)
+    }
+
+    /// The seed that reproduces this shuffle's orders.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    // splitmix64, which is plenty random for picking an order.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_orders() {
+        let (mut a, mut b) = (Shuffle::new(7), Shuffle::new(7));
+        for _ in 0..3 {
+            let (mut x, mut y) = ((0..10).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+            a.shuffle(&mut x);
+            b.shuffle(&mut y);
+            assert_eq!(x, y);
+        }
+    }
+
+    #[test]
+    fn shuffles_into_a_permutation() {
+        let mut items = (0..10).collect::<Vec<_>>();
+        Shuffle::new(0).shuffle(&mut items);
+
+        assert_ne!(items, (0..10).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..10).collect::<Vec<_>>());
+    }
+}
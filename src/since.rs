@@ -0,0 +1,147 @@
+use crate::args::parse_duration;
+use crate::watch::WatchRoots;
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What --since finds the changed files from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Since {
+    /// Files modified after a time.
+    Time(SystemTime),
+    /// Files that differ from a git ref, and untracked files.
+    Ref(String),
+}
+
+impl Since {
+    /// The absolute paths of the files that changed.
+    pub fn changed(&self, roots: &WatchRoots) -> Result<HashSet<PathBuf>, Box<dyn Error>> {
+        match self {
+            Since::Time(time) => Ok(roots.modified_since(*time)?.into_iter().collect()),
+            Since::Ref(reference) => {
+                let top = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+                let diffed = git(&["diff", "--name-only", reference])?;
+                let untracked =
+                    git(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+                Ok(diffed
+                    .lines()
+                    .chain(untracked.lines())
+                    .map(|p| top.join(p))
+                    .collect())
+            }
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+impl FromStr for Since {
+    type Err = String;
+
+    /// A duration ago, like `2h`, a local date or time, like `2024-05-01 09:30`, or otherwise a
+    /// git ref.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ago) = parse_duration(s) {
+            return Ok(Since::Time(SystemTime::now() - ago));
+        }
+        let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+            });
+        match time {
+            Some(time) => Local
+                .from_local_datetime(&time)
+                .earliest()
+                .map(|t| Since::Time(t.into()))
+                .ok_or_else(|| format!("{:?} isn't a local time", s)),
+            None => Ok(Since::Ref(s.to_string())),
+        }
+    }
+}
+
+/// The files changes are limited to by --since, until a full run lifts the limit.
+#[derive(Clone, Default)]
+pub struct SinceScope(Rc<RefCell<Option<HashSet<PathBuf>>>>);
+
+impl SinceScope {
+    pub fn new(files: HashSet<PathBuf>) -> Self {
+        SinceScope(Rc::new(RefCell::new(Some(files))))
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        self.0.borrow().as_ref().is_none_or(|f| f.contains(path))
+    }
+
+    pub fn lift(&self) {
+        self.0.borrow_mut().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_times_and_refs() {
+        let hour_ago = match "1h".parse() {
+            Ok(Since::Time(time)) => time,
+            other => panic!("{:?}", other),
+        };
+        let ago = SystemTime::now().duration_since(hour_ago).unwrap();
+        assert!(ago >= Duration::from_secs(3600) && ago < Duration::from_secs(3660));
+
+        assert!(matches!("2024-05-01".parse(), Ok(Since::Time(_))));
+        assert!(matches!("2024-05-01 09:30".parse(), Ok(Since::Time(_))));
+        assert_eq!("main".parse(), Ok(Since::Ref("main".to_string())));
+        assert_eq!("HEAD~3".parse(), Ok(Since::Ref("HEAD~3".to_string())));
+    }
+
+    #[test]
+    fn scope_allows_its_files_until_lifted() {
+        let scope = SinceScope::new(vec![PathBuf::from("/src/lib.rs")].into_iter().collect());
+
+        assert!(scope.allows(Path::new("/src/lib.rs")));
+        assert!(!scope.allows(Path::new("/src/main.rs")));
+        scope.clone().lift();
+        assert!(scope.allows(Path::new("/src/main.rs")));
+    }
+
+    #[test]
+    fn finds_files_modified_since() {
+        let tmp = TestDir::new();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("old.rs"), "").unwrap();
+        let before = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(dir.join("new.rs"), "").unwrap();
+        let roots = WatchRoots::new(std::slice::from_ref(&dir), &[]).unwrap();
+
+        let changed = Since::Time(before).changed(&roots).unwrap();
+
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(changed, vec![dir.join("new.rs")].into_iter().collect());
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One block per duration, from the lowest for the shortest to the highest for the longest.
+pub fn sparkline(durations: &[Duration]) -> String {
+    let min = durations.iter().min().copied().unwrap_or_default();
+    let max = durations.iter().max().copied().unwrap_or_default();
+    let range = (max - min).as_secs_f64();
+    durations
+        .iter()
+        .map(|d| {
+            if range == 0. {
+                return BLOCKS[0];
+            }
+            let level = (*d - min).as_secs_f64() / range * (BLOCKS.len() - 1) as f64;
+            BLOCKS[level.round() as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_between_shortest_and_longest() {
+        let durations = [1, 9, 3, 15].map(Duration::from_secs);
+
+        assert_eq!(sparkline(&durations), "▁▅▂█");
+    }
+
+    #[test]
+    fn equal_durations_are_flat() {
+        let durations = [3, 3].map(Duration::from_secs);
+
+        assert_eq!(sparkline(&durations), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::executor::CommandOutput;
+use std::str::FromStr;
+
+/// Overrides the outcome of commands exiting with a code, as `<code>:<pass|warn|fail>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeStatus {
+    pub code: i32,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CodeStatus {
+    pub fn apply(&self, output: &mut CommandOutput) {
+        if output.code != Some(self.code) {
+            return;
+        }
+
+        output.success = self.status != Status::Fail;
+        output.warning = self.status == Status::Warn;
+    }
+}
+
+impl FromStr for CodeStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let code = parts
+            .next()
+            .unwrap_or("")
+            .parse()
+            .map_err(|_| format!("expected <code>:<status>, got {:?}", s))?;
+        let status = match parts.next() {
+            Some("pass") => Status::Pass,
+            Some("warn") => Status::Warn,
+            Some("fail") => Status::Fail,
+            _ => return Err(format!("expected pass, warn or fail in {:?}", s)),
+        };
+        Ok(CodeStatus { code, status })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exited(code: i32) -> CommandOutput {
+        CommandOutput {
+            success: code == 0,
+            code: Some(code),
+            ..CommandOutput::default()
+        }
+    }
+
+    #[test]
+    fn warn_passes_with_warning() {
+        let mut output = exited(2);
+
+        "2:warn".parse::<CodeStatus>().unwrap().apply(&mut output);
+
+        assert!(output.success);
+        assert!(output.warning);
+    }
+
+    #[test]
+    fn other_codes_are_unchanged() {
+        let mut output = exited(1);
+
+        "2:pass".parse::<CodeStatus>().unwrap().apply(&mut output);
+
+        assert_eq!(output, exited(1));
+    }
+
+    #[test]
+    fn zero_can_fail() {
+        let mut output = exited(0);
+
+        "0:fail".parse::<CodeStatus>().unwrap().apply(&mut output);
+
+        assert!(!output.success);
+    }
+
+    #[test]
+    fn unknown_status_is_error() {
+        assert!("2:maybe".parse::<CodeStatus>().is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt::Display;
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
+
+/// Where a category of output is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn print(self, s: impl Display) {
+        match self {
+            Stream::Stdout => print!("{}", s),
+            Stream::Stderr => eprint!("{}", s),
+        }
+    }
+
+    pub fn println(self, s: impl Display) {
+        match self {
+            Stream::Stdout => println!("{}", s),
+            Stream::Stderr => eprintln!("{}", s),
+        }
+    }
+
+    pub fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stdout => std::io::stdout().is_terminal(),
+            Stream::Stderr => std::io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn flush(self) {
+        // Nothing useful can be done if the terminal went away.
+        let _ = match self {
+            Stream::Stdout => std::io::stdout().flush(),
+            Stream::Stderr => std::io::stderr().flush(),
+        };
+    }
+}
+
+impl FromStr for Stream {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Stream::Stdout),
+            "stderr" => Ok(Stream::Stderr),
+            other => Err(format!("expected stdout or stderr, got {:?}", other)),
+        }
+    }
+}
+
+/// Where command output is written. `Split` keeps each command stream on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputRouting {
+    Split,
+    All(Stream),
+}
+
+impl OutputRouting {
+    pub fn out(self) -> Stream {
+        match self {
+            OutputRouting::Split => Stream::Stdout,
+            OutputRouting::All(s) => s,
+        }
+    }
+
+    pub fn err(self) -> Stream {
+        match self {
+            OutputRouting::Split => Stream::Stderr,
+            OutputRouting::All(s) => s,
+        }
+    }
+}
+
+impl FromStr for OutputRouting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "split" => Ok(OutputRouting::Split),
+            other => other
+                .parse()
+                .map(OutputRouting::All)
+                .map_err(|_| format!("expected split, stdout or stderr, got {:?}", other)),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::args::split_named;
+use std::ffi::OsString;
+use std::str::FromStr;
+
+/// A command that only runs when asked for by name, as `<name>:<command>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub name: String,
+    pub command: OsString,
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, command) = split_named(s, "task")?;
+        if name == "preset" {
+            return Err("a task can't be named preset, :preset switches presets".to_string());
+        }
+        Ok(Task {
+            name: name.to_string(),
+            command: command.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_and_command() {
+        assert_eq!(
+            "migrate:diesel migration run --database-url postgres://localhost".parse(),
+            Ok(Task {
+                name: "migrate".to_string(),
+                command: "diesel migration run --database-url postgres://localhost".into(),
+            })
+        );
+        assert!("migrate".parse::<Task>().is_err());
+        assert!("seed db:./seed".parse::<Task>().is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::stream::Stream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Escape sequences undoing what active guards did to the terminal, in the order they were made.
+static ACTIVE: Mutex<Vec<(usize, Stream, String)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Undoes changes to the terminal when dropped, or from a panic once `restore_on_panic` is
+/// installed.
+pub struct TerminalGuard {
+    id: usize,
+}
+
+impl TerminalGuard {
+    /// `restore` is written to `stream` to undo the change.
+    pub fn new(stream: Stream, restore: impl Into<String>) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        active().push((id, stream, restore.into()));
+        TerminalGuard { id }
+    }
+
+    pub fn set_restore(&self, restore: impl Into<String>) {
+        if let Some(guard) = active().iter_mut().find(|g| g.0 == self.id) {
+            guard.2 = restore.into();
+        }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let guard = {
+            let mut active = active();
+            let position = active.iter().position(|g| g.0 == self.id);
+            position.map(|p| active.remove(p))
+        };
+        if let Some((_, stream, restore)) = guard {
+            stream.print(restore);
+            stream.flush();
+        }
+    }
+}
+
+fn active() -> std::sync::MutexGuard<'static, Vec<(usize, Stream, String)>> {
+    // A panic while holding the lock doesn't leave the list inconsistent.
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Restores the terminal before a panic's message is printed, so it's readable and the shell is
+/// usable afterwards.
+pub fn restore_on_panic() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let guards = std::mem::take(&mut *active());
+        for (_, stream, restore) in guards.into_iter().rev() {
+            stream.print(restore);
+            stream.flush();
+        }
+        hook(info)
+    }));
+}
+
+/// Keeps the status bar on the last line of the terminal, with a fixed region of lines above
+/// it, by scrolling everything else in the lines above those. The terminal is restored when this
+/// is dropped.
+pub struct PinnedStatus {
+    stream: Stream,
+    height: Option<usize>,
+    // Lines kept above the status bar.
+    region: usize,
+    guard: Option<TerminalGuard>,
+}
+
+impl PinnedStatus {
+    pub fn new(stream: Stream) -> Self {
+        PinnedStatus {
+            stream,
+            height: None,
+            region: 0,
+            guard: None,
+        }
+    }
+
+    /// Draws the status bar, and `region` in the lines above it.
+    pub fn draw(&mut self, status: &str, region: &[String]) {
+        let height = term_size::dimensions().map(|d| d.1).unwrap_or(24).max(2);
+        // Leave at least one line for output.
+        let region = &region[..region.len().min(height - 2)];
+        if self.height != Some(height) || self.region != region.len() {
+            // Clear what an earlier region left behind.
+            let first_row = height - self.region.max(region.len());
+            for row in first_row..height {
+                self.stream.print(format!("\x1b[{};1H\x1b[2K", row));
+            }
+            // Limit scrolling to the lines above the region and move there for further output.
+            let bottom = height - 1 - region.len();
+            self.stream
+                .print(format!("\x1b[1;{}r\x1b[{};1H", bottom, bottom));
+            self.height = Some(height);
+            self.region = region.len();
+
+            let restore = format!("\x1b[0m\x1b[r\x1b[{};1H\n", height);
+            match &self.guard {
+                Some(guard) => guard.set_restore(restore),
+                None => self.guard = Some(TerminalGuard::new(self.stream, restore)),
+            }
+        }
+
+        // Save the cursor, draw on the last lines, then return to the output.
+        let mut frame = "\x1b7".to_string();
+        let first_row = height - region.len();
+        for (row, line) in (first_row..).zip(region) {
+            frame.push_str(&format!("\x1b[{};1H\x1b[2K{}", row, line));
+        }
+        frame.push_str(&format!("\x1b[{};1H\x1b[2K{}\x1b8", height, status));
+        self.stream.print(frame);
+        self.stream.flush();
+    }
+}
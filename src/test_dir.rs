@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory only the calling test uses, removed again when dropped. Tests run in
+/// parallel, and other checkouts may be running them at the same time, so fixed names in the
+/// temp dir would collide.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new() -> TestDir {
+        let path = std::env::temp_dir().join(format!(
+            "watchdo-test-{}-{}",
+            std::process::id(),
+            CREATED.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_dir_is_fresh_and_removed() {
+        let (first, second) = (TestDir::new(), TestDir::new());
+        let path = first.path().to_path_buf();
+
+        assert_ne!(first.path(), second.path());
+        assert!(path.is_dir());
+        drop(first);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::badge::Aggregate;
+use colored::{Color, ColoredString, Colorize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// How a status bar cell looks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub glyph: String,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Cell {
+    fn new(glyph: &str, fg: Option<Color>, bg: Option<Color>) -> Self {
+        Cell {
+            glyph: glyph.to_string(),
+            fg,
+            bg,
+        }
+    }
+
+    pub fn paint(&self) -> ColoredString {
+        self.paint_glyph(&self.glyph)
+    }
+
+    /// Paints other text in the cell's colors.
+    pub fn paint_glyph(&self, glyph: &str) -> ColoredString {
+        let mut painted = glyph.normal();
+        if let Some(fg) = self.fg {
+            painted = painted.color(fg);
+        }
+        if let Some(bg) = self.bg {
+            painted = painted.on_color(bg);
+        }
+        painted
+    }
+
+    /// Parses `<glyph> [<color>] [on <color>]`, like `x white on red`. A glyph of a space is
+    /// written `" "`.
+    fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (glyph, colors) = match spec.strip_prefix('"') {
+            Some(quoted) => quoted
+                .split_once('"')
+                .ok_or_else(|| format!("unclosed quote in {:?}", spec))?,
+            None => spec.split_once(' ').unwrap_or((spec, "")),
+        };
+        if glyph.is_empty() {
+            return Err(format!("expected a glyph, got {:?}", spec));
+        }
+
+        let words = colors.split_whitespace().collect::<Vec<_>>();
+        let (fg, bg) = match words.iter().position(|w| *w == "on") {
+            Some(on) => (&words[..on], &words[on + 1..]),
+            None => (&words[..], &[][..]),
+        };
+        let color = |words: &[&str]| -> Result<Option<Color>, String> {
+            if words.is_empty() {
+                return Ok(None);
+            }
+            let name = words.join(" ");
+            name.parse()
+                .map(Some)
+                .map_err(|_| format!("unknown color {:?}", name))
+        };
+        Ok(Cell {
+            glyph: glyph.to_string(),
+            fg: color(fg)?,
+            bg: color(bg)?,
+        })
+    }
+}
+
+/// The glyphs and colors of every cell in the status bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Hasn't run, since watchdo started idle and nothing has changed yet.
+    pub idle: Cell,
+    pub requested: Cell,
+    /// Requested, but waiting for earlier commands before it can start.
+    pub waiting: Cell,
+    pub running: Cell,
+    pub passed: Cell,
+    pub warning: Cell,
+    pub failed: Cell,
+    /// Failed because the command's program wasn't found.
+    pub not_found: Cell,
+    /// Passed when it was expected to fail.
+    pub unexpected_pass: Cell,
+    pub expected_failure: Cell,
+    pub retried: Cell,
+    pub terminated_passed: Cell,
+    pub terminated_failed: Cell,
+    /// Fills the status bar before a command's first runs.
+    pub padding: Cell,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        use Color::*;
+
+        Theme {
+            idle: Cell::new("-", None, None),
+            requested: Cell::new(".", None, None),
+            waiting: Cell::new("…", None, None),
+            running: Cell::new("?", Some(Black), Some(Yellow)),
+            passed: Cell::new("✓", Some(White), Some(Green)),
+            warning: Cell::new("!", Some(Black), Some(Yellow)),
+            failed: Cell::new("x", Some(White), Some(Red)),
+            not_found: Cell::new("?", Some(White), Some(Red)),
+            unexpected_pass: Cell::new("✓", Some(Black), Some(Magenta)),
+            expected_failure: Cell::new("x", Some(White), Some(Blue)),
+            retried: Cell::new("r", Some(Black), Some(Cyan)),
+            terminated_passed: Cell::new("✓", Some(Black), Some(White)),
+            terminated_failed: Cell::new("x", Some(Black), Some(White)),
+            padding: Cell::new(" ", None, Some(White)),
+        }
+    }
+}
+
+impl Theme {
+    /// Avoids telling states apart by red and green alone.
+    pub fn colorblind() -> Self {
+        use Color::*;
+
+        Theme {
+            passed: Cell::new("✓", Some(White), Some(Blue)),
+            failed: Cell::new("x", Some(Black), Some(BrightYellow)),
+            not_found: Cell::new("?", Some(Black), Some(BrightYellow)),
+            warning: Cell::new("!", Some(Black), Some(Cyan)),
+            running: Cell::new("?", Some(Black), Some(White)),
+            unexpected_pass: Cell::new("+", Some(Black), Some(Magenta)),
+            expected_failure: Cell::new("-", Some(White), Some(Black)),
+            retried: Cell::new("r", Some(Black), Some(BrightCyan)),
+            ..Theme::default()
+        }
+    }
+
+    /// Tells states apart by glyph only, for terminals without colors.
+    pub fn monochrome() -> Self {
+        Theme {
+            idle: Cell::new("-", None, None),
+            requested: Cell::new(".", None, None),
+            waiting: Cell::new("…", None, None),
+            running: Cell::new("?", None, None),
+            passed: Cell::new("✓", None, None),
+            warning: Cell::new("!", None, None),
+            failed: Cell::new("x", None, None),
+            not_found: Cell::new("¿", None, None),
+            unexpected_pass: Cell::new("+", None, None),
+            expected_failure: Cell::new("-", None, None),
+            retried: Cell::new("r", None, None),
+            terminated_passed: Cell::new("t", None, None),
+            terminated_failed: Cell::new("T", None, None),
+            padding: Cell::new(" ", None, None),
+        }
+    }
+
+    /// Finds a theme by name, in the theme file if there is one and then in the built-in
+    /// default, colorblind and monochrome themes.
+    pub fn load(name: &str, file: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        if let Some(path) = file {
+            let mut themes = parse_themes(&std::fs::read_to_string(path)?)?;
+            if let Some(theme) = themes.remove(name) {
+                return Ok(theme);
+            }
+        }
+
+        match name {
+            "default" => Ok(Theme::default()),
+            "colorblind" => Ok(Theme::colorblind()),
+            "monochrome" => Ok(Theme::monochrome()),
+            other => Err(format!("unknown theme {:?}", other).into()),
+        }
+    }
+
+    /// Uses `glyph` for every passing cell.
+    /// The theme with the padding in the background color of the overall health's cell, so the
+    /// whole status bar shows it. Themes without that color keep their padding.
+    pub fn tinted(&self, health: Aggregate) -> Theme {
+        let cell = match health {
+            Aggregate::Passing => &self.passed,
+            Aggregate::Failing => &self.failed,
+            Aggregate::Running => &self.running,
+        };
+        let mut tinted = self.clone();
+        tinted.padding.bg = cell.bg.or(self.padding.bg);
+        tinted
+    }
+
+    pub fn set_ok_glyph(&mut self, glyph: &str) {
+        for cell in [
+            &mut self.passed,
+            &mut self.unexpected_pass,
+            &mut self.terminated_passed,
+        ] {
+            cell.glyph = glyph.to_string();
+        }
+    }
+
+    fn cell_mut(&mut self, state: &str) -> Option<&mut Cell> {
+        Some(match state {
+            "idle" => &mut self.idle,
+            "requested" => &mut self.requested,
+            "waiting" => &mut self.waiting,
+            "running" => &mut self.running,
+            "passed" => &mut self.passed,
+            "warning" => &mut self.warning,
+            "failed" => &mut self.failed,
+            "not-found" => &mut self.not_found,
+            "unexpected-pass" => &mut self.unexpected_pass,
+            "expected-failure" => &mut self.expected_failure,
+            "retried" => &mut self.retried,
+            "terminated-passed" => &mut self.terminated_passed,
+            "terminated-failed" => &mut self.terminated_failed,
+            "padding" => &mut self.padding,
+            _ => return None,
+        })
+    }
+}
+
+/// Parses a theme file. Each theme starts with its `[name]`, followed by `<state> = <cell>`
+/// lines, like `failed = x white on red`. States it leaves out look like the default theme.
+/// Lines starting with `#` are comments.
+pub fn parse_themes(contents: &str) -> Result<HashMap<String, Theme>, String> {
+    let mut themes = HashMap::new();
+    let mut current = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim().to_string();
+            themes.insert(name.clone(), Theme::default());
+            current = Some(name);
+            continue;
+        }
+
+        let theme = current
+            .as_ref()
+            .and_then(|name| themes.get_mut(name))
+            .ok_or_else(|| format!("expected a [theme] before {:?}", line))?;
+        let (state, spec) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected '<state> = <cell>', got {:?}", line))?;
+        let cell = theme
+            .cell_mut(state.trim())
+            .ok_or_else(|| format!("unknown state {:?}", state.trim()))?;
+        *cell = Cell::parse(spec)?;
+    }
+    Ok(themes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tints_padding_with_health() {
+        let theme = Theme::default();
+        assert_eq!(
+            theme.tinted(Aggregate::Failing).padding.bg,
+            Some(Color::Red)
+        );
+        assert_eq!(
+            Theme::colorblind().tinted(Aggregate::Passing).padding.bg,
+            Some(Color::Blue)
+        );
+        assert_eq!(
+            Theme::monochrome().tinted(Aggregate::Running),
+            Theme::monochrome()
+        );
+    }
+
+    #[test]
+    fn parses_cells() {
+        assert_eq!(
+            Cell::parse("x white on red"),
+            Ok(Cell::new("x", Some(Color::White), Some(Color::Red)))
+        );
+        assert_eq!(
+            Cell::parse("? on bright yellow"),
+            Ok(Cell::new("?", None, Some(Color::BrightYellow)))
+        );
+        assert_eq!(
+            Cell::parse("\" \" on white"),
+            Ok(Cell::new(" ", None, Some(Color::White)))
+        );
+        assert_eq!(Cell::parse("!"), Ok(Cell::new("!", None, None)));
+    }
+
+    #[test]
+    fn unknown_color_is_error() {
+        assert!(Cell::parse("x mauve").is_err());
+    }
+
+    #[test]
+    fn themes_default_missing_states() {
+        let themes = parse_themes("# mine\n[loud]\nfailed = X bright red\n").unwrap();
+
+        assert_eq!(
+            themes["loud"],
+            Theme {
+                failed: Cell::new("X", Some(Color::BrightRed), None),
+                ..Theme::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_state_is_error() {
+        assert!(parse_themes("[loud]\nexploded = !\n").is_err());
+        assert!(parse_themes("failed = x\n").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::args::{split_selector, Colons};
+use crate::watch::WatchRoots;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A file whose changes only count when its content matches a pattern.
+#[derive(Debug)]
+pub struct ContentTrigger {
+    path: PathBuf,
+    pattern: Regex,
+}
+
+impl ContentTrigger {
+    /// Whether a change to `changed` should trigger a run. Both paths must be absolute.
+    pub fn allows(&self, changed: &Path) -> bool {
+        if changed != self.path {
+            return true;
+        }
+
+        match std::fs::read_to_string(changed) {
+            Ok(content) => self.pattern.is_match(&content),
+            Err(_) => false,
+        }
+    }
+
+    /// Resolves the path the way the watcher reports it, absolute and through no symlinks. A
+    /// file that doesn't exist yet is resolved through its directory.
+    pub fn canonicalize(self) -> Self {
+        let path = WatchRoots::absolute(&self.path);
+        let resolved = path.canonicalize().ok().or_else(|| {
+            let (dir, name) = path.parent().zip(path.file_name())?;
+            dir.canonicalize().ok().map(|d| d.join(name))
+        });
+        ContentTrigger {
+            path: resolved.unwrap_or(path),
+            ..self
+        }
+    }
+}
+
+impl FromStr for ContentTrigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, pattern) = split_selector(s, "<path>:<regex>", Colons::Right)?;
+        Ok(ContentTrigger {
+            path: PathBuf::from(path),
+            pattern: Regex::new(pattern).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn trigger(path: &Path) -> ContentTrigger {
+        format!("{}:^ready$", path.display()).parse().unwrap()
+    }
+
+    #[test]
+    fn other_paths_always_trigger() {
+        let trigger = trigger(Path::new("/nonexistent/flag"));
+
+        assert!(trigger.allows(Path::new("/nonexistent/other")));
+        assert!(!trigger.allows(Path::new("/nonexistent/flag")));
+    }
+
+    #[test]
+    fn path_triggers_when_content_matches() {
+        let dir = TestDir::new();
+        let path = dir.join("flag");
+        let trigger = trigger(&path);
+
+        std::fs::write(&path, "ready").unwrap();
+        let ready = trigger.allows(&path);
+        std::fs::write(&path, "building").unwrap();
+        let building = trigger.allows(&path);
+
+        assert!(ready);
+        assert!(!building);
+    }
+
+    #[test]
+    fn canonicalizes_through_the_directory() {
+        let dir = TestDir::new();
+        let trigger = trigger(&dir.join(".").join("missing-flag"));
+
+        assert_eq!(
+            trigger.canonicalize().path,
+            dir.path().canonicalize().unwrap().join("missing-flag")
+        );
+    }
+}
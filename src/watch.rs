@@ -0,0 +1,319 @@
+use crate::stream::Stream;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{RecursiveMode, Watcher};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The watched directories, each with ignore patterns anchored at its own root like a
+/// `.gitignore` in that directory.
+pub struct WatchRoots {
+    roots: Vec<Root>,
+    // Real paths of watched symlinks, with the link they were reached through.
+    links: Vec<(PathBuf, PathBuf)>,
+    // How many files, not directories, are watched.
+    watched_files: usize,
+}
+
+struct Root {
+    dir: PathBuf,
+    ignore: Gitignore,
+}
+
+impl WatchRoots {
+    pub fn new(dirs: &[PathBuf], ignores: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut roots = Vec::new();
+        for dir in dirs {
+            let dir = dir
+                .canonicalize()
+                .map_err(|e| format!("can't watch {}: {}", dir.display(), e))?;
+            let mut builder = GitignoreBuilder::new(&dir);
+            for pattern in ignores {
+                builder.add_line(None, pattern)?;
+            }
+            roots.push(Root {
+                ignore: builder.build()?,
+                dir,
+            });
+        }
+        Ok(WatchRoots {
+            roots,
+            links: Vec::new(),
+            watched_files: 0,
+        })
+    }
+
+    /// Watches every file that isn't ignored. With `symlink_targets`, the real paths of
+    /// symlinks are watched too, so changes made through them are seen. Symlinks to nothing
+    /// can't be watched, and are skipped with a warning on `stream`.
+    pub fn watch(
+        &mut self,
+        watcher: &mut impl Watcher,
+        symlink_targets: bool,
+        stream: Stream,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut links = Vec::new();
+        let mut files = 0;
+        for root in &self.roots {
+            for result in root.walk() {
+                let entry = match result {
+                    Err(e) if is_dangling(&e) => {
+                        stream
+                            .println(format!("watchdo: not watching a symlink to nothing: {}", e));
+                        continue;
+                    }
+                    result => result?,
+                };
+                let target = match entry.path_is_symlink() {
+                    true => match entry.path().canonicalize() {
+                        Ok(target) => Some(target),
+                        Err(e) => {
+                            stream.println(format!(
+                                "watchdo: not watching {}: {}",
+                                entry.path().display(),
+                                e
+                            ));
+                            continue;
+                        }
+                    },
+                    false => None,
+                };
+                watcher
+                    .watch(entry.path(), RecursiveMode::NonRecursive)
+                    .map_err(|e| format!("can't watch {}: {}", entry.path().display(), e))?;
+                if !entry.file_type().is_some_and(|t| t.is_dir()) {
+                    files += 1;
+                }
+
+                if let (true, Some(target)) = (symlink_targets, target) {
+                    watcher.watch(&target, RecursiveMode::NonRecursive)?;
+                    links.push((target, Self::absolute(entry.path())));
+                }
+            }
+        }
+        self.links = links;
+        self.watched_files = files;
+        Ok(())
+    }
+
+    pub fn watched_files(&self) -> usize {
+        self.watched_files
+    }
+
+    /// The files that aren't ignored and were modified after `time`.
+    pub fn modified_since(&self, time: SystemTime) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut modified = Vec::new();
+        for root in &self.roots {
+            for result in root.walk() {
+                let entry = match result {
+                    Err(e) if is_dangling(&e) => continue,
+                    result => result?,
+                };
+                let metadata = entry.metadata()?;
+                if metadata.is_file() && metadata.modified()? > time {
+                    modified.push(entry.into_path());
+                }
+            }
+        }
+        Ok(modified)
+    }
+
+    /// Maps a path under a watched symlink's real path back to the path through the link.
+    pub fn through_links(&self, path: PathBuf) -> PathBuf {
+        self.links
+            .iter()
+            .find_map(|(target, link)| path.strip_prefix(target).ok().map(|r| link.join(r)))
+            .unwrap_or(path)
+    }
+
+    /// Makes `path` absolute, resolving relative paths against the current directory.
+    pub fn absolute(path: &Path) -> PathBuf {
+        std::env::current_dir()
+            .map(|d| d.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// `path` relative to the watched directory containing it.
+    pub fn relative(&self, path: &Path) -> PathBuf {
+        let absolute = Self::absolute(path);
+        self.roots
+            .iter()
+            .find_map(|r| absolute.strip_prefix(&r.dir).ok())
+            .map(Path::to_path_buf)
+            .unwrap_or(absolute)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let absolute = Self::absolute(path);
+        self.roots
+            .iter()
+            .filter(|r| absolute.starts_with(&r.dir))
+            .any(|r| is_ignored(&r.ignore, &r.dir, &absolute))
+    }
+}
+
+/// Tries `attempt` until it succeeds, up to `retries` more times, waiting `delay` before the
+/// first retry and twice as long before each one after. `on_retry` hears about each failure
+/// that's retried, with how long until the next try.
+pub fn with_retries<T>(
+    retries: usize,
+    delay: Duration,
+    mut attempt: impl FnMut() -> Result<T, Box<dyn Error>>,
+    mut on_retry: impl FnMut(&dyn Error, Duration),
+) -> Result<T, Box<dyn Error>> {
+    let mut delay = delay;
+    for _ in 0..retries {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) => on_retry(&*e, delay),
+        }
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+    attempt().map_err(|e| match retries {
+        0 => e,
+        1 => format!("{} (gave up after 1 retry)", e).into(),
+        _ => format!("{} (gave up after {} retries)", e, retries).into(),
+    })
+}
+
+impl Root {
+    fn walk(&self) -> ignore::Walk {
+        let ignore = self.ignore.clone();
+        let dir = self.dir.clone();
+        ignore::WalkBuilder::new(&self.dir)
+            .follow_links(true)
+            .filter_entry(move |e| !is_ignored(&ignore, &dir, e.path()))
+            .build()
+    }
+}
+
+/// Whether walking failed at a symlink to nothing.
+fn is_dangling(e: &ignore::Error) -> bool {
+    match e {
+        ignore::Error::WithPath { err, .. } | ignore::Error::WithDepth { err, .. } => {
+            is_dangling(err)
+        }
+        ignore::Error::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+fn is_ignored(ignore: &Gitignore, root: &Path, path: &Path) -> bool {
+    // The matcher panics for paths outside its root, which happens for the root itself when it
+    // was given relative to the current directory.
+    if !path.starts_with(root) || path == root {
+        return false;
+    }
+    ignore
+        .matched_path_or_any_parents(path, path.is_dir())
+        .is_ignore()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn retries_until_it_succeeds() {
+        let mut attempts = 0;
+        let mut delays = Vec::new();
+        let result = with_retries(
+            3,
+            Duration::from_millis(1),
+            || {
+                attempts += 1;
+                match attempts {
+                    1 | 2 => Err("not mounted".into()),
+                    _ => Ok(attempts),
+                }
+            },
+            |_, delay| delays.push(delay),
+        );
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(delays, [Duration::from_millis(1), Duration::from_millis(2)]);
+    }
+
+    #[test]
+    fn gives_up_after_the_retries() {
+        let result = with_retries(
+            2,
+            Duration::ZERO,
+            || Err::<(), _>("not mounted".into()),
+            |_, _| {},
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "not mounted (gave up after 2 retries)"
+        );
+    }
+
+    fn roots(dirs: &[&Path], ignores: &[&str]) -> WatchRoots {
+        let dirs = dirs.iter().map(|d| d.to_path_buf()).collect::<Vec<_>>();
+        let ignores = ignores.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        WatchRoots::new(&dirs, &ignores).unwrap()
+    }
+
+    #[test]
+    fn patterns_are_anchored_at_each_root() {
+        let tmp = TestDir::new();
+        let (a, b) = (tmp.join("a"), tmp.join("b"));
+        std::fs::create_dir_all(a.join("target")).unwrap();
+        std::fs::create_dir_all(b.join("target")).unwrap();
+        let (a, b) = (a.canonicalize().unwrap(), b.canonicalize().unwrap());
+        let roots = roots(&[&a, &b], &["/target/"]);
+
+        assert!(roots.is_ignored(&a.join("target").join("out.rlib")));
+        assert!(roots.is_ignored(&b.join("target").join("out.rlib")));
+        assert!(!roots.is_ignored(&a.join("src.rs")));
+    }
+
+    #[test]
+    fn relative_to_containing_root() {
+        let tmp = TestDir::new();
+        let (a, b) = (tmp.join("a"), tmp.join("b"));
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let roots = roots(&[&a, &b], &[]);
+
+        assert_eq!(
+            roots.relative(&b.canonicalize().unwrap().join("src/main.rs")),
+            PathBuf::from("src/main.rs")
+        );
+    }
+
+    #[test]
+    fn maps_link_targets_back_through_the_link() {
+        let mut roots = roots(&[], &[]);
+        roots.links = vec![(PathBuf::from("/real/lib"), PathBuf::from("/project/lib"))];
+
+        assert_eq!(
+            roots.through_links(PathBuf::from("/real/lib/src/lib.rs")),
+            PathBuf::from("/project/lib/src/lib.rs")
+        );
+        assert_eq!(
+            roots.through_links(PathBuf::from("/real/other.rs")),
+            PathBuf::from("/real/other.rs")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn skips_dangling_symlinks() {
+        let tmp = TestDir::new();
+        std::fs::write(tmp.join("target.rs"), "").unwrap();
+        std::os::unix::fs::symlink(tmp.join("missing"), tmp.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(tmp.join("target.rs"), tmp.join("link.rs")).unwrap();
+        let mut roots = roots(&[tmp.path()], &[]);
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(tx, Duration::from_secs(1)).unwrap();
+
+        roots.watch(&mut watcher, true, Stream::Stderr).unwrap();
+
+        assert_eq!(roots.watched_files(), 2);
+        assert_eq!(roots.links.len(), 1);
+    }
+}